@@ -0,0 +1,210 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use hyper::header;
+
+use crate::api::handlers::bookmarks::BookmarkResponse;
+
+const TEXT_CSV: &str = "text/csv";
+const APPLICATION_NDJSON: &str = "application/x-ndjson";
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Format {
+    Json,
+    Html,
+    Csv,
+    NdJson,
+}
+
+impl Format {
+    /// Picks the first supported media type in the Accept header, falling back to json.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+        else {
+            return Format::Json;
+        };
+
+        for media_type in accept.split(',') {
+            let media_type = media_type.split(';').next().unwrap_or_default().trim();
+            match media_type {
+                "application/json" | "*/*" => return Format::Json,
+                "text/html" => return Format::Html,
+                TEXT_CSV => return Format::Csv,
+                APPLICATION_NDJSON => return Format::NdJson,
+                _ => {}
+            }
+        }
+
+        Format::Json
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_csv(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// A column of the CSV, named as the field of the bookmark
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub(crate) enum CsvColumn {
+    Id,
+    Url,
+    Title,
+    Description,
+    Notes,
+    Unread,
+    Shared,
+    IsArchived,
+    #[value(alias = "tags")]
+    TagNames,
+    Uuid,
+    DateAdded,
+    DateModified,
+}
+
+impl CsvColumn {
+    fn name(self) -> String {
+        clap::ValueEnum::to_possible_value(&self)
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default()
+    }
+
+    fn field(self, bookmark: &BookmarkResponse) -> String {
+        match self {
+            CsvColumn::Id => bookmark.id.to_string(),
+            CsvColumn::Url => bookmark.url.clone(),
+            CsvColumn::Title => bookmark.title.clone(),
+            CsvColumn::Description => bookmark.description.clone().unwrap_or_default(),
+            CsvColumn::Notes => bookmark.notes.clone().unwrap_or_default(),
+            CsvColumn::Unread => bookmark.unread.to_string(),
+            CsvColumn::Shared => bookmark.shared.to_string(),
+            CsvColumn::IsArchived => bookmark.is_archived.to_string(),
+            // tags can't have whitespace
+            CsvColumn::TagNames => bookmark.tag_names.join(" "),
+            CsvColumn::Uuid => bookmark.uuid.clone().unwrap_or_default(),
+            CsvColumn::DateAdded => bookmark.date_added.clone(),
+            CsvColumn::DateModified => bookmark.date_modified.clone(),
+        }
+    }
+}
+
+pub(crate) struct CsvOptions {
+    pub(crate) columns: Vec<CsvColumn>,
+    pub(crate) delimiter: char,
+    pub(crate) header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            columns: vec![
+                CsvColumn::Url,
+                CsvColumn::Title,
+                CsvColumn::Description,
+                CsvColumn::Notes,
+                CsvColumn::Unread,
+                CsvColumn::TagNames,
+                CsvColumn::DateAdded,
+                CsvColumn::DateModified,
+            ],
+            delimiter: ',',
+            header: true,
+        }
+    }
+}
+
+fn render_html(bookmarks: &[BookmarkResponse]) -> String {
+    let mut result = vec![
+        "<!DOCTYPE html>".to_owned(),
+        "<html><head><meta charset=\"utf-8\"><title>Bookmarks</title></head><body>".to_owned(),
+        "<ul>".to_owned(),
+    ];
+
+    for bookmark in bookmarks {
+        let tags: Vec<String> = bookmark
+            .tag_names
+            .iter()
+            .map(|tag| format!("#{}", escape_html(tag)))
+            .collect();
+        result.push(format!(
+            r#"<li><a href="{}">{}</a> <small>{}</small></li>"#,
+            escape_html(&bookmark.url),
+            escape_html(&bookmark.title),
+            tags.join(" ")
+        ));
+    }
+
+    result.push("</ul>".to_owned());
+    result.push("</body></html>".to_owned());
+    result.join("\n")
+}
+
+pub(crate) fn render_csv(bookmarks: &[BookmarkResponse], options: &CsvOptions) -> String {
+    let delimiter = options.delimiter.to_string();
+    let mut result = vec![];
+    if options.header {
+        let names: Vec<String> = options.columns.iter().map(|column| column.name()).collect();
+        result.push(names.join(&delimiter));
+    }
+
+    for bookmark in bookmarks {
+        let fields: Vec<String> = options
+            .columns
+            .iter()
+            .map(|column| escape_csv(&column.field(bookmark), options.delimiter))
+            .collect();
+        result.push(fields.join(&delimiter));
+    }
+
+    result.join("\r\n") + "\r\n"
+}
+
+fn render_ndjson(bookmarks: &[BookmarkResponse]) -> String {
+    bookmarks
+        .iter()
+        .filter_map(|bookmark| serde_json::to_string(bookmark).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Renders the bookmarks in the given format, or `None` for json which is left to the handler
+/// since the json body contains more than just the bookmarks.
+pub(crate) fn render_bookmarks(
+    format: &Format,
+    bookmarks: &[BookmarkResponse],
+) -> Option<Response> {
+    match format {
+        Format::Json => None,
+        Format::Html => Some(axum::response::Html(render_html(bookmarks)).into_response()),
+        Format::Csv => Some(
+            (
+                [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+                render_csv(bookmarks, &CsvOptions::default()),
+            )
+                .into_response(),
+        ),
+        Format::NdJson => Some(
+            (
+                [(header::CONTENT_TYPE, APPLICATION_NDJSON)],
+                render_ndjson(bookmarks),
+            )
+                .into_response(),
+        ),
+    }
+}
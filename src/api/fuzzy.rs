@@ -0,0 +1,100 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::collections::{BTreeSet, HashSet};
+use tracing::error;
+
+// Words need to share at least this share of their trigrams to be considered similar
+const MIN_SIMILARITY: f64 = 0.3;
+
+// Trigrams of the word padded like in pg_trgm, so the start and end of words weigh more
+fn trigrams(word: &str) -> HashSet<String> {
+    let chars: Vec<char> = format!("  {} ", word.to_lowercase()).chars().collect();
+    chars
+        .windows(3)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(str::to_lowercase)
+}
+
+fn closest<'a>(word: &str, vocabulary: &'a [(String, HashSet<String>)]) -> Option<&'a str> {
+    let word_trigrams = trigrams(word);
+    vocabulary
+        .iter()
+        .map(|(candidate, candidate_trigrams)| {
+            (candidate, similarity(&word_trigrams, candidate_trigrams))
+        })
+        .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(candidate, _)| candidate.as_str())
+}
+
+/// Replaces the free text terms in the search query with the most similar words from the titles
+/// and urls of the bookmarks. Returns `None` if there's nothing to correct.
+pub(crate) async fn suggest(pool: &SqlitePool, q: &str) -> Option<String> {
+    let rows = match sqlx::query("SELECT title, url FROM posts")
+        .map(|row: SqliteRow| (row.get::<String, _>("title"), row.get::<String, _>("url")))
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("Failed to get words for suggestions: {}", err);
+            return None;
+        }
+    };
+
+    // sorted to make the choice between equally similar words stable
+    let unique: BTreeSet<String> = rows
+        .iter()
+        .flat_map(|(title, url)| words(title).chain(words(url)))
+        .collect();
+    let vocabulary: Vec<(String, HashSet<String>)> = unique
+        .into_iter()
+        .map(|word| {
+            let word_trigrams = trigrams(&word);
+            (word, word_trigrams)
+        })
+        .collect();
+
+    let mut changed = false;
+    let corrected: Vec<String> = q
+        .split_whitespace()
+        .map(|token| {
+            let lowercase = token.to_lowercase();
+            if token.starts_with('#') || vocabulary.iter().any(|(word, _)| *word == lowercase) {
+                return token.to_owned();
+            }
+            match closest(token, &vocabulary) {
+                Some(word) => {
+                    changed = true;
+                    word.to_owned()
+                }
+                None => token.to_owned(),
+            }
+        })
+        .collect();
+
+    if changed {
+        Some(corrected.join(" "))
+    } else {
+        None
+    }
+}
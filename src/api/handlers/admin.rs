@@ -0,0 +1,963 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::audit::AffectedIds;
+use crate::restore::{self, RestoreMode, RestoreReport};
+use crate::retention::RetentionAction;
+use crate::totp;
+use crate::{AppState, PostID};
+use axum::body::{Body, Bytes};
+use axum::extract::{Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{middleware, Extension, Json, Router};
+use chrono::{DateTime, TimeZone, Utc};
+use hyper::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::query_builder::QueryBuilder;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_util::io::ReaderStream;
+use tracing::{error, info};
+
+use super::bookmarks::{
+    delete_bookmark, get_bookmarks, rebuild_url_keys, url_key, BookmarkResponse,
+};
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        // the routes changing data need a TOTP code when set up
+        .route("/rebuild", post(handle_post_rebuild))
+        .route("/rewrite", post(handle_post_rewrite))
+        .route("/restore", post(handle_post_restore))
+        .route("/retention", post(handle_post_retention))
+        .route_layer(middleware::from_fn_with_state(state.clone(), totp::require))
+        .route("/audit", get(handle_get_audit))
+        .route("/retention", get(handle_get_retention))
+        .route("/backup.sqlite", get(handle_get_backup))
+        .with_state(state)
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct AuditDb {
+    id: i64,
+    date: i64,
+    token: String,
+    method: String,
+    route: String,
+    status: u16,
+    ids: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct AuditResponse {
+    pub(crate) id: i64,
+    pub(crate) date: String,
+    pub(crate) token: String,
+    pub(crate) method: String,
+    pub(crate) route: String,
+    pub(crate) status: u16,
+    pub(crate) ids: Vec<PostID>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct AuditsResponse {
+    count: usize,
+    pub(crate) results: Vec<AuditResponse>,
+}
+
+impl From<AuditDb> for AuditResponse {
+    fn from(val: AuditDb) -> Self {
+        let date = Utc.timestamp_opt(val.date, 0).unwrap();
+
+        AuditResponse {
+            id: val.id,
+            date: date.to_rfc3339(),
+            token: val.token,
+            method: val.method,
+            route: val.route,
+            status: val.status,
+            ids: val
+                .ids
+                .split(',')
+                .filter_map(|id| id.parse().ok())
+                .collect(),
+        }
+    }
+}
+
+// admin/audit?method=DELETE&since=2025-01-01T00:00:00Z
+#[derive(Deserialize, Default)]
+struct AuditQuery {
+    method: Option<String>,
+    route: Option<String>,
+    token: Option<String>,
+    id: Option<PostID>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+fn parse_date(date: &str) -> Result<i64, StatusCode> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.timestamp())
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+async fn handle_get_audit(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditsResponse>, StatusCode> {
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new("SELECT * FROM audit WHERE 1");
+
+    if let Some(method) = query.method {
+        sql.push(" AND method = ");
+        sql.push_bind(method.to_uppercase());
+    }
+    if let Some(route) = query.route {
+        sql.push(" AND route LIKE ");
+        sql.push_bind(format!("{route}%"));
+    }
+    if let Some(token) = query.token {
+        sql.push(" AND token = ");
+        sql.push_bind(token);
+    }
+    if let Some(id) = query.id {
+        sql.push(" AND (',' || ids || ',') LIKE ");
+        sql.push_bind(format!("%,{id},%"));
+    }
+    if let Some(since) = query.since {
+        sql.push(" AND date >= ");
+        sql.push_bind(parse_date(&since)?);
+    }
+    if let Some(until) = query.until {
+        sql.push(" AND date <= ");
+        sql.push_bind(parse_date(&until)?);
+    }
+
+    sql.push(" ORDER BY id DESC");
+
+    let limit = query.limit.unwrap_or(100);
+    if limit > 0 {
+        sql.push(" LIMIT ");
+        sql.push_bind(limit);
+    }
+    if let Some(offset) = query.offset {
+        sql.push(" OFFSET ");
+        sql.push_bind(offset);
+    }
+
+    match sql.build_query_as::<AuditDb>().fetch_all(&state.pool).await {
+        Ok(rows) => {
+            let entries: Vec<AuditResponse> = rows.into_iter().map(AuditResponse::from).collect();
+            Ok(Json(AuditsResponse {
+                count: entries.len(),
+                results: entries,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to get audit entries: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn handle_get_backup(State(state): State<Arc<AppState>>) -> Result<Response, StatusCode> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let path = std::env::temp_dir().join(format!(
+        "pinrs-backup-{}-{}.sqlite",
+        std::process::id(),
+        nanos
+    ));
+
+    // the snapshot holds every bookmark and token, so only the server may read it. VACUUM INTO
+    // takes an empty file and keeps its mode, create_new refuses a file or link put there first
+    if let Err(err) = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .await
+    {
+        error!("Failed to create backup file: {} ({})", path.display(), err);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    // VACUUM INTO gives a consistent snapshot without blocking writers for longer than the copy
+    if let Err(err) = sqlx::query("VACUUM INTO $1")
+        .bind(path.to_string_lossy().into_owned())
+        .execute(&state.pool)
+        .await
+    {
+        error!("Failed to create backup: {}", err);
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let file = tokio::fs::File::open(&path).await;
+
+    // the open file can still be read after it's removed
+    if let Err(err) = tokio::fs::remove_file(&path).await {
+        error!("Failed to remove backup file: {} ({})", path.display(), err);
+    }
+
+    match file {
+        Ok(file) => {
+            info!("sending backup");
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "application/vnd.sqlite3"),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        "attachment; filename=\"pinrs.sqlite\"",
+                    ),
+                ],
+                Body::from_stream(ReaderStream::new(file)),
+            )
+                .into_response())
+        }
+        Err(err) => {
+            error!("Failed to open backup file: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RestoreQuery {
+    // merge or replace
+    mode: Option<String>,
+}
+
+// Restores the backup in the body, an SQLite database or linkding JSON, see restore.rs
+async fn handle_post_restore(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RestoreQuery>,
+    body: Bytes,
+) -> Result<Json<RestoreReport>, (StatusCode, String)> {
+    let mode = match query.mode.as_deref() {
+        None | Some("merge") => RestoreMode::Merge,
+        Some("replace") => RestoreMode::Replace,
+        Some(mode) => {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown mode: {mode}")));
+        }
+    };
+
+    match restore::restore(&state.pool, &body, mode).await {
+        Ok(report) => {
+            info!(
+                "restored {} of {} bookmarks",
+                report.restored, report.in_backup
+            );
+            Ok(Json(report))
+        }
+        Err(err) if err.downcast_ref::<sqlx::Error>().is_some() => {
+            error!("Failed to restore backup: {}", err);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to restore backup".to_owned(),
+            ))
+        }
+        Err(err) => Err((StatusCode::BAD_REQUEST, err.to_string())),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct RewriteResponse {
+    rewritten: Vec<PostID>,
+    // the rewritten url already belongs to another bookmark
+    conflicts: Vec<PostID>,
+}
+
+// Applies the rewrite rules to the bookmarks saved before the rules were added
+async fn handle_post_rewrite(
+    State(state): State<Arc<AppState>>,
+) -> Result<(Extension<AffectedIds>, Json<RewriteResponse>), StatusCode> {
+    let posts = match sqlx::query("SELECT id, url FROM posts")
+        .map(|row: SqliteRow| (row.get::<PostID, _>("id"), row.get::<String, _>("url")))
+        .fetch_all(&state.pool)
+        .await
+    {
+        Ok(posts) => posts,
+        Err(err) => {
+            error!("Failed to get posts to rewrite: {}", err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut rewritten = vec![];
+    let mut conflicts = vec![];
+    for (id, url) in posts {
+        let Some(new_url) = state.rewrite_rules.apply(&url) else {
+            continue;
+        };
+
+        match sqlx::query(
+            "UPDATE posts SET url = $1, original_url = COALESCE(original_url, url), date_modified = unixepoch(), url_key = $3 WHERE id = $2",
+        )
+        .bind(&new_url)
+        .bind(id)
+        .bind(url_key(&new_url))
+        .execute(&state.pool)
+        .await
+        {
+            Ok(_) => rewritten.push(id),
+            Err(err) => {
+                info!("Failed to rewrite {} to {}: {}", url, new_url, err);
+                conflicts.push(id);
+            }
+        }
+    }
+
+    info!("rewrote {} bookmarks", rewritten.len());
+    Ok((
+        Extension(AffectedIds(rewritten.clone())),
+        Json(RewriteResponse {
+            rewritten,
+            conflicts,
+        }),
+    ))
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct RetentionResult {
+    tag: String,
+    action: RetentionAction,
+    days: u32,
+    bookmarks: Vec<BookmarkResponse>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct RetentionResponse {
+    // nothing was changed, the bookmarks are those that would be
+    dry_run: bool,
+    results: Vec<RetentionResult>,
+}
+
+// The bookmarks each retention rule applies to, deleted or archived unless it's a dry run. The
+// rules are applied in order, a bookmark deleted by one isn't archived by the next.
+async fn apply_retention(
+    state: &AppState,
+    dry_run: bool,
+) -> Result<Vec<RetentionResult>, sqlx::Error> {
+    let mut results = vec![];
+    for rule in &state.retention_rules.0 {
+        let bookmarks = get_bookmarks(&state.pool, rule.query()).await;
+        if !dry_run {
+            for bookmark in &bookmarks {
+                match rule.action {
+                    RetentionAction::Delete => {
+                        delete_bookmark(&state.pool, bookmark.id).await?;
+                    }
+                    RetentionAction::Archive => {
+                        sqlx::query(
+                            "UPDATE posts SET is_archived = 1, date_modified = unixepoch() WHERE id = $1",
+                        )
+                        .bind(bookmark.id)
+                        .execute(&state.pool)
+                        .await?;
+                    }
+                }
+            }
+        }
+        results.push(RetentionResult {
+            tag: rule.tag.clone(),
+            action: rule.action,
+            days: rule.days,
+            bookmarks,
+        });
+    }
+    Ok(results)
+}
+
+// Previews the retention rules, see retention.rs
+async fn handle_get_retention(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RetentionResponse>, StatusCode> {
+    match apply_retention(&state, true).await {
+        Ok(results) => Ok(Json(RetentionResponse {
+            dry_run: true,
+            results,
+        })),
+        Err(err) => {
+            error!("Failed to preview the retention rules: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Deletes and archives the bookmarks matching the retention rules
+async fn handle_post_retention(
+    State(state): State<Arc<AppState>>,
+) -> Result<(Extension<AffectedIds>, Json<RetentionResponse>), StatusCode> {
+    match apply_retention(&state, false).await {
+        Ok(results) => {
+            let ids: Vec<PostID> = results
+                .iter()
+                .flat_map(|result| result.bookmarks.iter().map(|bookmark| bookmark.id))
+                .collect();
+            info!("applied the retention rules to {} bookmarks", ids.len());
+            Ok((
+                Extension(AffectedIds(ids)),
+                Json(RetentionResponse {
+                    dry_run: false,
+                    results,
+                }),
+            ))
+        }
+        Err(err) => {
+            error!("Failed to apply the retention rules: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Derived data that can get out of sync after editing the database by hand
+const REBUILD_STEPS: [(&str, &str); 3] = [
+    (
+        "fts",
+        "INSERT INTO posts_fts (posts_fts) VALUES ('rebuild')",
+    ),
+    (
+        "post_tag",
+        "DELETE FROM post_tag WHERE post_id NOT IN (SELECT id FROM posts) OR tag_id NOT IN (SELECT id FROM tags)",
+    ),
+    // kept after a delete for bringing the bookmark back from the graveyard
+    (
+        "large_notes",
+        "DELETE FROM large_notes WHERE uuid NOT IN (SELECT uuid FROM posts WHERE uuid IS NOT NULL UNION SELECT post ->> 'uuid' FROM graveyard WHERE post IS NOT NULL)",
+    ),
+];
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct RebuildStep {
+    name: String,
+    ok: bool,
+    // rows changed by the step, if it tells
+    rows: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct RebuildResponse {
+    steps: Vec<RebuildStep>,
+}
+
+fn rebuild_step(name: &str, result: Result<u64, sqlx::Error>) -> RebuildStep {
+    match result {
+        Ok(rows) => RebuildStep {
+            name: name.to_owned(),
+            ok: true,
+            rows,
+            error: None,
+        },
+        Err(err) => {
+            error!("Failed to rebuild {}: {}", name, err);
+            RebuildStep {
+                name: name.to_owned(),
+                ok: false,
+                rows: 0,
+                error: Some(err.to_string()),
+            }
+        }
+    }
+}
+
+// Rebuilds the full text index and the url keys, and removes tag links to bookmarks or tags that
+// don't exist. A failing step doesn't stop the others.
+async fn handle_post_rebuild(State(state): State<Arc<AppState>>) -> Json<RebuildResponse> {
+    let mut steps = vec![];
+    for (name, sql) in REBUILD_STEPS {
+        let result = sqlx::query(sql).execute(&state.pool).await;
+        steps.push(rebuild_step(
+            name,
+            result.map(|result| result.rows_affected()),
+        ));
+    }
+
+    // the keys are made in Rust, see bookmarks::url_key
+    let result = match state.pool.begin().await {
+        Ok(mut tx) => match rebuild_url_keys(&mut tx).await {
+            Ok(rows) => tx.commit().await.map(|()| rows),
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    };
+    steps.push(rebuild_step("url_key", result));
+
+    info!("rebuilt derived data");
+    Json(RebuildResponse { steps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::bookmarks::BookmarkResponse;
+    use crate::retention::RetentionRules;
+    use crate::rewrite::RewriteRules;
+    use crate::{app, app_with_state, open_db, setup_db};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_get_backup() {
+        // VACUUM INTO an in-memory database doesn't write any file, so use a real one
+        let db = std::env::temp_dir().join(format!("pinrs-test-{}.db", std::process::id()));
+        let pool = open_db(&format!("sqlite://{}?mode=rwc", db.display()), false).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/admin/backup.sqlite")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let _ = std::fs::remove_file(&db);
+
+        assert!(body.starts_with(b"SQLite format 3\0"));
+    }
+
+    #[tokio::test]
+    async fn test_get_audit() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        r#"{"url": "https://example.com", "title": "a"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/api/bookmarks/12345")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/admin/audit")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: AuditsResponse = serde_json::from_slice(&body).unwrap();
+
+        assert!(res.results.len() == 2);
+        assert!(res.results[0].method == "DELETE");
+        assert!(res.results[0].status == 404);
+        assert!(res.results[0].ids == vec![12345]);
+        assert!(res.results[1].method == "POST");
+        assert!(res.results[1].route == "/api/bookmarks");
+        assert!(res.results[1].token == "token");
+        assert!(res.results[1].ids.len() == 1);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/admin/audit?method=post")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: AuditsResponse = serde_json::from_slice(&body).unwrap();
+
+        assert!(res.results.len() == 1);
+        assert!(res.results[0].method == "POST");
+    }
+
+    #[tokio::test]
+    async fn test_post_rewrite() {
+        let pool = setup_db(true).await;
+        let app_without_rules = app(pool.clone(), TOKEN.to_owned());
+
+        for url in [
+            "https://twitter.com/a",
+            "https://twitter.com/b",
+            "https://nitter.example/b",
+        ] {
+            let response = app_without_rules
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(format!(r#"{{"url": "{url}", "title": "a"}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let mut state = AppState::new(pool, TOKEN.to_owned());
+        state.rewrite_rules =
+            RewriteRules::parse("^https://twitter.com/ https://nitter.example/").unwrap();
+        let app = app_with_state(state);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/admin/rewrite")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: RewriteResponse = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(res.rewritten, vec![1]);
+        assert_eq!(res.conflicts, vec![2]);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks/1")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let bookmark: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(bookmark.url, "https://nitter.example/a");
+        assert_eq!(
+            bookmark.original_url.as_deref(),
+            Some("https://twitter.com/a")
+        );
+
+        // new bookmarks are rewritten when saved
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        r#"{"url": "https://twitter.com/c", "title": "c"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let bookmark: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(bookmark.url, "https://nitter.example/c");
+        assert_eq!(
+            bookmark.original_url.as_deref(),
+            Some("https://twitter.com/c")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let pool = setup_db(true).await;
+        let app_without_rules = app(pool.clone(), TOKEN.to_owned());
+
+        for (url, tag, date_added) in [
+            ("https://example.com/a", "temp", "2020-01-01T00:00:00Z"),
+            ("https://example.com/b", "temp", "2100-01-01T00:00:00Z"),
+            ("https://example.com/c", "news", "2020-01-01T00:00:00Z"),
+            ("https://example.com/d", "keep", "2020-01-01T00:00:00Z"),
+        ] {
+            let response = app_without_rules
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(format!(
+                            r#"{{"url": "{url}", "title": "a", "tag_names": ["{tag}"], "date_added": "{date_added}"}}"#
+                        )))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let mut state = AppState::new(pool, TOKEN.to_owned());
+        state.retention_rules = RetentionRules::parse("temp delete 30d\nnews archive 7d").unwrap();
+        let app = app_with_state(state);
+
+        let applied = vec![vec!["https://example.com/a"], vec!["https://example.com/c"]];
+        for (method, dry_run, urls) in [
+            ("GET", true, applied.clone()),
+            ("POST", false, applied),
+            ("GET", true, vec![vec![], vec![]]),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri("/api/admin/retention")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let res: RetentionResponse = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(res.dry_run, dry_run);
+            let result_urls: Vec<Vec<&str>> = res
+                .results
+                .iter()
+                .map(|result| {
+                    result
+                        .bookmarks
+                        .iter()
+                        .map(|bookmark| bookmark.url.as_str())
+                        .collect()
+                })
+                .collect();
+            assert_eq!(result_urls, urls, "{method}");
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks/archived")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["x-total-count"],
+            "1",
+            "the news bookmark is archived"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_rebuild() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        r#"{"url": "https://example.com", "title": "rebuilt", "tag_names": ["a"]}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // break the key, the index and the tags like a bulk edit without triggers or foreign keys
+        // would
+        sqlx::query("UPDATE posts SET url_key = 'edited'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO posts_fts (posts_fts) VALUES ('delete-all')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO post_tag (post_id, tag_id) VALUES (12345, 1)")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("PRAGMA foreign_keys = ON")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/admin/rebuild")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: RebuildResponse = serde_json::from_slice(&body).unwrap();
+
+        assert!(res.steps.iter().all(|step| step.ok));
+        assert_eq!(res.steps[1].name, "post_tag");
+        assert_eq!(res.steps[1].rows, 1);
+        let url_key_step = res.steps.last().unwrap();
+        assert_eq!(url_key_step.name, "url_key");
+        assert_eq!(url_key_step.rows, 1);
+        let key: String = sqlx::query_scalar("SELECT url_key FROM posts")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(key, "https://example.com/");
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks?q=rebuilt")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get("x-total-count")
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            "1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_restore() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let restore = |app: Router, mode: &str, body: &'static str| {
+            let uri = format!("/api/admin/restore?mode={mode}");
+            async move {
+                app.oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let json = r#"[{"url": "https://example.com", "title": "restored", "description": "", "notes": "", "unread": false, "tag_names": ["a"], "date_added": "2024-01-01T00:00:00Z", "date_modified": "2024-01-01T00:00:00Z"}]"#;
+        let response = restore(app.clone(), "replace", json).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: RestoreReport = serde_json::from_slice(&body).unwrap();
+        assert_eq!((report.restored, report.bookmarks, report.tags), (1, 1, 1));
+
+        let response = restore(app.clone(), "merge", json).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: RestoreReport = serde_json::from_slice(&body).unwrap();
+        assert_eq!((report.restored, report.bookmarks), (0, 1));
+
+        let response = restore(app.clone(), "merge", "not a backup").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = restore(app, "wipe", json).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -2,22 +2,52 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use crate::api::etag::etag;
+use crate::api::format::{render_bookmarks, Format};
+use crate::api::fuzzy;
+use crate::audit::AffectedIds;
+use crate::metrics::SearchKind;
+use crate::notes;
+use crate::rewrite::RewriteRules;
 use crate::{AppState, PostID, TagID};
-use axum::extract::{Path, Query, State};
-use axum::response::IntoResponse;
+use axum::extract::{OriginalUri, Path, Query, RawQuery, State};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Uri};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post, put};
-use axum::{Json, Router};
-use chrono::{TimeZone, Utc};
-use hyper::StatusCode;
+use axum::{middleware, Extension, Json, Router};
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use hyper::{header, StatusCode};
 use serde::{Deserialize, Serialize};
+use similar::TextDiff;
 use sqlx::query_builder::QueryBuilder;
 use sqlx::sqlite::SqliteRow;
-use sqlx::{Row, SqlitePool};
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{debug, error, info};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, instrument, warn, Span};
 
-use super::tags::TagDb;
+use super::graveyard;
+use super::smart_tags;
+use super::tags::{is_valid_tag_name, TagDb};
+use super::templates;
+
+const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+// Posts with their tags as a JSON array, to be grouped by posts.id. Unlike a comma separated
+// list, the array can't be confused by the characters in the tags.
+const SELECT_BOOKMARKS: &str = r"
+    SELECT posts.*, json_group_array(tags.name) FILTER (WHERE tags.name IS NOT NULL) AS tag_names
+        FROM posts
+        LEFT OUTER JOIN post_tag ON (posts.id = post_tag.post_id)
+        LEFT OUTER JOIN tags ON (tags.id = post_tag.tag_id)
+";
+
+// bookmarks per page unless given, 0 is all
+const DEFAULT_LIMIT: u32 = 100;
+
+// near-duplicates returned by /check at most
+const MAX_CANDIDATES: usize = 10;
 
 #[derive(sqlx::FromRow, Deserialize, Serialize, Debug)]
 struct BookmarkDb {
@@ -26,51 +56,171 @@ struct BookmarkDb {
     title: String,
     description: Option<String>,
     notes: Option<String>,
+    private_notes: Option<String>,
     unread: Option<bool>,
+    shared: Option<bool>,
+    is_archived: Option<bool>,
+    original_url: Option<String>,
+    source: Option<String>,
+    referrer: Option<String>,
+    uuid: Option<String>,
+    // JSON array, see SELECT_BOOKMARKS
     tag_names: Option<String>,
     date_added: i64,
     date_modified: i64,
 }
 
-#[derive(sqlx::FromRow, Debug, Deserialize, Serialize)]
+#[derive(sqlx::FromRow, Debug, Default, Deserialize, Serialize)]
 pub(crate) struct BookmarkRequest {
     pub(crate) url: String,
     pub(crate) title: String,
     pub(crate) description: Option<String>,
     pub(crate) notes: Option<String>,
+    // never included in the shared site or, unless asked for, in exports
+    pub(crate) private_notes: Option<String>,
     pub(crate) unread: Option<bool>,
+    pub(crate) shared: Option<bool>,
+    pub(crate) is_archived: Option<bool>,
     pub(crate) tag_names: Option<Vec<String>>,
+    // the url as given, when changed by the rewrite rules
     #[serde(skip_deserializing)]
-    pub(crate) date_added: Option<i64>,
+    pub(crate) original_url: Option<String>,
+    // how the bookmark was created, e.g. api or import:linkding
     #[serde(skip_deserializing)]
+    pub(crate) source: Option<String>,
+    // the page the bookmark was saved from
+    pub(crate) referrer: Option<String>,
+    // text selected when saving, added to the notes as a quote
+    #[serde(skip_serializing)]
+    pub(crate) selected_text: Option<String>,
+    // set by the server unless given when adding, e.g. when migrating from another service
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub(crate) date_added: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_date")]
     pub(crate) date_modified: Option<i64>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Default)]
+// A date as in the responses, e.g. 2025-01-01T00:00:00Z, or a timestamp
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Date {
+        Timestamp(i64),
+        Rfc3339(String),
+    }
+
+    match Option::<Date>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Date::Timestamp(timestamp)) => Ok(Some(timestamp)),
+        Some(Date::Rfc3339(date)) => DateTime::parse_from_rfc3339(&date)
+            .map(|date| Some(date.timestamp()))
+            .map_err(|_| serde::de::Error::custom(format!("invalid date: {date}"))),
+    }
+}
+
+// the flags mirror the linkding API
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
 pub(crate) struct BookmarkResponse {
     pub(crate) id: PostID,
     pub(crate) url: String,
     pub(crate) title: String,
     pub(crate) description: Option<String>,
     pub(crate) notes: Option<String>,
+    pub(crate) private_notes: Option<String>,
     pub(crate) unread: bool,
+    pub(crate) shared: bool,
+    pub(crate) is_archived: bool,
+    pub(crate) original_url: Option<String>,
+    pub(crate) source: Option<String>,
+    pub(crate) referrer: Option<String>,
+    // stable across instances, unlike the id
+    pub(crate) uuid: Option<String>,
     pub(crate) tag_names: Vec<String>,
     pub(crate) date_added: String,
     pub(crate) date_modified: String,
+    // set for results of a corrected search query, see did_you_mean
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) fuzzy: bool,
 }
 
+/// The bookmark after a change, with the fields that changed and their previous values
 #[derive(Deserialize, Serialize, Debug)]
-struct BookmarksResponse {
-    count: usize,
-    results: Vec<BookmarkResponse>,
+pub(crate) struct BookmarkChangeResponse {
+    #[serde(flatten)]
+    pub(crate) bookmark: BookmarkResponse,
+    pub(crate) changed_fields: Vec<String>,
+    pub(crate) previous: serde_json::Map<String, serde_json::Value>,
+}
+
+// not changed by the client, date_modified changes with any change
+const UNCHANGEABLE_FIELDS: [&str; 6] = [
+    "id",
+    "uuid",
+    "source",
+    "date_added",
+    "date_modified",
+    "fuzzy",
+];
+
+impl BookmarkChangeResponse {
+    fn new(before: &BookmarkResponse, after: BookmarkResponse) -> Self {
+        // the order of the tags doesn't matter
+        let normalized = |bookmark: &BookmarkResponse| {
+            let mut value = serde_json::to_value(bookmark).unwrap_or_default();
+            if let Some(serde_json::Value::Array(tags)) = value.get_mut("tag_names") {
+                tags.sort_by_key(ToString::to_string);
+            }
+            value
+        };
+        let serde_json::Value::Object(mut before) = normalized(before) else {
+            unreachable!("a bookmark is an object")
+        };
+        let after_value = normalized(&after);
+
+        let mut changed_fields = vec![];
+        let mut previous = serde_json::Map::new();
+        for (field, value) in &mut before {
+            if UNCHANGEABLE_FIELDS.contains(&field.as_str())
+                || after_value.get(field) == Some(value)
+            {
+                continue;
+            }
+            changed_fields.push(field.clone());
+            previous.insert(field.clone(), value.take());
+        }
+
+        BookmarkChangeResponse {
+            bookmark: after,
+            changed_fields,
+            previous,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct BookmarksResponse {
+    // number of results in this page
+    pub(crate) count: usize,
+    // number of bookmarks matching the query, page until offset reaches it
+    total: usize,
+    limit: u32,
+    offset: u32,
+    pub(crate) results: Vec<BookmarkResponse>,
+    // the corrected search query when the original one didn't match anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    did_you_mean: Option<String>,
 }
 
 impl From<BookmarkDb> for BookmarkResponse {
     fn from(val: BookmarkDb) -> Self {
-        let mut tags = vec![];
-        if let Some(tag_names) = val.tag_names {
-            tags = tag_names.split(',').map(String::from).collect();
-        }
+        let tags = val
+            .tag_names
+            .and_then(|tag_names| serde_json::from_str(&tag_names).ok())
+            .unwrap_or_default();
 
         let added = Utc.timestamp_opt(val.date_added, 0).unwrap();
         let modified = Utc.timestamp_opt(val.date_modified, 0).unwrap();
@@ -81,10 +231,18 @@ impl From<BookmarkDb> for BookmarkResponse {
             title: val.title,
             description: val.description,
             notes: val.notes,
+            private_notes: val.private_notes,
             unread: val.unread.unwrap_or_default(),
+            shared: val.shared.unwrap_or_default(),
+            is_archived: val.is_archived.unwrap_or_default(),
+            original_url: val.original_url,
+            source: val.source,
+            referrer: val.referrer,
+            uuid: val.uuid,
             tag_names: tags,
             date_added: added.to_rfc3339(),
             date_modified: modified.to_rfc3339(),
+            fuzzy: false,
         }
     }
 }
@@ -93,25 +251,39 @@ pub fn configure(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/", get(handle_get_bookmarks))
         .route("/", post(handle_post_bookmark))
+        .route("/quick", post(handle_post_quick_bookmark))
+        .route("/by-url", put(handle_put_bookmark_by_url))
         .route("/{id}", get(handle_get_bookmark))
         .route("/{id}", put(handle_put_bookmark))
         .route("/{id}", delete(handle_delete_bookmark))
+        .route("/uuid/{uuid}", get(handle_get_bookmark_by_uuid))
+        .route("/archived", get(handle_get_archived_bookmarks))
         .route("/check", get(handle_check_bookmark))
+        .route("/counts", get(handle_get_counts))
+        .route("/facets", get(handle_get_facets))
+        .route("/on-this-day", get(handle_get_on_this_day))
+        .route("/calendar", get(handle_get_calendar))
+        .route("/{id}/tags", post(handle_post_bookmark_tags))
+        .route("/{id}/notes/revisions", get(handle_get_notes_revisions))
+        .route(
+            "/{id}/notes/revisions/{revision}/diff",
+            get(handle_get_notes_diff),
+        )
+        .layer(middleware::from_fn(etag))
         .with_state(state)
 }
 
 struct LookupType<'a> {
     id: Option<PostID>,
     url: Option<&'a str>,
+    uuid: Option<&'a str>,
+    // the url as given, or any with the same url key
+    by_key: bool,
 }
 
+#[instrument(level = "debug", name = "db.get_bookmark", skip_all, fields(rows))]
 async fn get_bookmark(state: Arc<AppState>, from: LookupType<'_>) -> Option<BookmarkResponse> {
-    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(
-        r"SELECT posts.*,GROUP_CONCAT(tags.name) AS tag_names
-                    FROM posts
-                    LEFT OUTER JOIN post_tag ON (posts.id = post_tag.post_id)
-                    LEFT OUTER JOIN tags ON (tags.id = post_tag.tag_id)",
-    );
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(SELECT_BOOKMARKS);
 
     if let Some(id) = from.id {
         sql.push(" WHERE posts.id = ");
@@ -119,6 +291,13 @@ async fn get_bookmark(state: Arc<AppState>, from: LookupType<'_>) -> Option<Book
     } else if let Some(url) = from.url {
         sql.push(" WHERE posts.url = ");
         sql.push_bind(url);
+        if from.by_key {
+            sql.push(" OR posts.url_key = ");
+            sql.push_bind(url_key(url));
+        }
+    } else if let Some(uuid) = from.uuid {
+        sql.push(" WHERE posts.uuid = ");
+        sql.push_bind(uuid);
     } else {
         error!("get_bookmark called with the wrong parameters");
         return None;
@@ -133,7 +312,9 @@ async fn get_bookmark(state: Arc<AppState>, from: LookupType<'_>) -> Option<Book
     {
         Ok(row) => match row {
             Some(row) => {
-                let post: BookmarkResponse = row.into();
+                Span::current().record("rows", 1);
+                let mut post: BookmarkResponse = row.into();
+                notes::load(&state.pool, std::slice::from_mut(&mut post)).await;
                 Some(post)
             }
             None => None,
@@ -146,6 +327,39 @@ async fn get_bookmark(state: Arc<AppState>, from: LookupType<'_>) -> Option<Book
     }
 }
 
+pub(crate) async fn get_bookmark_by_id(
+    state: Arc<AppState>,
+    id: PostID,
+) -> Option<BookmarkResponse> {
+    get_bookmark(
+        state,
+        LookupType {
+            id: Some(id),
+            url: None,
+            uuid: None,
+            by_key: false,
+        },
+    )
+    .await
+}
+
+/// The bookmark with the url, as given or by its url key
+pub(crate) async fn get_bookmark_by_url(
+    state: Arc<AppState>,
+    url: &str,
+) -> Option<BookmarkResponse> {
+    get_bookmark(
+        state,
+        LookupType {
+            id: None,
+            url: Some(url),
+            uuid: None,
+            by_key: true,
+        },
+    )
+    .await
+}
+
 #[derive(Deserialize, Serialize, Debug, Default)]
 struct ResponseCheckMetadata {
     url: String,
@@ -156,47 +370,133 @@ struct ResponseCheck {
     bookmark: Option<BookmarkResponse>,
     metadata: Option<ResponseCheckMetadata>,
     auto_tags: Vec<String>,
+    // bookmarks with a url differing only by scheme, www., trailing slash, query or fragment
+    candidates: Vec<BookmarkResponse>,
 }
 #[derive(Deserialize)]
 struct Url {
     url: String,
+    // also a bookmark with the same url key, see url_key
+    #[serde(default)]
+    normalized: bool,
 }
 async fn handle_check_bookmark(
     State(state): State<Arc<AppState>>,
     Query(url): Query<Url>,
 ) -> Result<Json<ResponseCheck>, StatusCode> {
-    if let Some(post) = get_bookmark(
-        state,
+    // the bookmark is saved with the rewritten url
+    let lookup_url = state.rewrite_rules.apply(&url.url);
+    let lookup_url = lookup_url.as_deref().unwrap_or(&url.url);
+    let bookmark = get_bookmark(
+        state.clone(),
         LookupType {
-            url: Some(&url.url),
+            url: Some(lookup_url),
             id: None,
+            uuid: None,
+            by_key: url.normalized,
         },
     )
-    .await
-    {
-        let response = ResponseCheck {
-            bookmark: Some(post),
-            metadata: Some(ResponseCheckMetadata { url: url.url }),
-            auto_tags: vec![],
-        };
-        Ok(Json(response))
-    } else {
-        let response = ResponseCheck {
-            bookmark: None,
-            metadata: Some(ResponseCheckMetadata { url: url.url }),
-            auto_tags: vec![],
-        };
-        Ok(Json(response))
+    .await;
+    let candidates = get_candidates(&state, lookup_url, bookmark.as_ref().map(|post| post.id))
+        .await
+        .map_err(|err| {
+            error!("Failed to get near-duplicates: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(ResponseCheck {
+        bookmark,
+        metadata: Some(ResponseCheckMetadata { url: url.url }),
+        auto_tags: vec![],
+        candidates,
+    }))
+}
+
+// The host without www. and the path without a trailing slash, the same for urls differing only
+// by scheme, www., trailing slash, query or fragment
+fn loose_url(url: &str) -> Option<(String, String)> {
+    let url = url::Url::parse(url.trim()).ok()?;
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").unwrap_or(host).to_owned();
+    let port = url
+        .port()
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+    let key = format!("{host}{port}{}", url.path().trim_end_matches('/'));
+    Some((host, key))
+}
+
+// The bookmarks with the same loose url, except the one with the exact url
+async fn get_candidates(
+    state: &AppState,
+    url: &str,
+    exclude: Option<PostID>,
+) -> Result<Vec<BookmarkResponse>, sqlx::Error> {
+    let Some((host, key)) = loose_url(url) else {
+        return Ok(vec![]);
+    };
+
+    // narrowed down by the host in sql, and then compared in full
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(SELECT_BOOKMARKS);
+    sql.push(" WHERE instr(lower(posts.url), ");
+    sql.push_bind(host);
+    sql.push(") > 0");
+    if let Some(id) = exclude {
+        sql.push(" AND posts.id != ");
+        sql.push_bind(id);
     }
+    sql.push(" GROUP BY posts.id ORDER BY posts.date_added DESC, posts.id DESC");
+
+    let rows = sql
+        .build_query_as::<BookmarkDb>()
+        .fetch_all(&state.pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .filter(|row| loose_url(&row.url).is_some_and(|(_, row_key)| row_key == key))
+        .take(MAX_CANDIDATES)
+        .map(Into::into)
+        .collect())
 }
 
 #[derive(Default)]
 struct SearchQuery {
     tag_names: Vec<String>,
     text: Vec<String>,
+    // set when tags are combined with parentheses or OR, e.g. (#rust OR #go) async
+    expression: Option<SearchExpr>,
+}
+
+// A search with groups. Like without groups, the tags next to each other match any of them, and
+// they, the words and the groups next to each other all have to match.
+#[derive(Debug, PartialEq)]
+enum SearchExpr {
+    Tags(Vec<String>),
+    // words for the full text search
+    Text(String),
+    And(Vec<SearchExpr>),
+    Or(Vec<SearchExpr>),
 }
 
 fn parse_search(query: &str) -> SearchQuery {
+    // without tags the full text search handles parentheses and OR itself
+    let tokens = search_tokens(query);
+    if tokens
+        .iter()
+        .any(|token| matches!(*token, "(" | ")" | "OR"))
+        && tokens.iter().any(|token| token.starts_with('#'))
+    {
+        let (tags, text): (Vec<&str>, Vec<&str>) = tokens
+            .iter()
+            .filter(|token| !matches!(**token, "(" | ")" | "OR"))
+            .partition(|token| token.starts_with('#'));
+        return SearchQuery {
+            tag_names: tags.iter().map(|tag| tag[1..].to_owned()).collect(),
+            text: text.into_iter().map(str::to_owned).collect(),
+            expression: Some(parse_search_or(&tokens, &mut 0)),
+        };
+    }
+
     let tokens = query.split_whitespace();
 
     let mut tags = vec![];
@@ -214,116 +514,511 @@ fn parse_search(query: &str) -> SearchQuery {
     SearchQuery {
         tag_names: tags,
         text,
+        expression: None,
+    }
+}
+
+// The words of the query, with the parentheses as words of their own. Closing parentheses
+// without an opening one are left out.
+fn search_tokens(query: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut depth = 0;
+    for mut word in query.split_whitespace() {
+        while let Some(rest) = word.strip_prefix('(') {
+            tokens.push("(");
+            depth += 1;
+            word = rest;
+        }
+        let trimmed = word.trim_end_matches(')');
+        if !trimmed.is_empty() {
+            tokens.push(trimmed);
+        }
+        for _ in trimmed.len()..word.len() {
+            if depth > 0 {
+                tokens.push(")");
+                depth -= 1;
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_search_or(tokens: &[&str], pos: &mut usize) -> SearchExpr {
+    let mut terms = vec![parse_search_and(tokens, pos)];
+    while tokens.get(*pos) == Some(&"OR") {
+        *pos += 1;
+        terms.push(parse_search_and(tokens, pos));
+    }
+    terms.retain(|term| *term != SearchExpr::And(vec![]));
+    if terms.len() == 1 {
+        terms.remove(0)
+    } else {
+        SearchExpr::Or(terms)
+    }
+}
+
+fn parse_search_and(tokens: &[&str], pos: &mut usize) -> SearchExpr {
+    let mut terms = vec![];
+    let mut tags = vec![];
+    let mut words = vec![];
+    while let Some(&token) = tokens.get(*pos) {
+        match token {
+            ")" | "OR" => break,
+            "(" => {
+                *pos += 1;
+                terms.push(parse_search_or(tokens, pos));
+                // an unbalanced parenthesis is closed at the end
+                if tokens.get(*pos) == Some(&")") {
+                    *pos += 1;
+                }
+                continue;
+            }
+            _ => match token.strip_prefix('#') {
+                Some(tag) => tags.push(tag.to_owned()),
+                None => words.push(token),
+            },
+        }
+        *pos += 1;
+    }
+    if !tags.is_empty() {
+        terms.push(SearchExpr::Tags(tags));
+    }
+    if !words.is_empty() {
+        terms.push(SearchExpr::Text(words.join(" ")));
+    }
+    terms.retain(|term| *term != SearchExpr::And(vec![]));
+    if terms.len() == 1 {
+        terms.remove(0)
+    } else {
+        SearchExpr::And(terms)
     }
 }
 
 // bookmarks?q=#audio namen&unread=yes
-#[derive(Deserialize, Default)]
+#[derive(Clone, Deserialize, Default)]
 pub(crate) struct BookmarkQuery {
     pub(crate) q: Option<String>,
     pub(crate) limit: Option<u32>,
     pub(crate) offset: Option<u32>,
     pub(crate) unread: Option<String>,
+    pub(crate) shared: Option<String>,
+    // tag=rust&tag=async, all tags must match. Repeated keys aren't supported by Query, see
+    // tags_from_query
+    #[serde(skip)]
+    pub(crate) tag: Vec<String>,
+    // tag_any=rust,go, any of the tags must match
+    pub(crate) tag_any: Option<String>,
+    // modified=today|week|month|year, modified since the start of the day or within the last
+    // 7, 30 or 365 days
+    pub(crate) modified: Option<String>,
+    // url=https://example.com/, the bookmark with the url, as given or normalized
+    pub(crate) url: Option<String>,
+    // source=import:linkding, how the bookmarks were created
+    pub(crate) source: Option<String>,
+    // added_before=90d or added_before=2025-01-01T00:00:00Z
+    pub(crate) added_before: Option<String>,
+    // added_since=30d or added_since=2025-01-01T00:00:00Z
+    pub(crate) added_since: Option<String>,
+    // sort=title or sort=-title, the newest first if not given
+    pub(crate) sort: Option<String>,
+    // smart tags by name, expanded to their query when filtered on
+    #[serde(skip)]
+    pub(crate) smart_tags: HashMap<String, BookmarkQuery>,
+    // set by the route, the main listing only has the bookmarks that aren't archived. None
+    // includes both.
+    #[serde(skip)]
+    pub(crate) archived: Option<bool>,
+    // include=notes, the large notes too, see notes.rs
+    pub(crate) include: Option<String>,
 }
 
-pub(crate) async fn get_bookmarks(
-    pool: &SqlitePool,
-    query: BookmarkQuery,
-) -> Vec<BookmarkResponse> {
-    let limit = query.limit.unwrap_or(100);
-    let offset = query.offset.unwrap_or(0);
-    let unread = query.unread.unwrap_or("no".to_owned());
+impl BookmarkQuery {
+    /// Parses the query string of a listing, e.g. `unread=yes&tag=rust`
+    pub(crate) fn parse(query: &str) -> Option<Self> {
+        let uri: Uri = format!("/?{query}").parse().ok()?;
+        let Query(mut parsed) = Query::<BookmarkQuery>::try_from_uri(&uri).ok()?;
+        parsed.tag = tags_from_query(Some(query));
+        Some(parsed)
+    }
+}
 
-    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(
+fn tags_from_query(query: Option<&str>) -> Vec<String> {
+    url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .filter(|(key, value)| key == "tag" && !value.is_empty())
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+// The url as given and normalized, e.g. with the host in lowercase and without a default port
+fn url_variants(url: &str) -> Vec<String> {
+    let url = url.trim();
+    let mut variants = vec![url.to_owned()];
+    if let Ok(normalized) = url::Url::parse(url) {
+        if normalized.as_str() != url {
+            variants.push(normalized.into());
+        }
+    }
+    variants
+}
+
+/// The url with the host in lowercase and punycode, as parsed, and the query parameters in
+/// order. The scheme, path and fragment are kept, they can tell pages apart. No two bookmarks
+/// have the same key, see `posts_url_key`.
+pub(crate) fn url_key(url: &str) -> String {
+    let url = url.trim();
+    let Ok(mut parsed) = url::Url::parse(url) else {
+        return url.to_owned();
+    };
+
+    let mut params: Vec<(String, String)> = parsed.query_pairs().into_owned().collect();
+    if !params.is_empty() {
+        params.sort();
+        parsed.query_pairs_mut().clear().extend_pairs(params);
+    }
+    parsed.into()
+}
+
+/// Sets the keys of the bookmarks without one, e.g. restored from a backup, returning how many
+/// were set. A bookmark with the key of another one is left without, and isn't stopped from
+/// being duplicated.
+pub(crate) async fn fill_url_keys(conn: &mut SqliteConnection) -> Result<u64, sqlx::Error> {
+    let posts: Vec<(PostID, String)> =
+        sqlx::query_as("SELECT id, url FROM posts WHERE url_key IS NULL")
+            .fetch_all(&mut *conn)
+            .await?;
+    let mut filled = 0;
+    for (id, url) in posts {
+        match sqlx::query("UPDATE posts SET url_key = $1 WHERE id = $2")
+            .bind(url_key(&url))
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+        {
+            Ok(_) => filled += 1,
+            Err(err) => warn!("Bookmark {} is a duplicate, {}: {}", id, url, err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Sets the keys again of the bookmarks whose key isn't that of their url, e.g. after editing
+/// the database by hand or changing `url_key`, returning how many were set.
+pub(crate) async fn rebuild_url_keys(conn: &mut SqliteConnection) -> Result<u64, sqlx::Error> {
+    let posts: Vec<(PostID, String, Option<String>)> =
+        sqlx::query_as("SELECT id, url, url_key FROM posts")
+            .fetch_all(&mut *conn)
+            .await?;
+    // cleared first, a key can move from one bookmark to another
+    for (id, url, key) in posts {
+        if key.is_some_and(|key| key != url_key(&url)) {
+            sqlx::query("UPDATE posts SET url_key = NULL WHERE id = $1")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+    fill_url_keys(conn).await
+}
+
+// SQLite date modifier for the start of the modified period
+fn modified_since(modified: &str) -> Option<&'static str> {
+    match modified {
+        "today" => Some("start of day"),
+        "week" => Some("-7 days"),
+        "month" => Some("-30 days"),
+        "year" => Some("-365 days"),
+        _ => None,
+    }
+}
+
+fn push_where(sql: &mut QueryBuilder<'_, sqlx::Sqlite>, have_where_clause: &mut bool) {
+    sql.push(if *have_where_clause {
+        " AND "
+    } else {
+        " WHERE "
+    });
+    *have_where_clause = true;
+}
+
+// Matches posts having all (or any) of the tags
+fn push_tag_filter(sql: &mut QueryBuilder<'_, sqlx::Sqlite>, mut tags: Vec<String>, all: bool) {
+    // with the tags unique, a post has them all when it has as many post_tag rows as there are
+    // tags, which is cheaper than counting distinct tags
+    tags.sort_unstable();
+    tags.dedup();
+    let count = i64::try_from(tags.len()).unwrap_or_default();
+
+    sql.push(
         r"
-            SELECT posts.*, group_concat(tags.name) as tag_names
-                FROM posts
-                LEFT OUTER JOIN post_tag ON (posts.id = post_tag.post_id)
-                LEFT OUTER JOIN tags ON (tags.id = post_tag.tag_id)
-            ",
+            posts.id IN (
+                SELECT post_tag.post_id
+                    FROM post_tag
+                    JOIN tags ON (tags.id = post_tag.tag_id)
+                    WHERE tags.name IN (",
+    );
+    let mut separated = sql.separated(", ");
+    for tag in tags {
+        separated.push_bind(tag);
+    }
+    sql.push(")");
+
+    if all {
+        sql.push(" GROUP BY post_tag.post_id HAVING COUNT(*) = ");
+        sql.push_bind(count);
+    }
+    sql.push(")");
+}
+
+// Like push_tag_filter, but smart tags match the posts matching their query
+fn push_tags(
+    sql: &mut QueryBuilder<'_, sqlx::Sqlite>,
+    tags: Vec<String>,
+    all: bool,
+    smart_tags: &HashMap<String, BookmarkQuery>,
+) {
+    let (smart, tags): (Vec<String>, Vec<String>) = tags
+        .into_iter()
+        .partition(|tag| smart_tags.contains_key(tag));
+
+    sql.push("(");
+    let mut first = true;
+    if !tags.is_empty() {
+        push_tag_filter(sql, tags, all);
+        first = false;
+    }
+    for tag in smart {
+        if !first {
+            sql.push(if all { " AND " } else { " OR " });
+        }
+        first = false;
+        sql.push("posts.id IN (SELECT posts.id FROM posts");
+        push_filters(sql, &smart_tags[&tag]);
+        sql.push(")");
+    }
+    sql.push(")");
+}
+
+// Matches posts with the words in the full text search, or in their large notes
+fn push_text(sql: &mut QueryBuilder<'_, sqlx::Sqlite>, text: String) {
+    sql.push(
+        r"
+            posts.id IN (
+                SELECT rowid
+                    FROM posts_fts
+                    WHERE posts_fts
+                        MATCH ",
+    );
+    sql.push_bind(text.clone());
+    sql.push(
+        r"
+                UNION
+                SELECT large_posts.id
+                    FROM large_notes_fts
+                    JOIN large_notes ON large_notes.rowid = large_notes_fts.rowid
+                    JOIN posts AS large_posts ON large_posts.uuid = large_notes.uuid
+                    WHERE large_notes_fts
+                        MATCH ",
     );
+    sql.push_bind(text);
+    sql.push(")");
+}
+
+fn push_search_expr(
+    sql: &mut QueryBuilder<'_, sqlx::Sqlite>,
+    expression: SearchExpr,
+    smart_tags: &HashMap<String, BookmarkQuery>,
+) {
+    let (terms, separator) = match expression {
+        SearchExpr::Tags(tags) => return push_tags(sql, tags, false, smart_tags),
+        SearchExpr::Text(text) => return push_text(sql, text),
+        SearchExpr::And(terms) => (terms, " AND "),
+        SearchExpr::Or(terms) => (terms, " OR "),
+    };
+    if terms.is_empty() {
+        // nothing to match, e.g. ()
+        sql.push("1");
+        return;
+    }
+    sql.push("(");
+    for (i, term) in terms.into_iter().enumerate() {
+        if i > 0 {
+            sql.push(separator);
+        }
+        push_search_expr(sql, term, smart_tags);
+    }
+    sql.push(")");
+}
 
-    let search_query: SearchQuery;
+/// Timestamp for `added_before`, `added_since` and the tags' `unused_since`, either a number of days ago like
+/// 90d or a date.
+pub(crate) fn timestamp_before(before: &str) -> Option<i64> {
+    if let Some(days) = before.strip_suffix('d') {
+        let days: i64 = days.parse().ok()?;
+        return Some(Utc::now().timestamp() - days * 24 * 60 * 60);
+    }
+    DateTime::parse_from_rfc3339(before)
+        .ok()
+        .map(|date| date.timestamp())
+}
+
+/// Adds the WHERE clause for the filters in the query
+pub(crate) fn push_filters(sql: &mut QueryBuilder<'_, sqlx::Sqlite>, query: &BookmarkQuery) {
     let mut have_where_clause = false;
-    if let Some(q) = query.q {
-        search_query = parse_search(&q);
-
-        if !search_query.tag_names.is_empty() {
-            have_where_clause = true;
-            sql.push("WHERE posts.id IN (");
-            sql.push(
-                r"
-                    SELECT post_id
-                        FROM post_tag
-                        WHERE tag_id IN (
-                            SELECT id
-                            FROM tags
-                            WHERE ",
-            );
-            let mut first = true;
-            for tag in &search_query.tag_names {
-                if !first {
-                    sql.push(" OR ");
-                }
-                first = false;
-                sql.push(" name = ");
-                sql.push_bind(tag);
+    if let Some(q) = &query.q {
+        let search_query = parse_search(q);
+
+        if let Some(expression) = search_query.expression {
+            push_where(sql, &mut have_where_clause);
+            push_search_expr(sql, expression, &query.smart_tags);
+        } else {
+            if !search_query.tag_names.is_empty() {
+                push_where(sql, &mut have_where_clause);
+                push_tags(sql, search_query.tag_names, false, &query.smart_tags);
             }
-            sql.push(")");
-            if search_query.text.is_empty() {
-                sql.push(")");
+
+            if !search_query.text.is_empty() {
+                push_where(sql, &mut have_where_clause);
+                push_text(sql, search_query.text.join(" "));
             }
         }
+    }
 
-        if !search_query.text.is_empty() {
-            have_where_clause = true;
-            if search_query.tag_names.is_empty() {
-                sql.push("WHERE posts.id IN (");
-            } else {
-                sql.push(" INTERSECT ");
-            }
-            sql.push(
-                r"
-                    SELECT rowid
-                        FROM posts_fts
-                        WHERE posts_fts
-                            MATCH ",
-            );
-            sql.push_bind(search_query.text.join(" "));
-            sql.push(")");
+    if query.unread.as_deref() == Some("yes") {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.unread = 1");
+    }
+
+    if query.shared.as_deref() == Some("yes") {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.shared = 1");
+    }
+
+    if let Some(url) = &query.url {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.url IN (");
+        let mut separated = sql.separated(", ");
+        for variant in url_variants(url) {
+            separated.push_bind(variant);
         }
+        sql.push(")");
     }
 
-    if unread == "yes" {
-        sql.push(format!(
-            " {} posts.unread = 1",
-            if have_where_clause { "AND" } else { "WHERE" }
-        ));
+    if let Some(source) = &query.source {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.source = ");
+        sql.push_bind(source.clone());
     }
 
-    sql.push(
-        r"
-                GROUP BY posts.id
-                ORDER BY posts.date_added DESC, posts.id DESC
-                ",
-    );
+    if let Some(since) = query.modified.as_deref().and_then(modified_since) {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.date_modified >= unixepoch('now', ");
+        sql.push_bind(since);
+        sql.push(")");
+    }
+
+    if let Some(archived) = query.archived {
+        push_where(sql, &mut have_where_clause);
+        sql.push("COALESCE(posts.is_archived, 0) = ");
+        sql.push_bind(archived);
+    }
+
+    if let Some(before) = query.added_before.as_deref().and_then(timestamp_before) {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.date_added < ");
+        sql.push_bind(before);
+    }
+
+    if let Some(since) = query.added_since.as_deref().and_then(timestamp_before) {
+        push_where(sql, &mut have_where_clause);
+        sql.push("posts.date_added >= ");
+        sql.push_bind(since);
+    }
+
+    if !query.tag.is_empty() {
+        push_where(sql, &mut have_where_clause);
+        push_tags(sql, query.tag.clone(), true, &query.smart_tags);
+    }
+
+    let tag_any: Vec<String> = query
+        .tag_any
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect();
+    if !tag_any.is_empty() {
+        push_where(sql, &mut have_where_clause);
+        push_tags(sql, tag_any, false, &query.smart_tags);
+    }
+}
+
+// Number of bookmarks matching the query, disregarding limit and offset
+#[instrument(level = "debug", name = "db.count_bookmarks", skip_all, fields(count))]
+pub(crate) async fn count_bookmarks(pool: &SqlitePool, query: &BookmarkQuery) -> i64 {
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> =
+        QueryBuilder::new("SELECT COUNT(*) AS count FROM posts");
+
+    push_filters(&mut sql, query);
+
+    match sql.build().fetch_one(pool).await {
+        Ok(row) => {
+            let count = row.get::<i64, _>("count");
+            Span::current().record("count", count);
+            count
+        }
+        Err(err) => {
+            error!("Failed to count posts: {}", err);
+            0
+        }
+    }
+}
+
+#[instrument(level = "debug", name = "db.get_bookmarks", skip_all, fields(rows))]
+pub(crate) async fn get_bookmarks(
+    pool: &SqlitePool,
+    query: BookmarkQuery,
+) -> Vec<BookmarkResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(SELECT_BOOKMARKS);
+
+    push_filters(&mut sql, &query);
+
+    sql.push(" GROUP BY posts.id ORDER BY ");
+    // the title collation is registered in setup_db, see collation.rs
+    sql.push(match query.sort.as_deref() {
+        Some("title") => "posts.title COLLATE title, posts.id",
+        Some("-title") => "posts.title COLLATE title DESC, posts.id DESC",
+        _ => "posts.date_added DESC, posts.id DESC",
+    });
 
     if limit > 0 {
         sql.push(" LIMIT ");
         sql.push_bind(limit);
     }
     if offset > 0 {
+        // OFFSET needs a LIMIT, -1 is no limit
+        if limit == 0 {
+            sql.push(" LIMIT -1");
+        }
         sql.push(" OFFSET ");
         sql.push_bind(offset);
     }
 
     match sql.build_query_as::<BookmarkDb>().fetch_all(pool).await {
         Ok(rows) => {
+            Span::current().record("rows", rows.len());
             let mut posts = vec![];
             for row in rows {
                 let post: BookmarkResponse = row.into();
                 posts.push(post);
             }
+            if query.include.as_deref() == Some("notes") {
+                notes::load(pool, &mut posts).await;
+            }
             posts
         }
         Err(err) => {
@@ -335,57 +1030,566 @@ pub(crate) async fn get_bookmarks(
 
 async fn handle_get_bookmarks(
     State(state): State<Arc<AppState>>,
-    Query(query): Query<BookmarkQuery>,
-) -> Result<Json<BookmarksResponse>, StatusCode> {
-    let bookmarks = get_bookmarks(&state.pool, query).await;
-    Ok(Json(BookmarksResponse {
-        count: bookmarks.len(),
-        results: bookmarks,
-    }))
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(mut query): Query<BookmarkQuery>,
+) -> Response {
+    query.tag = tags_from_query(raw_query.as_deref());
+    query.archived = Some(false);
+    list_bookmarks(&state, &uri, &headers, query).await
 }
 
-async fn handle_get_bookmark(
+async fn handle_get_archived_bookmarks(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<PostID>,
-) -> Result<Json<BookmarkResponse>, StatusCode> {
-    match get_bookmark(
-        state,
-        LookupType {
-            id: Some(id),
-            url: None,
-        },
-    )
-    .await
-    {
-        Some(post) => Ok(Json(post)),
-        None => Err(StatusCode::NOT_FOUND),
-    }
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(mut query): Query<BookmarkQuery>,
+) -> Response {
+    query.tag = tags_from_query(raw_query.as_deref());
+    query.archived = Some(true);
+    list_bookmarks(&state, &uri, &headers, query).await
 }
 
-async fn handle_delete_bookmark(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<PostID>,
-) -> Result<(), StatusCode> {
-    match sqlx::query("DELETE from posts WHERE id=$1")
-        .bind(id)
-        .execute(&state.pool)
-        .await
+async fn list_bookmarks(
+    state: &AppState,
+    uri: &Uri,
+    headers: &HeaderMap,
+    mut query: BookmarkQuery,
+) -> Response {
+    query.smart_tags = smart_tags::get_smart_tags(&state.pool).await;
+
+    // the bookmark is saved with the rewritten url
+    if let Some(url) = query
+        .url
+        .as_deref()
+        .and_then(|url| state.rewrite_rules.apply(url))
     {
-        Ok(_) => {
-            info!("deleted bookmark: {}", id);
-            Ok(())
-        }
-        Err(err) => {
-            // probably the tag was already added to the post
-            error!("Failed to delete bookmark: {} ({})", id, err);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        query.url = Some(url);
+    }
+
+    let search_kind =
+        query.q.as_deref().map(parse_search).and_then(|search| {
+            SearchKind::of(!search.tag_names.is_empty(), !search.text.is_empty())
+        });
+    let started = Instant::now();
+
+    let mut bookmarks = get_bookmarks(&state.pool, query.clone()).await;
+
+    if let Some(kind) = search_kind {
+        state.metrics.observe_search(kind, started.elapsed());
+    }
+
+    // fall back to the closest matching words if a search doesn't give any results
+    let mut did_you_mean = None;
+    if bookmarks.is_empty() {
+        if let Some(q) = &query.q {
+            if let Some(suggestion) = fuzzy::suggest(&state.pool, q).await {
+                bookmarks = get_bookmarks(
+                    &state.pool,
+                    BookmarkQuery {
+                        q: Some(suggestion.clone()),
+                        ..query.clone()
+                    },
+                )
+                .await;
+                for bookmark in &mut bookmarks {
+                    bookmark.fuzzy = true;
+                }
+                did_you_mean = Some(suggestion);
+            }
         }
     }
-}
 
-async fn add_tag_to_post(
-    pool: &SqlitePool,
-    post_id: PostID,
+    let total = if did_you_mean.is_some() {
+        bookmarks.len()
+    } else {
+        usize::try_from(count_bookmarks(&state.pool, &query).await).unwrap_or_default()
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = query.offset.unwrap_or_default();
+    let mut response = match render_bookmarks(&Format::from_headers(headers), &bookmarks) {
+        Some(response) => response,
+        None => Json(BookmarksResponse {
+            count: bookmarks.len(),
+            total,
+            limit,
+            offset,
+            results: bookmarks,
+            did_you_mean,
+        })
+        .into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(X_TOTAL_COUNT, HeaderValue::from(total));
+    if let Some(links) = pagination_links(uri, limit, offset, total) {
+        response.headers_mut().insert(header::LINK, links);
+    }
+    response
+}
+
+/// The `Link` header with the first, previous, next and last pages of a listing, as the
+/// request's path and query with another offset. None without a limit, i.e. a single page.
+pub(crate) fn pagination_links(
+    uri: &Uri,
+    limit: u32,
+    offset: u32,
+    total: usize,
+) -> Option<HeaderValue> {
+    if limit == 0 {
+        return None;
+    }
+    let params: Vec<(String, String)> =
+        url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+            .into_owned()
+            .filter(|(key, _)| key != "limit" && key != "offset")
+            .collect();
+    let link = |offset: u32, rel: &str| {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&params)
+            .append_pair("limit", &limit.to_string())
+            .append_pair("offset", &offset.to_string())
+            .finish();
+        format!("<{}?{}>; rel=\"{}\"", uri.path(), query, rel)
+    };
+
+    let total = u32::try_from(total).unwrap_or(u32::MAX);
+    let last = total.saturating_sub(1) / limit * limit;
+    let mut links = vec![link(0, "first")];
+    if offset > 0 {
+        links.push(link(offset.saturating_sub(limit), "prev"));
+    }
+    if offset.saturating_add(limit) < total {
+        links.push(link(offset + limit, "next"));
+    }
+    links.push(link(last, "last"));
+    HeaderValue::from_str(&links.join(", ")).ok()
+}
+
+#[derive(sqlx::FromRow, Deserialize, Serialize, Debug)]
+struct CountsResponse {
+    total: i64,
+    unread: i64,
+    archived: i64,
+    untagged: i64,
+    // pinrs doesn't check the links, always 0, kept for clients expecting it
+    broken: i64,
+}
+
+async fn handle_get_counts(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CountsResponse>, StatusCode> {
+    match sqlx::query_as::<_, CountsResponse>(
+        r"
+            SELECT COUNT(*) AS total,
+                COALESCE(SUM(posts.unread = 1), 0) AS unread,
+                COALESCE(SUM(COALESCE(posts.is_archived, 0) = 1), 0) AS archived,
+                COALESCE(SUM(NOT EXISTS (SELECT 1 FROM post_tag WHERE post_tag.post_id = posts.id)), 0) AS untagged,
+                0 AS broken
+            FROM posts
+        ",
+    )
+    .fetch_one(&state.pool)
+    .await
+    {
+        Ok(counts) => Ok(Json(counts)),
+        Err(err) => {
+            error!("Failed to get counts: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct OnThisDayQuery {
+    // YYYY-MM-DD, today unless given
+    date: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct OnThisDayResponse {
+    count: usize,
+    results: Vec<BookmarkResponse>,
+}
+
+// The bookmarks added on the same day of the year in the years before, newest first. Like the
+// listing, the archived bookmarks are left out.
+async fn handle_get_on_this_day(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OnThisDayQuery>,
+) -> Result<Json<OnThisDayResponse>, StatusCode> {
+    let date = match query.date {
+        Some(date) => {
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|_| StatusCode::BAD_REQUEST)?
+        }
+        None => Utc::now().date_naive(),
+    };
+
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(SELECT_BOOKMARKS);
+    sql.push(" WHERE strftime('%m-%d', posts.date_added, 'unixepoch') = ");
+    sql.push_bind(date.format("%m-%d").to_string());
+    sql.push(" AND posts.date_added < unixepoch(");
+    sql.push_bind(date.format("%Y-01-01").to_string());
+    sql.push(") AND COALESCE(posts.is_archived, 0) = 0");
+    sql.push(" GROUP BY posts.id ORDER BY posts.date_added DESC, posts.id DESC");
+
+    match sql
+        .build_query_as::<BookmarkDb>()
+        .fetch_all(&state.pool)
+        .await
+    {
+        Ok(rows) => {
+            let bookmarks: Vec<BookmarkResponse> = rows.into_iter().map(Into::into).collect();
+            Ok(Json(OnThisDayResponse {
+                count: bookmarks.len(),
+                results: bookmarks,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to get bookmarks on this day: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct CalendarQuery {
+    // this year unless given
+    year: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, sqlx::FromRow)]
+struct CalendarDay {
+    // YYYY-MM-DD
+    date: String,
+    count: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct CalendarResponse {
+    year: i32,
+    total: i64,
+    // only the days with bookmarks added
+    days: Vec<CalendarDay>,
+}
+
+// The number of bookmarks added each day of the year, archived or not, e.g. for a heatmap
+async fn handle_get_calendar(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CalendarQuery>,
+) -> Result<Json<CalendarResponse>, StatusCode> {
+    let year = query.year.unwrap_or_else(|| Utc::now().year());
+    if !(0..=9999).contains(&year) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match sqlx::query_as::<_, CalendarDay>(
+        r"
+            SELECT strftime('%Y-%m-%d', date_added, 'unixepoch') AS date, COUNT(*) AS count
+                FROM posts
+                WHERE date_added >= unixepoch($1) AND date_added < unixepoch($1, '+1 year')
+                GROUP BY date
+                ORDER BY date
+        ",
+    )
+    .bind(format!("{year:04}-01-01"))
+    .fetch_all(&state.pool)
+    .await
+    {
+        Ok(days) => Ok(Json(CalendarResponse {
+            year,
+            total: days.iter().map(|day| day.count).sum(),
+            days,
+        })),
+        Err(err) => {
+            error!("Failed to get the calendar: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct FacetCount {
+    name: String,
+    count: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct FacetsResponse {
+    total: i64,
+    unread: i64,
+    read: i64,
+    tags: Vec<FacetCount>,
+    domains: Vec<FacetCount>,
+}
+
+// Counts per tag, domain and unread state of the bookmarks matching the same filters as the
+// listing, for drawing facets without a request per count
+async fn handle_get_facets(
+    State(state): State<Arc<AppState>>,
+    RawQuery(raw_query): RawQuery,
+    Query(mut query): Query<BookmarkQuery>,
+) -> Result<Json<FacetsResponse>, StatusCode> {
+    query.tag = tags_from_query(raw_query.as_deref());
+    query.archived.get_or_insert(false);
+    query.smart_tags = smart_tags::get_smart_tags(&state.pool).await;
+
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> =
+        QueryBuilder::new("SELECT posts.url, posts.unread FROM posts");
+    push_filters(&mut sql, &query);
+    let posts = sql
+        .build_query_as::<(String, Option<bool>)>()
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|err| {
+            error!("Failed to get facets: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(
+        r"
+            SELECT tags.name, COUNT(*)
+                FROM post_tag
+                JOIN tags ON tags.id = post_tag.tag_id
+                WHERE post_tag.post_id IN (SELECT posts.id FROM posts",
+    );
+    push_filters(&mut sql, &query);
+    sql.push(") GROUP BY tags.name ORDER BY COUNT(*) DESC, tags.name");
+    let tags = sql
+        .build_query_as::<(String, i64)>()
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|err| {
+            error!("Failed to get tag facets: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut domains: HashMap<String, i64> = HashMap::new();
+    for (url, _) in &posts {
+        if let Some(host) = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+        {
+            *domains.entry(host).or_default() += 1;
+        }
+    }
+    let mut domains: Vec<FacetCount> = domains
+        .into_iter()
+        .map(|(name, count)| FacetCount { name, count })
+        .collect();
+    domains.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+
+    let total = i64::try_from(posts.len()).unwrap_or_default();
+    let unread = i64::try_from(
+        posts
+            .iter()
+            .filter(|(_, unread)| unread.unwrap_or_default())
+            .count(),
+    )
+    .unwrap_or_default();
+
+    Ok(Json(FacetsResponse {
+        total,
+        unread,
+        read: total - unread,
+        tags: tags
+            .into_iter()
+            .map(|(name, count)| FacetCount { name, count })
+            .collect(),
+        domains,
+    }))
+}
+
+#[derive(sqlx::FromRow, Deserialize, Serialize, Debug)]
+struct NotesRevisionDb {
+    revision: i64,
+    notes: String,
+    date: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct NotesRevisionResponse {
+    revision: i64,
+    notes: String,
+    date: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct NotesRevisionsResponse {
+    count: usize,
+    results: Vec<NotesRevisionResponse>,
+}
+
+impl From<NotesRevisionDb> for NotesRevisionResponse {
+    fn from(val: NotesRevisionDb) -> Self {
+        let date = Utc.timestamp_opt(val.date, 0).unwrap();
+
+        NotesRevisionResponse {
+            revision: val.revision,
+            notes: val.notes,
+            date: date.to_rfc3339(),
+        }
+    }
+}
+
+// The revisions of the notes, numbered from 1 for the oldest
+async fn get_notes_revisions(
+    state: &AppState,
+    id: PostID,
+) -> Result<Vec<NotesRevisionDb>, StatusCode> {
+    sqlx::query_as::<_, NotesRevisionDb>(
+        r"
+            SELECT ROW_NUMBER() OVER (ORDER BY id) AS revision, notes, date
+                FROM notes_revisions
+                WHERE post_id = $1
+                ORDER BY id
+        ",
+    )
+    .bind(id)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        error!("Failed to get notes revisions: {} ({})", id, err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn get_notes(state: &AppState, id: PostID) -> Result<String, StatusCode> {
+    let internal_error = |err: sqlx::Error| {
+        error!("Failed to get notes: {} ({})", id, err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut conn = state.pool.acquire().await.map_err(internal_error)?;
+    let notes = sqlx::query_scalar::<_, Option<String>>("SELECT notes FROM posts WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(internal_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    match notes::get(&mut conn, id).await.map_err(internal_error)? {
+        Some(large) => Ok(large),
+        None => Ok(notes.unwrap_or_default()),
+    }
+}
+
+async fn handle_get_notes_revisions(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<PostID>,
+) -> Result<Json<NotesRevisionsResponse>, StatusCode> {
+    // not found for a bookmark that doesn't exist, rather than no revisions
+    get_notes(&state, id).await?;
+
+    let revisions: Vec<NotesRevisionResponse> = get_notes_revisions(&state, id)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(Json(NotesRevisionsResponse {
+        count: revisions.len(),
+        results: revisions,
+    }))
+}
+
+// Unified diff from the revision to the current notes
+async fn handle_get_notes_diff(
+    State(state): State<Arc<AppState>>,
+    Path((id, revision)): Path<(PostID, i64)>,
+) -> Result<String, StatusCode> {
+    let current = get_notes(&state, id).await?;
+    let Some(old) = get_notes_revisions(&state, id)
+        .await?
+        .into_iter()
+        .find(|old| old.revision == revision)
+    else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(TextDiff::from_lines(&old.notes, &current)
+        .unified_diff()
+        .header(&format!("revision {revision}"), "current")
+        .to_string())
+}
+
+async fn handle_get_bookmark(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<PostID>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    match get_bookmark(
+        state,
+        LookupType {
+            id: Some(id),
+            url: None,
+            uuid: None,
+            by_key: false,
+        },
+    )
+    .await
+    {
+        Some(post) => Ok(Json(post)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn handle_get_bookmark_by_uuid(
+    State(state): State<Arc<AppState>>,
+    Path(uuid): Path<String>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    match get_bookmark(
+        state,
+        LookupType {
+            id: None,
+            url: None,
+            uuid: Some(&uuid.to_lowercase()),
+            by_key: false,
+        },
+    )
+    .await
+    {
+        Some(post) => Ok(Json(post)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Deletes the bookmark, keeping it in the graveyard
+pub(crate) async fn delete_bookmark(pool: &SqlitePool, id: PostID) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    graveyard::bury(&mut tx, id).await?;
+    let result = sqlx::query("DELETE from posts WHERE id=$1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+async fn handle_delete_bookmark(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<PostID>,
+) -> Result<(), StatusCode> {
+    match delete_bookmark(&state.pool, id).await {
+        Ok(0) => {
+            info!("bookmark to delete not found: {}", id);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Ok(_) => {
+            info!("deleted bookmark: {}", id);
+            Ok(())
+        }
+        Err(err) => {
+            // probably the tag was already added to the post
+            error!("Failed to delete bookmark: {} ({})", id, err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[instrument(level = "debug", name = "db.add_tag_to_post", skip(pool))]
+async fn add_tag_to_post(
+    pool: &SqlitePool,
+    post_id: PostID,
     tag_id: TagID,
 ) -> Result<(), StatusCode> {
     match sqlx::query("INSERT INTO post_tag (post_id, tag_id) VALUES ($1, $2)")
@@ -409,6 +1613,11 @@ async fn add_tag_to_post(
     }
 }
 
+#[instrument(
+    level = "debug",
+    name = "db.update_tags_for_post",
+    skip(state, new_tags)
+)]
 async fn update_tags_for_post(state: &AppState, post_id: PostID, new_tags: Vec<String>) {
     let mut old_tag_ids = sqlx::query("SELECT tag_id FROM post_tag WHERE post_id = $1")
         .bind(post_id)
@@ -493,31 +1702,88 @@ async fn update_tags_for_post(state: &AppState, post_id: PostID, new_tags: Vec<S
 async fn handle_put_bookmark(
     State(state): State<Arc<AppState>>,
     Path(id): Path<PostID>,
-    Json(payload): Json<BookmarkRequest>,
-) -> Result<Json<BookmarkResponse>, StatusCode> {
-    // add post
-    let _post = match sqlx::query(
+    Json(mut payload): Json<BookmarkRequest>,
+) -> Result<Json<BookmarkChangeResponse>, StatusCode> {
+    if !has_valid_tags(&payload) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if notes_too_large(&state, &payload) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    rewrite_url(&state.rewrite_rules, &mut payload);
+
+    let lookup = LookupType {
+        id: Some(id),
+        url: None,
+        uuid: None,
+        by_key: false,
+    };
+    let Some(before) = get_bookmark(state.clone(), lookup).await else {
+        info!("bookmark to update not found: {}", id);
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    // notes left out are kept, as are large notes given empty, like listings have them
+    let notes = payload
+        .notes
+        .filter(|notes| !notes.is_empty() || !notes::is_large(before.notes.as_deref()));
+
+    // update post, with the large notes stored first for the revisions, see notes.rs
+    let mut tx = state.pool.begin().await.map_err(|err| {
+        error!("Failed to update bookmark: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if notes.is_some() {
+        if let Err(err) = notes::store(&mut tx, id, notes.as_deref()).await {
+            error!("Failed to store the notes of bookmark: {} ({})", id, err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    match sqlx::query(
         r"
             UPDATE posts
-                SET (url, title, unread, description, notes, date_modified) = ($1, $2, $3, $4, $5, unixepoch())
-                WHERE posts.id = $6
+                SET (url, title, unread, shared, description, notes, private_notes, is_archived, original_url, date_modified, url_key) = ($1, $2, $3, COALESCE($4, shared), $5, COALESCE($6, notes), COALESCE($7, private_notes), COALESCE($8, is_archived), CASE WHEN $9 IS NOT NULL THEN $9 WHEN url = $1 THEN original_url END, unixepoch(), $11)
+                WHERE posts.id = $10
         ",
     )
-    .bind(payload.url)
+    .bind(&payload.url)
     .bind(payload.title)
     .bind(payload.unread.unwrap_or_default())
+    // left out by older clients, kept like the private notes
+    .bind(payload.shared)
     .bind(payload.description.unwrap_or_default())
-    .bind(payload.notes.unwrap_or_default())
+    .bind(inline_notes(notes.as_deref()))
+    // linkding clients don't know about private notes, keep them if not given
+    .bind(payload.private_notes)
+    .bind(payload.is_archived)
+    // an unchanged url keeps its original, e.g. when a client sends back the rewritten url
+    .bind(payload.original_url)
     .bind(id)
-    .execute(&state.pool)
+    .bind(url_key(&payload.url))
+    .execute(&mut *tx)
     .await
     {
-        Ok(post) => Ok(post),
+        Ok(result) if result.rows_affected() == 0 => {
+            info!("bookmark to update not found: {}", id);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        Ok(_) => {}
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            info!("Not updating bookmark {}, the url is already saved: {}", id, err);
+            return Err(StatusCode::CONFLICT);
+        }
         Err(err) => {
-            error!("Failed to add bookmark: {}", err);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            error!("Failed to update bookmark: {}", err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
-    };
+    }
+
+    if let Err(err) = tx.commit().await {
+        error!("Failed to update bookmark: {}", err);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
 
     update_tags_for_post(&state, id, payload.tag_names.unwrap_or_default()).await;
 
@@ -526,49 +1792,213 @@ async fn handle_put_bookmark(
         LookupType {
             id: Some(id),
             url: None,
+            uuid: None,
+            by_key: false,
         },
     )
     .await
     {
-        Some(post) => Ok(Json(post)),
+        Some(post) => Ok(Json(BookmarkChangeResponse::new(&before, post))),
         None => Err(StatusCode::NOT_FOUND),
     }
 }
 
-pub(crate) async fn add_bookmark(
-    pool: &SqlitePool,
-    bookmark: BookmarkRequest,
-) -> Result<PostID, StatusCode> {
-    let now = i64::try_from(
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
-    )
-    .unwrap_or_default();
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub(crate) struct TagsChangeRequest {
+    #[serde(default)]
+    pub(crate) add: Vec<String>,
+    #[serde(default)]
+    pub(crate) remove: Vec<String>,
+}
 
-    // add post
-    let post = match sqlx::query("INSERT INTO posts (url, title, unread, description, notes, date_added, date_modified) VALUES ($1, $2, $3, $4, $5, $6, $7)")
-        .bind(bookmark.url)
-        .bind(bookmark.title)
-        .bind(bookmark.unread)
-        .bind(bookmark.description)
-        .bind(bookmark.notes)
-        .bind(bookmark.date_added.unwrap_or(now))
-        .bind(bookmark.date_modified.unwrap_or(now))
-        .execute(pool)
-        .await
-    {
-        Ok(post) => post,
-        Err(err) => {
-            error!("Failed to add bookmark: {}", err);
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    };
+#[instrument(level = "debug", name = "db.change_tags", skip(pool, change))]
+async fn change_tags(
+    pool: &SqlitePool,
+    id: PostID,
+    change: &TagsChangeRequest,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
 
-    let post_id = post.last_insert_rowid() as PostID;
+    // written first to take the write lock, the changes are then made on the current tags
+    let updated = sqlx::query("UPDATE posts SET date_modified = unixepoch() WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
 
-    for tag in bookmark.tag_names.unwrap_or_default() {
+    for tag in &change.add {
+        sqlx::query("INSERT OR IGNORE INTO tags (name, date_added) VALUES ($1, unixepoch())")
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO post_tag (post_id, tag_id) SELECT $1, id FROM tags WHERE name = $2",
+        )
+        .bind(id)
+        .bind(tag)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for tag in &change.remove {
+        sqlx::query(
+            "DELETE FROM post_tag WHERE post_id = $1 AND tag_id IN (SELECT id FROM tags WHERE name = $2)",
+        )
+        .bind(id)
+        .bind(tag)
+        .execute(&mut *tx)
+        .await?;
+        // as in update_tags_for_post, a tag without bookmarks is removed
+        sqlx::query(
+            "DELETE FROM tags WHERE name = $1 AND NOT EXISTS (SELECT 1 FROM post_tag WHERE tag_id = tags.id)",
+        )
+        .bind(tag)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+// Adds and removes tags relative to the current ones, unlike PUT replacing all of them. Two
+// clients tagging the same bookmark then don't undo each other's changes.
+async fn handle_post_bookmark_tags(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<PostID>,
+    Json(change): Json<TagsChangeRequest>,
+) -> Result<Json<BookmarkResponse>, StatusCode> {
+    if !change
+        .add
+        .iter()
+        .chain(&change.remove)
+        .all(|tag| is_valid_tag_name(tag))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match change_tags(&state.pool, id, &change).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            error!("Failed to change tags of bookmark: {} ({})", id, err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    get_bookmark(
+        state,
+        LookupType {
+            id: Some(id),
+            url: None,
+            uuid: None,
+            by_key: false,
+        },
+    )
+    .await
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+fn has_valid_tags(bookmark: &BookmarkRequest) -> bool {
+    bookmark
+        .tag_names
+        .iter()
+        .flatten()
+        .all(|tag| is_valid_tag_name(tag))
+}
+
+// Longer than PINRS_MAX_NOTES_SIZE
+fn notes_too_large(state: &AppState, bookmark: &BookmarkRequest) -> bool {
+    state.max_notes_size.is_some_and(|max| {
+        bookmark
+            .notes
+            .as_ref()
+            .is_some_and(|notes| notes.len() > max)
+    })
+}
+
+// The notes kept in the bookmark itself, empty if they're large, see notes.rs
+fn inline_notes(notes: Option<&str>) -> Option<&str> {
+    notes.map(|notes| {
+        if notes::is_large(Some(notes)) {
+            ""
+        } else {
+            notes
+        }
+    })
+}
+
+#[instrument(level = "debug", name = "db.add_bookmark", skip_all, fields(post_id))]
+pub(crate) async fn add_bookmark(
+    pool: &SqlitePool,
+    bookmark: BookmarkRequest,
+) -> Result<PostID, StatusCode> {
+    if !has_valid_tags(&bookmark) {
+        error!("Invalid tags for bookmark: {:?}", bookmark.tag_names);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = i64::try_from(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    )
+    .unwrap_or_default();
+
+    // add post
+    let key = url_key(&bookmark.url);
+    let post = match sqlx::query("INSERT INTO posts (url, title, unread, shared, description, notes, private_notes, is_archived, original_url, source, referrer, date_added, date_modified, url_key) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)")
+        .bind(bookmark.url)
+        .bind(bookmark.title)
+        .bind(bookmark.unread)
+        .bind(bookmark.shared.unwrap_or_default())
+        .bind(bookmark.description)
+        .bind(inline_notes(bookmark.notes.as_deref()))
+        .bind(bookmark.private_notes)
+        .bind(bookmark.is_archived.unwrap_or_default())
+        .bind(bookmark.original_url)
+        .bind(bookmark.source)
+        .bind(bookmark.referrer)
+        .bind(bookmark.date_added.unwrap_or(now))
+        .bind(bookmark.date_modified.unwrap_or(now))
+        .bind(key)
+        .execute(pool)
+        .await
+    {
+        Ok(post) => post,
+        // the url, or its key, is already saved
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            info!("Bookmark already saved: {}", err);
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(err) => {
+            error!("Failed to add bookmark: {}", err);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    let post_id = post.last_insert_rowid() as PostID;
+    Span::current().record("post_id", post_id);
+
+    if notes::is_large(bookmark.notes.as_deref()) {
+        let stored = match pool.acquire().await {
+            Ok(mut conn) => notes::store(&mut conn, post_id, bookmark.notes.as_deref()).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = stored {
+            error!(
+                "Failed to store the notes of bookmark: {} ({})",
+                post_id, err
+            );
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    for tag in bookmark.tag_names.unwrap_or_default() {
         let _ = match sqlx::query_as::<_, TagDb>("SELECT * FROM tags WHERE name = $1")
             .bind(&tag)
             .fetch_all(pool)
@@ -609,13 +2039,146 @@ pub(crate) async fn add_bookmark(
     Ok(post_id)
 }
 
+// Applies the rewrite rules to the url, keeping the url as given in original_url
+fn rewrite_url(rules: &RewriteRules, bookmark: &mut BookmarkRequest) {
+    if let Some(url) = rules.apply(&bookmark.url) {
+        bookmark.original_url = Some(std::mem::replace(&mut bookmark.url, url));
+    }
+}
+
+// Adds the selected text to the top of the notes as a markdown quote
+fn quote_selected_text(bookmark: &mut BookmarkRequest) {
+    let Some(text) = bookmark.selected_text.take() else {
+        return;
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        return;
+    }
+
+    let quote = text
+        .lines()
+        .map(|line| format!("> {line}").trim_end().to_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+    bookmark.notes = Some(match bookmark.notes.as_deref().map(str::trim) {
+        Some(notes) if !notes.is_empty() => format!("{quote}\n\n{notes}"),
+        _ => quote,
+    });
+}
+
 async fn handle_post_bookmark(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<BookmarkRequest>,
 ) -> impl IntoResponse {
+    create_bookmark(state, payload).await
+}
+
+// Only the url is needed, see templates.rs for the template
+#[derive(Deserialize, Debug)]
+struct QuickBookmarkRequest {
+    url: String,
+    #[serde(default)]
+    title: String,
+    description: Option<String>,
+    notes: Option<String>,
+    #[serde(default)]
+    tag_names: Vec<String>,
+    unread: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+struct QuickQuery {
+    template: Option<String>,
+}
+
+async fn handle_post_quick_bookmark(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<QuickQuery>,
+    Json(payload): Json<QuickBookmarkRequest>,
+) -> (
+    StatusCode,
+    Extension<AffectedIds>,
+    Result<Json<BookmarkResponse>, String>,
+) {
+    let mut bookmark = BookmarkRequest {
+        url: payload.url,
+        title: payload.title,
+        description: payload.description,
+        notes: payload.notes,
+        tag_names: Some(payload.tag_names),
+        unread: payload.unread,
+        ..Default::default()
+    };
+
+    if let Some(name) = query.template {
+        match templates::get_template(&state.pool, &name).await {
+            Ok(Some(template)) => template.apply(&mut bookmark),
+            Ok(None) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Extension(AffectedIds(vec![])),
+                    Err(format!("No template named {name}")),
+                )
+            }
+            Err(status) => {
+                return (
+                    status,
+                    Extension(AffectedIds(vec![])),
+                    Err("Failed to get the template".to_string()),
+                )
+            }
+        }
+    }
+
+    create_bookmark(state, bookmark).await
+}
+
+async fn create_bookmark(
+    state: Arc<AppState>,
+    mut payload: BookmarkRequest,
+) -> (
+    StatusCode,
+    Extension<AffectedIds>,
+    Result<Json<BookmarkResponse>, String>,
+) {
+    rewrite_url(&state.rewrite_rules, &mut payload);
+    quote_selected_text(&mut payload);
+    payload.source = Some("api".to_owned());
+
+    if notes_too_large(&state, &payload) {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Extension(AffectedIds(vec![])),
+            Err("The notes are too large".to_string()),
+        );
+    }
+
+    let url = payload.url.clone();
     let post_id = match add_bookmark(&state.pool, payload).await {
         Ok(post_id) => post_id,
-        Err(status) => return (StatusCode::BAD_REQUEST, Err(format!("{status}"))),
+        // with the bookmark already saved, for its id
+        Err(StatusCode::CONFLICT) => {
+            return match get_bookmark_by_url(state, &url).await {
+                Some(existing) => (
+                    StatusCode::CONFLICT,
+                    Extension(AffectedIds(vec![])),
+                    Ok(Json(existing)),
+                ),
+                None => (
+                    StatusCode::CONFLICT,
+                    Extension(AffectedIds(vec![])),
+                    Err("The bookmark is already saved".to_string()),
+                ),
+            };
+        }
+        Err(status) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Extension(AffectedIds(vec![])),
+                Err(format!("{status}")),
+            )
+        }
     };
 
     match get_bookmark(
@@ -623,23 +2186,133 @@ async fn handle_post_bookmark(
         LookupType {
             id: Some(post_id),
             url: None,
+            uuid: None,
+            by_key: false,
         },
     )
     .await
     {
-        Some(post) => (StatusCode::CREATED, Ok(Json(post))),
+        Some(post) => (
+            StatusCode::CREATED,
+            Extension(AffectedIds(vec![post_id])),
+            Ok(Json(post)),
+        ),
         None => (
             StatusCode::NOT_FOUND,
+            Extension(AffectedIds(vec![post_id])),
             Err("Failed to get the added bookmark".to_string()),
         ),
     }
 }
 
+#[derive(Deserialize, Serialize, Debug)]
+struct BookmarkUpsertResponse {
+    // false if the bookmark was already saved and has been updated
+    created: bool,
+    bookmark: BookmarkResponse,
+}
+
+/// Adds the bookmark, or updates the one already saved with the url. When updating, the title is
+/// replaced, as are the tags and the other fields given, while those left out are kept. Returns
+/// the id and whether the bookmark was added.
+pub(crate) async fn upsert_bookmark(
+    state: &Arc<AppState>,
+    mut payload: BookmarkRequest,
+) -> Result<(PostID, bool), StatusCode> {
+    if !has_valid_tags(&payload) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    rewrite_url(&state.rewrite_rules, &mut payload);
+    quote_selected_text(&mut payload);
+    if notes_too_large(state, &payload) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let Some(existing) = get_bookmark_by_url(state.clone(), &payload.url).await else {
+        payload.source = Some("api".to_owned());
+        return Ok((add_bookmark(&state.pool, payload).await?, true));
+    };
+
+    // the large notes stored first for the revisions, see notes.rs
+    let update_error = |err: sqlx::Error| {
+        error!("Failed to update bookmark: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut tx = state.pool.begin().await.map_err(update_error)?;
+    if payload.notes.is_some() {
+        if let Err(err) = notes::store(&mut tx, existing.id, payload.notes.as_deref()).await {
+            error!(
+                "Failed to store the notes of bookmark: {} ({})",
+                existing.id, err
+            );
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    sqlx::query(
+        r"
+            UPDATE posts
+                SET title = $1,
+                    description = COALESCE($2, description),
+                    notes = COALESCE($3, notes),
+                    private_notes = COALESCE($4, private_notes),
+                    unread = COALESCE($5, unread),
+                    shared = COALESCE($6, shared),
+                    is_archived = COALESCE($7, is_archived),
+                    date_modified = unixepoch()
+                WHERE id = $8
+        ",
+    )
+    .bind(payload.title)
+    .bind(payload.description)
+    .bind(inline_notes(payload.notes.as_deref()))
+    .bind(payload.private_notes)
+    .bind(payload.unread)
+    .bind(payload.shared)
+    .bind(payload.is_archived)
+    .bind(existing.id)
+    .execute(&mut *tx)
+    .await
+    .map_err(update_error)?;
+    tx.commit().await.map_err(update_error)?;
+    if let Some(tag_names) = payload.tag_names {
+        update_tags_for_post(state, existing.id, tag_names).await;
+    }
+    Ok((existing.id, false))
+}
+
+async fn handle_put_bookmark_by_url(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<BookmarkRequest>,
+) -> Result<
+    (
+        StatusCode,
+        Extension<AffectedIds>,
+        Json<BookmarkUpsertResponse>,
+    ),
+    StatusCode,
+> {
+    let (id, created) = upsert_bookmark(&state, payload).await?;
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+
+    match get_bookmark_by_id(state, id).await {
+        Some(bookmark) => Ok((
+            status,
+            Extension(AffectedIds(vec![id])),
+            Json(BookmarkUpsertResponse { created, bookmark }),
+        )),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
 /*********************************************************************/
 /******************************* TESTS *******************************/
 /*********************************************************************/
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::{
         api::handlers::{
@@ -658,17 +2331,21 @@ mod tests {
 
     const TOKEN: &str = "abc";
 
-    fn get_random_string(len: usize) -> String {
+    pub(crate) fn get_random_string(len: usize) -> String {
         let chars = "abcdefghijklmnopqrstuvwxyz";
         random_string::generate(len, chars)
     }
 
-    struct CreatedBookmark {
-        bookmark: BookmarkRequest,
-        response: Response,
+    pub(crate) struct CreatedBookmark {
+        pub(crate) bookmark: BookmarkRequest,
+        pub(crate) response: Response,
     }
 
-    async fn add_post(app: Router, tags: Option<Vec<String>>, unread: bool) -> CreatedBookmark {
+    pub(crate) async fn add_post(
+        app: Router,
+        tags: Option<Vec<String>>,
+        unread: bool,
+    ) -> CreatedBookmark {
         let url = get_random_string(5);
         let title = format!(
             "{} {} {}",
@@ -705,8 +2382,7 @@ mod tests {
             notes: Some(notes),
             unread: Some(unread),
             tag_names: Some(tag_names),
-            date_added: None,
-            date_modified: None,
+            ..Default::default()
         };
         let bookmark = serde_json::to_string(&bookmark_req).unwrap();
         //let bookmark = Json(&BookmarkRequest{url: url.to_owned(), title: title.to_owned(), description: None, notes: None, unread: Some(false), tag_names: None });
@@ -795,6 +2471,88 @@ mod tests {
         assert!(post.url == res.url && post.title == res.title);
     }
 
+    #[tokio::test]
+    async fn test_head_bookmarks() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        add_post(app.clone(), None, false).await;
+        add_post(app.clone(), None, false).await;
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("HEAD")
+                    .uri("/api/bookmarks?limit=1")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-total-count"], "2");
+        assert!(response.headers().contains_key(header::ETAG));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks?limit=1")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let etag = response.headers()[header::ETAG].clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        // detail route, with and without a matching etag
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("HEAD")
+                    .uri(format!("/api/bookmarks/{}", posts.results[0].id))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag_detail = response.headers()[header::ETAG].clone();
+        assert!(etag_detail != etag);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/bookmarks/{}", posts.results[0].id))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::IF_NONE_MATCH, etag_detail)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
     #[tokio::test]
     async fn test_check_post() {
         let pool = setup_db(true).await;
@@ -902,8 +2660,7 @@ mod tests {
             notes: None,
             unread: Some(false),
             tag_names: Some(vec![expected_tag_names[1].clone(), new_tag.clone()]),
-            date_added: None,
-            date_modified: None,
+            ..Default::default()
         };
         let bookmark_json = serde_json::to_string(&bookmark_req).unwrap();
         // update bookmark
@@ -930,6 +2687,14 @@ mod tests {
 
         assert!(res.tag_names.contains(&expected_tag_names[1]));
         assert!(res.tag_names.contains(&new_tag));
+
+        let change: BookmarkChangeResponse = serde_json::from_str(body_str.as_str()).unwrap();
+        assert!(change.changed_fields.contains(&"tag_names".to_owned()));
+        assert!(!change.changed_fields.contains(&"url".to_owned()));
+        assert!(change.previous["tag_names"]
+            .as_array()
+            .unwrap()
+            .contains(&expected_tag_names[0].clone().into()));
         // Our time resolution is 1 sec, it takes less than that to run the test so these will most
         // often be the same. Could add a sleep before updating the post, but that's a bit
         // annoying.
@@ -995,7 +2760,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 2);
+        assert!(posts.results.len() == 2);
 
         // get posts with limit
         let response = app
@@ -1015,7 +2780,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 1);
+        assert!(posts.results.len() == 1);
         assert!(posts
             .results
             .iter()
@@ -1052,7 +2817,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 5);
+        assert!(posts.results.len() == 5);
 
         // get posts with offset
         let response = app
@@ -1072,7 +2837,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 3);
+        assert!(posts.results.len() == 3);
         assert!(posts
             .results
             .iter()
@@ -1117,7 +2882,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 5);
+        assert!(posts.results.len() == 5);
 
         // get posts with offset
         let response = app
@@ -1137,7 +2902,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 2);
+        assert!(posts.results.len() == 2);
         assert!(posts
             .results
             .iter()
@@ -1149,77 +2914,85 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_get_bookmark_tag() {
+    async fn test_private_notes() {
         let pool = setup_db(true).await;
         let app = app(pool, TOKEN.to_owned());
 
-        let tag1 = vec![get_random_string(5)];
-        let post1 = add_post(app.clone(), Some(tag1.clone()), false).await;
-        add_post(app.clone(), None, false).await;
-
-        // get posts
+        let bookmark_req = BookmarkRequest {
+            url: get_random_string(5),
+            title: get_random_string(5),
+            notes: Some("notes".to_owned()),
+            private_notes: Some("secret".to_owned()),
+            shared: Some(true),
+            ..Default::default()
+        };
         let response = app
             .clone()
             .oneshot(
                 Request::builder()
-                    //.uri(format!("/api/bookmarks"))
+                    .method("POST")
                     .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
-                    .body(Body::empty())
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_string(&bookmark_req).unwrap()))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
 
         let body = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
             .unwrap();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
-        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
-
-        assert!(posts.results.iter().count() == 2);
+        let res: BookmarkResponse = serde_json::from_str(body_str.as_str()).unwrap();
+        assert!(res.private_notes == Some("secret".to_owned()));
 
-        // get posts with query for tags
+        // a client not knowing about private notes, or leaving out the rest, updates the bookmark
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri(format!("/api/bookmarks?q=%23{}", tag1[0]))
+                    .method("PUT")
+                    .uri(format!("/api/bookmarks/{}", res.id))
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
-                    .body(Body::empty())
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(format!(
+                        r#"{{"url": "{}", "title": "new title"}}"#,
+                        bookmark_req.url
+                    )))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
 
         let body = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
             .unwrap();
         let body_str = String::from_utf8(body.to_vec()).unwrap();
-        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
-
-        assert!(posts.results.iter().count() == 1);
-
-        assert!(posts.results[0].tag_names.contains(&tag1[0]));
-        assert!(posts.results[0].url == post1.bookmark.url);
-        assert!(posts.results[0].title == post1.bookmark.title);
+        let res: BookmarkResponse = serde_json::from_str(body_str.as_str()).unwrap();
+        assert!(res.title == "new title");
+        assert!(res.private_notes == Some("secret".to_owned()));
+        assert!(res.notes == Some("notes".to_owned()));
+        assert!(res.shared);
     }
 
     #[tokio::test]
-    async fn test_get_bookmark_tags() {
+    async fn test_get_bookmark_tag() {
         let pool = setup_db(true).await;
         let app = app(pool, TOKEN.to_owned());
 
         let tag1 = vec![get_random_string(5)];
         let post1 = add_post(app.clone(), Some(tag1.clone()), false).await;
-        let tag2 = vec![get_random_string(5)];
-        let post2 = add_post(app.clone(), Some(tag2.clone()), false).await;
         add_post(app.clone(), None, false).await;
 
-        // get posts with query for tags
+        // get posts
         let response = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .uri(format!("/api/bookmarks?q=%23{}%20%23{}", tag1[0], tag2[0]))
+                    //.uri(format!("/api/bookmarks"))
+                    .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
                     .body(Body::empty())
                     .unwrap(),
@@ -1233,7 +3006,63 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 2);
+        assert!(posts.results.len() == 2);
+
+        // get posts with query for tags
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/bookmarks?q=%23{}", tag1[0]))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 1);
+
+        assert!(posts.results[0].tag_names.contains(&tag1[0]));
+        assert!(posts.results[0].url == post1.bookmark.url);
+        assert!(posts.results[0].title == post1.bookmark.title);
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmark_tags() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let tag1 = vec![get_random_string(5)];
+        let post1 = add_post(app.clone(), Some(tag1.clone()), false).await;
+        let tag2 = vec![get_random_string(5)];
+        let post2 = add_post(app.clone(), Some(tag2.clone()), false).await;
+        add_post(app.clone(), None, false).await;
+
+        // get posts with query for tags
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/bookmarks?q=%23{}%20%23{}", tag1[0], tag2[0]))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 2);
 
         assert!(posts
             .results
@@ -1250,6 +3079,73 @@ mod tests {
                 && post.tag_names.contains(&tag2[0])));
     }
 
+    #[tokio::test]
+    async fn test_get_bookmark_tag_params() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let tag1 = get_random_string(5);
+        let tag2 = get_random_string(5);
+        let tag3 = get_random_string(5);
+        let post1 = add_post(app.clone(), Some(vec![tag1.clone(), tag2.clone()]), false).await;
+        let post2 = add_post(app.clone(), Some(vec![tag1.clone()]), false).await;
+        let post3 = add_post(app.clone(), Some(vec![tag3.clone()]), false).await;
+
+        // all tags must match
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/bookmarks?tag={tag1}&tag={tag2}"))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 1);
+        assert!(posts.results[0].url == post1.bookmark.url);
+
+        // any of the tags must match
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/bookmarks?tag_any={tag1},{tag3}&tag={tag1}"))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 2);
+        assert!(posts
+            .results
+            .iter()
+            .any(|post| post.url == post1.bookmark.url));
+        assert!(posts
+            .results
+            .iter()
+            .any(|post| post.url == post2.bookmark.url));
+        assert!(!posts
+            .results
+            .iter()
+            .any(|post| post.url == post3.bookmark.url));
+    }
+
     #[tokio::test]
     async fn test_get_bookmark_free_text() {
         let pool = setup_db(true).await;
@@ -1269,7 +3165,7 @@ mod tests {
                             .description
                             .unwrap()
                             .split_whitespace()
-                            .nth(0)
+                            .next()
                             .unwrap()
                     ))
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
@@ -1285,12 +3181,85 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 1);
+        assert!(posts.results.len() == 1);
 
         assert!(posts.results[0].url == post1.bookmark.url);
         assert!(posts.results[0].title == post1.bookmark.title);
     }
 
+    #[tokio::test]
+    async fn test_get_bookmark_fuzzy() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        add_post(app.clone(), None, false).await;
+        let bookmark_req = BookmarkRequest {
+            url: "https://example.com/rust".to_owned(),
+            title: "Programming languages".to_owned(),
+            ..Default::default()
+        };
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_string(&bookmark_req).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // exact match, no suggestion
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks?q=programming")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 1);
+        assert!(!posts.results[0].fuzzy);
+        assert!(posts.did_you_mean.is_none());
+
+        // misspelled
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks?q=progamming")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 1);
+        assert!(posts.results[0].fuzzy);
+        assert!(posts.results[0].url == bookmark_req.url);
+        assert!(posts.did_you_mean == Some("programming".to_owned()));
+    }
+
     #[tokio::test]
     async fn test_get_bookmark_tag_and_free_text() {
         let pool = setup_db(true).await;
@@ -1333,12 +3302,73 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 1);
+        assert!(posts.results.len() == 1);
 
         assert!(posts.results[0].url == post2.bookmark.url);
         assert!(posts.results[0].title == post2.bookmark.title);
     }
 
+    #[tokio::test]
+    async fn test_get_bookmark_or_groups() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let (a, b, c, x) = (
+            get_random_string(5),
+            get_random_string(5),
+            get_random_string(5),
+            get_random_string(5),
+        );
+        let post1 = add_post(app.clone(), Some(vec![a.clone(), x.clone()]), false).await;
+        let post2 = add_post(app.clone(), Some(vec![b.clone()]), false).await;
+        let post3 = add_post(app.clone(), Some(vec![c.clone()]), false).await;
+        let word = post2.bookmark.notes.unwrap();
+        let word = word.split_whitespace().nth(1).unwrap();
+
+        for (q, urls) in [
+            (
+                format!("(%23{a}%20OR%20{word})"),
+                vec![&post1.bookmark.url, &post2.bookmark.url],
+            ),
+            (
+                format!("(%23{a}%20OR%20%23{c})%20%23{x}"),
+                vec![&post1.bookmark.url],
+            ),
+            (
+                format!("%23{b}%20OR%20(%23{c}%20{word})"),
+                vec![&post2.bookmark.url],
+            ),
+            (
+                format!("%23{a})%20OR%20%23{c}"),
+                vec![&post1.bookmark.url, &post3.bookmark.url],
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?q={q}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{q}");
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+            let mut found: Vec<&String> = posts.results.iter().map(|post| &post.url).collect();
+            found.sort();
+            let mut urls = urls;
+            urls.sort();
+
+            assert_eq!(found, urls, "{q}");
+        }
+    }
+
     #[tokio::test]
     async fn test_get_bookmark_unread() {
         let pool = setup_db(true).await;
@@ -1357,7 +3387,7 @@ mod tests {
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri(format!("/api/bookmarks?unread=yes",))
+                    .uri("/api/bookmarks?unread=yes")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
                     .body(Body::empty())
                     .unwrap(),
@@ -1371,7 +3401,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 1);
+        assert!(posts.results.len() == 1);
 
         assert!(posts.results[0].url == post2.bookmark.url);
         assert!(posts.results[0].title == post2.bookmark.title);
@@ -1407,7 +3437,7 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 1);
+        assert!(posts.results.len() == 1);
 
         assert!(posts
             .results
@@ -1416,45 +3446,37 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_delete_bookmark() {
+    async fn test_get_bookmark_shared() {
         let pool = setup_db(true).await;
         let app = app(pool, TOKEN.to_owned());
 
-        add_post(app.clone(), None, true).await;
-        add_post(app.clone(), None, true).await;
-
-        let CreatedBookmark {
-            bookmark,
-            response: _response,
-        } = add_post(app.clone(), None, false).await;
+        add_post(app.clone(), None, false).await;
 
-        // get existing post
+        let bookmark_req = BookmarkRequest {
+            url: get_random_string(5),
+            title: get_random_string(5),
+            shared: Some(true),
+            ..Default::default()
+        };
         let response = app
             .clone()
             .oneshot(
                 Request::builder()
-                    .uri(format!("/api/bookmarks/check?url={}", bookmark.url))
+                    .method("POST")
+                    .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
-                    .body(Body::empty())
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_string(&bookmark_req).unwrap()))
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert!(response.status() == StatusCode::OK);
-
-        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
-            .await
-            .unwrap();
-        let body_str = String::from_utf8(body.to_vec()).unwrap();
-        let res: ResponseCheck = serde_json::from_str(body_str.as_str()).unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
 
-        // delete post
         let response = app
-            .clone()
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri(format!("/api/bookmarks/{}", res.bookmark.unwrap().id))
+                    .uri("/api/bookmarks?shared=yes")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
                     .body(Body::empty())
                     .unwrap(),
@@ -1462,56 +3484,294 @@ mod tests {
             .await
             .unwrap();
 
-        assert!(response.status() == StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        // get posts
+        assert!(posts.results.len() == 1);
+        assert!(posts.results[0].url == bookmark_req.url);
+        assert!(posts.results[0].shared);
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_archived() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let post1 = add_post(app.clone(), None, false).await;
+
+        let bookmark_req = BookmarkRequest {
+            url: get_random_string(5),
+            title: get_random_string(5),
+            is_archived: Some(true),
+            ..Default::default()
+        };
         let response = app
+            .clone()
             .oneshot(
                 Request::builder()
+                    .method("POST")
                     .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
-                    .body(Body::empty())
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_string(&bookmark_req).unwrap()))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
 
-        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
-            .await
-            .unwrap();
-        let body_str = String::from_utf8(body.to_vec()).unwrap();
-        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+        for (uri, url, archived) in [
+            ("/api/bookmarks", &post1.bookmark.url, false),
+            ("/api/bookmarks/archived", &bookmark_req.url, true),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
 
-        assert!(posts.results.iter().count() == 2);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
 
-        assert!(posts.results.iter().any(|post| post.url != bookmark.url));
+            assert_eq!(posts.results.len(), 1);
+            assert_eq!(&posts.results[0].url, url);
+            assert_eq!(posts.results[0].is_archived, archived);
+        }
     }
 
     #[tokio::test]
-    async fn test_delete_bookmark_non_existing() {
+    async fn test_get_bookmarks_accept() {
         let pool = setup_db(true).await;
         let app = app(pool, TOKEN.to_owned());
 
-        add_post(app.clone(), None, true).await;
-        add_post(app.clone(), None, true).await;
+        let post1 = add_post(app.clone(), None, false).await;
+        add_post(app.clone(), None, false).await;
 
-        // delete non-existing post
         let response = app
             .clone()
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri(format!("/api/bookmarks/12345"))
+                    .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::ACCEPT, "text/csv")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        assert!(response.status() == StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/csv"));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let lines: Vec<&str> = body_str.lines().collect();
+
+        assert!(lines.len() == 3);
+        assert!(lines[0].starts_with("url,title"));
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with(&post1.bookmark.url)));
 
-        // get posts
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::ACCEPT, "application/x-ndjson")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: Vec<BookmarkResponse> = body_str
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert!(posts.len() == 2);
+        assert!(posts.iter().any(|post| post.url == post1.bookmark.url));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::ACCEPT, "text/html,application/xhtml+xml")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body_str.contains(&format!("<a href=\"{}\">", post1.bookmark.url)));
+    }
+
+    #[tokio::test]
+    async fn test_get_counts() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        add_post(app.clone(), None, true).await;
+        add_post(app.clone(), Some(vec![]), false).await;
+        let archived = add_post(app.clone(), None, false).await;
+        sqlx::query("UPDATE posts SET is_archived = 1 WHERE url = $1")
+            .bind(&archived.bookmark.url)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks/counts")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.status() == StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let counts: CountsResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(counts.total == 3);
+        assert!(counts.unread == 1);
+        assert!(counts.archived == 1);
+        assert!(counts.untagged == 1);
+        assert!(counts.broken == 0);
+    }
+
+    #[tokio::test]
+    async fn test_delete_bookmark() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        add_post(app.clone(), None, true).await;
+        add_post(app.clone(), None, true).await;
+
+        let CreatedBookmark {
+            bookmark,
+            response: _response,
+        } = add_post(app.clone(), None, false).await;
+
+        // get existing post
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/bookmarks/check?url={}", bookmark.url))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.status() == StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let res: ResponseCheck = serde_json::from_str(body_str.as_str()).unwrap();
+
+        // delete post
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(format!("/api/bookmarks/{}", res.bookmark.unwrap().id))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.status() == StatusCode::OK);
+
+        // get posts
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
+
+        assert!(posts.results.len() == 2);
+
+        assert!(posts.results.iter().any(|post| post.url != bookmark.url));
+    }
+
+    #[tokio::test]
+    async fn test_delete_bookmark_non_existing() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        add_post(app.clone(), None, true).await;
+        add_post(app.clone(), None, true).await;
+
+        // delete non-existing post
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/api/bookmarks/12345")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.status() == StatusCode::NOT_FOUND);
+
+        // get posts
         let response = app
             .oneshot(
                 Request::builder()
@@ -1529,6 +3789,1162 @@ mod tests {
         let body_str = String::from_utf8(body.to_vec()).unwrap();
         let posts: BookmarksResponse = serde_json::from_str(body_str.as_str()).unwrap();
 
-        assert!(posts.results.iter().count() == 2);
+        assert!(posts.results.len() == 2);
+    }
+
+    #[tokio::test]
+    async fn test_put_bookmark_non_existing() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let bookmark_req = BookmarkRequest {
+            url: get_random_string(5),
+            title: get_random_string(5),
+            description: None,
+            notes: None,
+            unread: Some(false),
+            tag_names: Some(vec![get_random_string(5)]),
+            ..Default::default()
+        };
+        let bookmark_json = serde_json::to_string(&bookmark_req).unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri("/api/bookmarks/12345")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(bookmark_json))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert!(response.status() == StatusCode::NOT_FOUND);
+
+        // the tag should not have been created
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/tags")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body_str = String::from_utf8(body.to_vec()).unwrap();
+        let res: TagsResponse = serde_json::from_str(body_str.as_str()).unwrap();
+        assert!(res.results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_modified() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let recent = add_post(app.clone(), None, false).await;
+        let old = add_post(app.clone(), None, false).await;
+        sqlx::query("UPDATE posts SET date_modified = unixepoch('now', '-20 days') WHERE url = $1")
+            .bind(&old.bookmark.url)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for (modified, count) in [("today", 1), ("week", 1), ("month", 2), ("unknown", 2)] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?modified={modified}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(posts.results.len(), count, "modified={modified}");
+            assert!(posts
+                .results
+                .iter()
+                .any(|post| post.url == recent.bookmark.url));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_pagination() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for _ in 0..5 {
+            add_post(app.clone(), None, false).await;
+        }
+
+        for (params, count, limit, offset) in [
+            ("limit=2", 2, 2, 0),
+            ("limit=2&offset=4", 1, 2, 4),
+            ("limit=2&offset=5", 0, 2, 5),
+            ("limit=2&offset=100", 0, 2, 100),
+            ("limit=0&offset=3", 2, 0, 3),
+            ("offset=5", 0, 100, 5),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?{params}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{params}");
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(posts.count, count, "{params}");
+            assert_eq!(posts.results.len(), count, "{params}");
+            assert_eq!(posts.total, 5, "{params}");
+            assert_eq!(posts.limit, limit, "{params}");
+            assert_eq!(posts.offset, offset, "{params}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_links() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for _ in 0..5 {
+            add_post(app.clone(), None, true).await;
+        }
+
+        for (params, links) in [
+            (
+                "unread=yes&limit=2&offset=2",
+                Some(concat!(
+                    r#"</api/bookmarks?unread=yes&limit=2&offset=0>; rel="first", "#,
+                    r#"</api/bookmarks?unread=yes&limit=2&offset=0>; rel="prev", "#,
+                    r#"</api/bookmarks?unread=yes&limit=2&offset=4>; rel="next", "#,
+                    r#"</api/bookmarks?unread=yes&limit=2&offset=4>; rel="last""#,
+                )),
+            ),
+            (
+                "offset=1",
+                Some(concat!(
+                    r#"</api/bookmarks?limit=100&offset=0>; rel="first", "#,
+                    r#"</api/bookmarks?limit=100&offset=0>; rel="prev", "#,
+                    r#"</api/bookmarks?limit=100&offset=0>; rel="last""#,
+                )),
+            ),
+            ("limit=0", None),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?{params}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{params}");
+            assert_eq!(
+                response
+                    .headers()
+                    .get(header::LINK)
+                    .map(|links| links.to_str().unwrap()),
+                links,
+                "{params}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_url() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        add_post(app.clone(), None, false).await;
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        r#"{"url": "https://example.com/a?b=c", "title": "a"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for (url, count) in [
+            ("https%3A%2F%2Fexample.com%2Fa%3Fb%3Dc", 1),
+            ("https%3A%2F%2FExample.com%3A443%2Fa%3Fb%3Dc", 1),
+            ("https%3A%2F%2Fexample.com%2Fa", 0),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?url={url}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(posts.results.len(), count, "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_bookmark_dates() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for (body, status, added) in [
+            (
+                r#"{"url": "https://a.example", "title": "a", "date_added": "2019-05-01T10:00:00+02:00", "date_modified": 1600000000}"#,
+                StatusCode::CREATED,
+                Some("2019-05-01T08:00:00+00:00"),
+            ),
+            (
+                r#"{"url": "https://b.example", "title": "b", "date_added": null}"#,
+                StatusCode::CREATED,
+                None,
+            ),
+            (
+                r#"{"url": "https://c.example", "title": "c", "date_added": "yesterday"}"#,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                None,
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{body}");
+            if status != StatusCode::CREATED {
+                continue;
+            }
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let bookmark: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+            match added {
+                Some(added) => {
+                    assert_eq!(bookmark.date_added, added);
+                    assert_eq!(bookmark.date_modified, "2020-09-13T12:26:40+00:00");
+                }
+                None => assert!(bookmark
+                    .date_added
+                    .starts_with(&Utc::now().format("%Y-").to_string())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_candidates() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for url in [
+            "https://www.example.com/a/?ref=feed",
+            "http://example.com/a",
+            "https://example.com/ab",
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(format!(r#"{{"url": "{url}", "title": "a"}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        for (url, exact, candidates) in [
+            ("http%3A%2F%2Fexample.com%2Fa", true, 1),
+            ("https%3A%2F%2Fexample.com%2Fa%23top", false, 2),
+            ("http%3A%2F%2FEXAMPLE.com%2Fa&normalized=true", true, 1),
+            ("https%3A%2F%2Fexample.com%2F", false, 0),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks/check?url={url}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let res: ResponseCheck = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(res.bookmark.is_some(), exact, "{url}");
+            assert_eq!(res.candidates.len(), candidates, "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_post_bookmark_url_key() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let mut first = None;
+        for (url, status) in [
+            ("http://example.com/a?b=1&c=2", StatusCode::CREATED),
+            ("http://EXAMPLE.com/a?c=2&b=1", StatusCode::CONFLICT),
+            ("https://example.com/a?b=1&c=2", StatusCode::CREATED),
+            ("http://www.example.com/a?b=1&c=2", StatusCode::CREATED),
+            ("http://example.com/a/?b=1&c=2", StatusCode::CREATED),
+            ("http://example.com/a?b=1&c=2#/b", StatusCode::CREATED),
+            ("https://example.com:8080/a?b=1&c=2", StatusCode::CREATED),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(format!(r#"{{"url": "{url}", "title": "a"}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{url}");
+
+            // a conflict has the bookmark already saved
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let bookmark: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+            let id = *first.get_or_insert(bookmark.id);
+            if status == StatusCode::CONFLICT {
+                assert_eq!(bookmark.id, id);
+            }
+        }
+
+        assert_eq!(url_key("ftp://example.com/a/#b"), "ftp://example.com/a/#b");
+        assert_eq!(
+            url_key("https://EXAMPLE.com/a/?c=2&b=1#top"),
+            "https://example.com/a/?b=1&c=2#top"
+        );
+        assert_eq!(url_key("not a url "), "not a url");
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_source() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let post = add_post(app.clone(), None, false).await;
+
+        for (source, count) in [("api", 1), ("import%3Alinkding", 0)] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?source={source}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(posts.results.len(), count, "{source}");
+            if count > 0 {
+                assert_eq!(posts.results[0].url, post.bookmark.url);
+                assert_eq!(posts.results[0].source.as_deref(), Some("api"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_put_bookmark_by_url() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for (body, status, title, notes, tags) in [
+            (
+                r#"{"url": "https://example.com/a", "title": "a", "notes": "n", "tag_names": ["x"]}"#,
+                StatusCode::CREATED,
+                "a",
+                "n",
+                vec!["x"],
+            ),
+            (
+                r#"{"url": "https://EXAMPLE.com/a", "title": "b", "tag_names": ["y", "z"]}"#,
+                StatusCode::OK,
+                "b",
+                "n",
+                vec!["y", "z"],
+            ),
+            (
+                r#"{"url": "https://example.com/a", "title": "b", "notes": "m"}"#,
+                StatusCode::OK,
+                "b",
+                "m",
+                vec!["y", "z"],
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("PUT")
+                        .uri("/api/bookmarks/by-url")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{body}");
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let res: BookmarkUpsertResponse = serde_json::from_slice(&body).unwrap();
+
+            assert_eq!(res.created, status == StatusCode::CREATED);
+            assert_eq!(res.bookmark.id, 1);
+            assert_eq!(res.bookmark.url, "https://example.com/a");
+            assert_eq!(res.bookmark.title, title);
+            assert_eq!(res.bookmark.notes.as_deref(), Some(notes));
+            assert_eq!(res.bookmark.tag_names, tags);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_on_this_day_and_calendar() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for (url, date_added) in [
+            ("https://example.com/a", "2022-05-01T10:00:00Z"),
+            ("https://example.com/b", "2023-05-01T23:00:00Z"),
+            ("https://example.com/c", "2023-05-02T00:00:00Z"),
+            ("https://example.com/d", "2024-05-01T08:00:00Z"),
+            ("https://example.com/e", "2023-12-31T12:00:00Z"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(format!(
+                            r#"{{"url": "{url}", "title": "a", "date_added": "{date_added}"}}"#
+                        )))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks/on-this-day?date=2024-05-01")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: OnThisDayResponse = serde_json::from_slice(&body).unwrap();
+        let urls: Vec<&str> = res.results.iter().map(|post| post.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://example.com/b", "https://example.com/a"]);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks/calendar?year=2023")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: CalendarResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(res.total, 3);
+        assert_eq!(
+            res.days,
+            ["2023-05-01", "2023-05-02", "2023-12-31"]
+                .into_iter()
+                .map(|date| CalendarDay {
+                    date: date.to_owned(),
+                    count: 1,
+                })
+                .collect::<Vec<_>>()
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks/on-this-day?date=05-01")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_facets() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let tag1 = get_random_string(5);
+        let tag2 = get_random_string(6);
+        for (url, tags, unread) in [
+            ("https://example.com/a", vec![tag1.clone()], true),
+            (
+                "https://example.com/b",
+                vec![tag1.clone(), tag2.clone()],
+                false,
+            ),
+            ("https://example.org/", vec![tag2.clone()], true),
+        ] {
+            let bookmark = serde_json::to_string(&BookmarkRequest {
+                url: url.to_owned(),
+                unread: Some(unread),
+                tag_names: Some(tags),
+                ..Default::default()
+            })
+            .unwrap();
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(bookmark))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let get_facets = |app: Router, params: String| async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks/facets?{params}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<FacetsResponse>(&body).unwrap()
+        };
+
+        let facets = get_facets(app.clone(), String::new()).await;
+        assert_eq!((facets.total, facets.unread, facets.read), (3, 2, 1));
+        assert_eq!(
+            facets.domains,
+            vec![
+                FacetCount {
+                    name: "example.com".to_owned(),
+                    count: 2
+                },
+                FacetCount {
+                    name: "example.org".to_owned(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(facets.tags.len(), 2);
+        assert!(facets.tags.iter().all(|tag| tag.count == 2));
+
+        let facets = get_facets(app.clone(), format!("q=%23{tag2}")).await;
+        assert_eq!((facets.total, facets.unread, facets.read), (2, 1, 1));
+        assert_eq!(facets.domains.len(), 2);
+        assert!(facets.tags.contains(&FacetCount {
+            name: tag1.clone(),
+            count: 1
+        }));
+        assert!(facets.tags.contains(&FacetCount {
+            name: tag2.clone(),
+            count: 2
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_post_bookmark_context() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let body = serde_json::json!({
+            "url": "https://example.com/article",
+            "title": "Article",
+            "notes": "read later",
+            "referrer": "https://news.example.com/",
+            "selected_text": "first line\nsecond line",
+        });
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            created.referrer.as_deref(),
+            Some("https://news.example.com/")
+        );
+        assert_eq!(
+            created.notes.as_deref(),
+            Some("> first line\n> second line\n\nread later")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_bookmark_tags() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let kept = get_random_string(5);
+        let removed = get_random_string(6);
+        let added = get_random_string(7);
+        let created = add_post(
+            app.clone(),
+            Some(vec![kept.clone(), removed.clone()]),
+            false,
+        )
+        .await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let id = serde_json::from_slice::<BookmarkResponse>(&body)
+            .unwrap()
+            .id;
+
+        let post_tags = |id: PostID, change: &TagsChangeRequest| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/api/bookmarks/{id}/tags"))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(serde_json::to_string(change).unwrap()))
+                    .unwrap(),
+            )
+        };
+
+        let change = TagsChangeRequest {
+            add: vec![added.clone(), kept.clone()],
+            remove: vec![removed.clone()],
+        };
+        let response = post_tags(id, &change).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut bookmark: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        bookmark.tag_names.sort();
+        let mut expected = vec![added, kept];
+        expected.sort();
+        assert_eq!(bookmark.tag_names, expected);
+
+        // the removed tag had no other bookmarks
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/api/tags/{removed}"))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = post_tags(id + 1, &change).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let change = TagsChangeRequest {
+            add: vec!["two words".to_owned()],
+            ..Default::default()
+        };
+        let response = post_tags(id, &change).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmark_by_uuid() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let created = add_post(app.clone(), None, false).await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        let uuid = created.uuid.unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+
+        let get = |uri: String| {
+            app.clone().oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = get(format!("/api/bookmarks/uuid/{}", uuid.to_uppercase()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let bookmark: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(bookmark.id, created.id);
+
+        let response = get("/api/bookmarks/uuid/unknown".to_owned()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_notes_revisions() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let created = add_post(app.clone(), None, false).await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let id = serde_json::from_slice::<BookmarkResponse>(&body)
+            .unwrap()
+            .id;
+        let first = created.bookmark.notes.clone().unwrap();
+
+        for notes in ["second", "second\nthird"] {
+            let bookmark = serde_json::to_string(&BookmarkRequest {
+                notes: Some(notes.to_owned()),
+                url: created.bookmark.url.clone(),
+                title: created.bookmark.title.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("PUT")
+                        .uri(format!("/api/bookmarks/{id}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(bookmark))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let get = |app: Router, uri: String| async move {
+            app.oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+        };
+
+        let response = get(app.clone(), format!("/api/bookmarks/{id}/notes/revisions")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let revisions: NotesRevisionsResponse = serde_json::from_slice(&body).unwrap();
+        let notes: Vec<&str> = revisions
+            .results
+            .iter()
+            .map(|revision| revision.notes.as_str())
+            .collect();
+        assert_eq!(notes, vec![first.as_str(), "second", "second\nthird"]);
+
+        let response = get(
+            app.clone(),
+            format!("/api/bookmarks/{id}/notes/revisions/1/diff"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let diff = String::from_utf8(body.to_vec()).unwrap();
+        assert!(diff.starts_with("--- revision 1\n+++ current\n"));
+        assert!(diff.contains(&format!("-{first}")));
+        assert!(diff.contains("+second\n+third"));
+
+        let response = get(
+            app.clone(),
+            format!("/api/bookmarks/{id}/notes/revisions/4/diff"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = get(app, "/api/bookmarks/12345/notes/revisions".to_owned()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_bookmarks_sort_title() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for title in ["banan", "Apa", "École"] {
+            let bookmark = serde_json::to_string(&BookmarkRequest {
+                url: get_random_string(5),
+                title: title.to_owned(),
+                ..Default::default()
+            })
+            .unwrap();
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(bookmark))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        for (sort, titles) in [
+            ("title", ["Apa", "banan", "École"]),
+            ("-title", ["École", "banan", "Apa"]),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?sort={sort}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+            let found: Vec<&str> = posts
+                .results
+                .iter()
+                .map(|post| post.title.as_str())
+                .collect();
+            assert_eq!(found, titles, "{sort}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_notes() {
+        let pool = setup_db(true).await;
+        let mut state = AppState::new(pool, TOKEN.to_owned());
+        state.max_notes_size = Some(4 * notes::LARGE_NOTES_SIZE);
+        let app = crate::app_with_state(state);
+
+        let request = |method: &str, uri: &str, body: Option<String>| {
+            let builder = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref());
+            let request = builder
+                .body(body.map_or_else(Body::empty, Body::from))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let bookmark = |notes: &str| {
+            Some(
+                serde_json::json!({"url": "https://example.com", "title": "a", "notes": notes})
+                    .to_string(),
+            )
+        };
+
+        let large = "a pasted article ".repeat(notes::LARGE_NOTES_SIZE / 8);
+        let response = request("POST", "/api/bookmarks", bookmark(&large))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created.notes.as_deref(), Some(large.as_str()));
+
+        let list = |uri: &'static str| {
+            let response = request("GET", uri, None);
+            async move {
+                let body = axum::body::to_bytes(response.await.unwrap().into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let res: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+                res.results[0].notes.clone().unwrap_or_default()
+            }
+        };
+        assert_eq!(list("/api/bookmarks").await, "");
+        assert_eq!(list("/api/bookmarks?include=notes").await, large);
+
+        let too_large = "a".repeat(4 * notes::LARGE_NOTES_SIZE + 1);
+        let response = request(
+            "PUT",
+            &format!("/api/bookmarks/{}", created.id),
+            bookmark(&too_large),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = request(
+            "PUT",
+            &format!("/api/bookmarks/{}", created.id),
+            bookmark("short"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(list("/api/bookmarks").await, "short");
+        assert_eq!(list("/api/bookmarks?include=notes").await, "short");
+    }
+
+    #[tokio::test]
+    async fn test_large_notes_revisions() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let request = |method: &str, uri: String, notes: Option<&str>| {
+            let body = notes.map_or_else(Body::empty, |notes| {
+                Body::from(
+                    serde_json::json!({"url": "https://example.com", "title": "a", "notes": notes})
+                        .to_string(),
+                )
+            });
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(body)
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert!(response.status().is_success());
+                axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let body = request("POST", "/api/bookmarks".to_owned(), Some("first")).await;
+        let id = serde_json::from_slice::<BookmarkResponse>(&body)
+            .unwrap()
+            .id;
+
+        let large = "a pasted article about zyzzyvas\n".repeat(notes::LARGE_NOTES_SIZE / 16);
+        let larger = format!("{large}the end\n");
+        for notes in [large.as_str(), larger.as_str()] {
+            request("PUT", format!("/api/bookmarks/{id}"), Some(notes)).await;
+        }
+
+        let search = || async {
+            let body = request("GET", "/api/bookmarks?q=zyzzyvas".to_owned(), None).await;
+            serde_json::from_slice::<BookmarksResponse>(&body)
+                .unwrap()
+                .count
+        };
+        assert_eq!(search().await, 1);
+
+        let body = request(
+            "GET",
+            format!("/api/bookmarks/{id}/notes/revisions/2/diff"),
+            None,
+        )
+        .await;
+        let diff = String::from_utf8(body.to_vec()).unwrap();
+        assert!(diff.ends_with("+the end\n"), "{diff}");
+
+        // empty as in the listings, they're kept
+        request("PUT", format!("/api/bookmarks/{id}"), Some("")).await;
+        assert_eq!(search().await, 1);
+
+        request("PUT", format!("/api/bookmarks/{id}"), Some("short")).await;
+        assert_eq!(search().await, 0);
+
+        let body = request("GET", format!("/api/bookmarks/{id}/notes/revisions"), None).await;
+        let revisions: NotesRevisionsResponse = serde_json::from_slice(&body).unwrap();
+        let notes: Vec<&str> = revisions
+            .results
+            .iter()
+            .map(|revision| revision.notes.as_str())
+            .collect();
+        assert_eq!(
+            notes,
+            vec!["first", large.as_str(), larger.as_str(), "short"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_large_notes_listed_and_put_back() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let large = "a pasted article\n".repeat(notes::LARGE_NOTES_SIZE / 8);
+        let request = |method: &str, uri: &str, body: String| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert!(response.status().is_success());
+                axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let body = serde_json::json!({"url": "https://example.com", "title": "a", "notes": large});
+        let body = request("POST", "/api/bookmarks", body.to_string()).await;
+        let id = serde_json::from_slice::<BookmarkResponse>(&body)
+            .unwrap()
+            .id;
+
+        // a client sending back the bookmark as listed, without the notes, with a new title
+        let body = request("GET", "/api/bookmarks", String::new()).await;
+        let mut listed = serde_json::from_slice::<BookmarksResponse>(&body)
+            .unwrap()
+            .results
+            .remove(0);
+        assert_eq!(listed.notes.as_deref(), Some(""));
+        listed.title = "b".to_owned();
+        let uri = format!("/api/bookmarks/{id}");
+        request("PUT", &uri, serde_json::to_string(&listed).unwrap()).await;
+
+        let body = request("GET", &uri, String::new()).await;
+        let bookmark = serde_json::from_slice::<BookmarkResponse>(&body).unwrap();
+        assert_eq!(bookmark.title, "b");
+        assert_eq!(bookmark.notes, Some(large));
     }
 }
@@ -0,0 +1,293 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The graveyard keeps the url, title and tags of deleted bookmarks, searchable with
+//! `/api/graveyard?q=`, to find a bookmark long after it was deleted. The whole bookmark is kept
+//! too, for bringing it back with `POST /api/undo`.
+
+use crate::{AppState, PostID};
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{TimeZone, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, SqliteConnection};
+use std::sync::Arc;
+use tracing::error;
+
+use super::bookmarks::url_key;
+
+// the columns of posts kept in the graveyard
+const POST_COLUMNS: [&str; 15] = [
+    "id",
+    "url",
+    "title",
+    "description",
+    "notes",
+    "private_notes",
+    "unread",
+    "shared",
+    "is_archived",
+    "original_url",
+    "source",
+    "referrer",
+    "uuid",
+    "date_added",
+    "date_modified",
+];
+
+// graves per page unless given, 0 is all
+const DEFAULT_LIMIT: u32 = 100;
+
+#[derive(Debug, sqlx::FromRow)]
+struct GraveDb {
+    url: String,
+    title: String,
+    // JSON array
+    tag_names: String,
+    deleted_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct GraveResponse {
+    pub(crate) url: String,
+    pub(crate) title: String,
+    pub(crate) tag_names: Vec<String>,
+    pub(crate) deleted_at: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct GraveyardResponse {
+    pub(crate) count: usize,
+    pub(crate) results: Vec<GraveResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraveyardQuery {
+    // words matching the url, title or tags, all of them have to match
+    q: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+impl From<GraveDb> for GraveResponse {
+    fn from(val: GraveDb) -> Self {
+        let deleted = Utc.timestamp_opt(val.deleted_at, 0).unwrap();
+
+        GraveResponse {
+            url: val.url,
+            title: val.title,
+            tag_names: serde_json::from_str(&val.tag_names).unwrap_or_default(),
+            deleted_at: deleted.to_rfc3339(),
+        }
+    }
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(handle_get_graveyard))
+        .with_state(state)
+}
+
+/// Adds the bookmark to the graveyard, before it's deleted in the same transaction.
+pub(crate) async fn bury(conn: &mut SqliteConnection, post_id: PostID) -> Result<(), sqlx::Error> {
+    let post: Vec<String> = POST_COLUMNS
+        .iter()
+        .map(|column| format!("'{column}', posts.{column}"))
+        .collect();
+    sqlx::query(&format!(
+        r"
+            INSERT INTO graveyard (url, title, tag_names, deleted_at, post_id, post)
+                SELECT posts.url, posts.title,
+                    json_group_array(tags.name) FILTER (WHERE tags.name IS NOT NULL),
+                    unixepoch(), posts.id, json_object({})
+                FROM posts
+                LEFT OUTER JOIN post_tag ON (posts.id = post_tag.post_id)
+                LEFT OUTER JOIN tags ON (tags.id = post_tag.tag_id)
+                WHERE posts.id = $1
+                GROUP BY posts.id
+        ",
+        post.join(", ")
+    ))
+    .bind(post_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Brings the bookmark in the grave back with its tags, and removes the grave. It gets its old
+/// id back unless a new bookmark has taken it. Fails if a bookmark with the same url, or url
+/// key, has been added since.
+pub(crate) async fn raise(
+    conn: &mut SqliteConnection,
+    grave_id: i64,
+) -> Result<PostID, sqlx::Error> {
+    let values: Vec<String> = POST_COLUMNS
+        .iter()
+        .map(|column| match *column {
+            "id" => "CASE WHEN EXISTS (SELECT 1 FROM posts WHERE id = post_id) THEN NULL ELSE post_id END".to_owned(),
+            column => format!("post ->> '{column}'"),
+        })
+        .collect();
+    let post_id = sqlx::query(&format!(
+        "INSERT INTO posts ({}) SELECT {} FROM graveyard WHERE id = $1",
+        POST_COLUMNS.join(", "),
+        values.join(", ")
+    ))
+    .bind(grave_id)
+    .execute(&mut *conn)
+    .await?
+    .last_insert_rowid();
+    let url: String = sqlx::query_scalar("SELECT url FROM posts WHERE id = $1")
+        .bind(post_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE posts SET url_key = $1 WHERE id = $2")
+        .bind(url_key(&url))
+        .bind(post_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        r"
+            INSERT OR IGNORE INTO tags (name, date_added)
+                SELECT json_each.value, unixepoch() FROM graveyard, json_each(graveyard.tag_names)
+                WHERE graveyard.id = $1
+        ",
+    )
+    .bind(grave_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        r"
+            INSERT OR IGNORE INTO post_tag (post_id, tag_id)
+                SELECT $2, tags.id
+                FROM graveyard, json_each(graveyard.tag_names)
+                JOIN tags ON tags.name = json_each.value
+                WHERE graveyard.id = $1
+        ",
+    )
+    .bind(grave_id)
+    .bind(post_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM graveyard WHERE id = $1")
+        .bind(grave_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(post_id)
+}
+
+async fn handle_get_graveyard(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<GraveyardQuery>,
+) -> Result<Json<GraveyardResponse>, StatusCode> {
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new("SELECT * FROM graveyard");
+
+    let words: Vec<String> = query
+        .q
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|word| format!("%{word}%"))
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        sql.push(if i == 0 { " WHERE " } else { " AND " });
+        sql.push("(url LIKE ");
+        sql.push_bind(word.clone());
+        sql.push(" OR title LIKE ");
+        sql.push_bind(word.clone());
+        sql.push(" OR tag_names LIKE ");
+        sql.push_bind(word.clone());
+        sql.push(")");
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    sql.push(" ORDER BY deleted_at DESC, id DESC LIMIT ");
+    // -1 is no limit
+    sql.push_bind(if limit == 0 { -1 } else { i64::from(limit) });
+    sql.push(" OFFSET ");
+    sql.push_bind(query.offset.unwrap_or(0));
+
+    match sql.build_query_as::<GraveDb>().fetch_all(&state.pool).await {
+        Ok(rows) => {
+            let graves: Vec<GraveResponse> = rows.into_iter().map(Into::into).collect();
+            Ok(Json(GraveyardResponse {
+                count: graves.len(),
+                results: graves,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to search the graveyard: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::bookmarks::tests::{add_post, get_random_string};
+    use crate::api::handlers::bookmarks::BookmarkResponse;
+    use crate::{app, setup_db};
+    use axum::{body::Body, http::Request};
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_graveyard() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let tag = get_random_string(8);
+        let created = add_post(app.clone(), Some(vec![tag.clone()]), false).await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let id = serde_json::from_slice::<BookmarkResponse>(&body)
+            .unwrap()
+            .id;
+        add_post(app.clone(), None, false).await;
+
+        let request = |method: &str, uri: String| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = request("DELETE", format!("/api/bookmarks/{id}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        for (q, count) in [
+            (tag.clone(), 1),
+            (created.bookmark.title.clone(), 1),
+            (get_random_string(9), 0),
+        ] {
+            let response = request("GET", format!("/api/graveyard?q={}", q.replace(' ', "+")))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let graves: GraveyardResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(graves.count, count, "{q}");
+            if count > 0 {
+                assert_eq!(graves.results[0].url, created.bookmark.url);
+                assert_eq!(graves.results[0].tag_names, vec![tag.clone()]);
+            }
+        }
+    }
+}
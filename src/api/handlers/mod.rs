@@ -5,11 +5,24 @@
 use crate::AppState;
 use axum::Router;
 use std::sync::Arc;
+pub mod admin;
 pub mod bookmarks;
+pub mod graveyard;
+pub mod pinboard;
+pub mod smart_tags;
 pub mod tags;
+pub mod templates;
+pub mod tokens;
+pub mod undo;
 
 pub fn configure(state: &Arc<AppState>) -> Router {
     Router::new()
+        .nest("/admin", admin::configure(state.clone()))
         .nest("/bookmarks", bookmarks::configure(state.clone()))
+        .nest("/graveyard", graveyard::configure(state.clone()))
+        .nest("/smart_tags", smart_tags::configure(state.clone()))
         .nest("/tags", tags::configure(state.clone()))
+        .nest("/templates", templates::configure(state.clone()))
+        .nest("/tokens", tokens::configure(state.clone()))
+        .nest("/undo", undo::configure(state.clone()))
 }
@@ -0,0 +1,541 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! `/pinboard/v1`, the part of the Pinboard API used by Pinboard clients and browser extensions:
+//! `posts/add`, `posts/delete`, `posts/get`, `posts/recent`, `tags/get` and `tags/rename`. The
+//! answers are XML like Pinboard's, or JSON with `format=json`. Clients authenticate with
+//! `auth_token=<user>:<PINRS_TOKEN>`, see `auth::StaticToken`.
+
+use crate::api::format::escape_html;
+use crate::audit::{AffectedIds, Identity};
+use crate::AppState;
+use axum::extract::{Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use hyper::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::bookmarks::{
+    delete_bookmark, get_bookmark_by_url, get_bookmarks, timestamp_before, upsert_bookmark,
+    BookmarkQuery, BookmarkRequest, BookmarkResponse,
+};
+use super::smart_tags::get_smart_tags;
+use super::tags::{get_tag, is_valid_tag_name, rename_tag};
+
+// posts/recent, as in Pinboard
+const DEFAULT_RECENT: u32 = 15;
+const MAX_RECENT: u32 = 100;
+
+// tags a listing can be filtered on, as in Pinboard
+const MAX_FILTER_TAGS: usize = 3;
+
+const XML_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>"#;
+
+/// The routes making changes with GET, for the audit log and the read-only mode
+pub(crate) const WRITE_ROUTES: [&str; 3] = [
+    "/pinboard/v1/posts/add",
+    "/pinboard/v1/posts/delete",
+    "/pinboard/v1/tags/rename",
+];
+
+// The parameters of all the methods
+#[derive(Deserialize, Debug, Default)]
+struct PinboardQuery {
+    // json, or xml if not given
+    format: Option<String>,
+    url: Option<String>,
+    // the title and description, named as in Pinboard
+    description: Option<String>,
+    extended: Option<String>,
+    // separated by spaces, or commas
+    tags: Option<String>,
+    // the tags to filter on, separated the same way
+    tag: Option<String>,
+    dt: Option<String>,
+    replace: Option<String>,
+    shared: Option<String>,
+    toread: Option<String>,
+    count: Option<u32>,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl PinboardQuery {
+    fn json(&self) -> bool {
+        self.format.as_deref() == Some("json")
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct PinboardPost {
+    pub(crate) href: String,
+    pub(crate) description: String,
+    pub(crate) extended: String,
+    pub(crate) meta: String,
+    pub(crate) hash: String,
+    pub(crate) time: String,
+    pub(crate) shared: String,
+    pub(crate) toread: String,
+    pub(crate) tags: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct PinboardPosts {
+    pub(crate) date: String,
+    pub(crate) user: String,
+    pub(crate) posts: Vec<PinboardPost>,
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_owned()
+}
+
+// As in Pinboard, e.g. 2025-01-01T00:00:00Z
+fn pinboard_time(date: &str) -> String {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| {
+            date.with_timezone(&Utc)
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+impl From<BookmarkResponse> for PinboardPost {
+    fn from(val: BookmarkResponse) -> Self {
+        PinboardPost {
+            href: val.url,
+            description: val.title,
+            extended: val.description.unwrap_or_default(),
+            // changes with the bookmark
+            meta: pinboard_time(&val.date_modified),
+            // the uuid as 32 hex digits, in place of the md5 of the url
+            hash: val.uuid.unwrap_or_default().replace('-', ""),
+            time: pinboard_time(&val.date_added),
+            shared: yes_no(val.shared),
+            toread: yes_no(val.unread),
+            tags: val.tag_names.join(" "),
+        }
+    }
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/posts/add", get(handle_posts_add))
+        .route("/posts/delete", get(handle_posts_delete))
+        .route("/posts/get", get(handle_posts_get))
+        .route("/posts/recent", get(handle_posts_recent))
+        .route("/tags/get", get(handle_tags_get))
+        .route("/tags/rename", get(handle_tags_rename))
+        .with_state(state)
+}
+
+fn split_tags(tags: Option<&str>) -> Vec<String> {
+    tags.unwrap_or_default()
+        .split([' ', ','])
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn xml(body: &str) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/xml; charset=utf-8")],
+        format!("{XML_HEADER}\n{body}"),
+    )
+        .into_response()
+}
+
+// The result of a change, e.g. done or item not found, as the result code
+fn result_code(query: &PinboardQuery, code: &str) -> Response {
+    if query.json() {
+        Json(BTreeMap::from([("result_code", code)])).into_response()
+    } else {
+        xml(&format!(r#"<result code="{}" />"#, escape_html(code)))
+    }
+}
+
+fn posts(query: &PinboardQuery, posts: PinboardPosts) -> Response {
+    if query.json() {
+        return Json(posts).into_response();
+    }
+
+    let mut result = vec![format!(
+        r#"<posts dt="{}" user="{}">"#,
+        escape_html(&posts.date),
+        escape_html(&posts.user)
+    )];
+    for post in posts.posts {
+        result.push(format!(
+            r#"<post href="{}" description="{}" extended="{}" meta="{}" hash="{}" time="{}" shared="{}" toread="{}" tag="{}" />"#,
+            escape_html(&post.href),
+            escape_html(&post.description),
+            escape_html(&post.extended),
+            escape_html(&post.meta),
+            escape_html(&post.hash),
+            escape_html(&post.time),
+            post.shared,
+            post.toread,
+            escape_html(&post.tags)
+        ));
+    }
+    result.push("</posts>".to_owned());
+    xml(&result.join("\n"))
+}
+
+// Adds the bookmark, or replaces the one with the url unless replace=no
+async fn handle_posts_add(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PinboardQuery>,
+) -> Response {
+    let Some(url) = query.url.clone().filter(|url| !url.is_empty()) else {
+        return result_code(&query, "missing url");
+    };
+    if query.replace.as_deref() == Some("no")
+        && get_bookmark_by_url(state.clone(), &url).await.is_some()
+    {
+        return result_code(&query, "item already exists");
+    }
+
+    let bookmark = BookmarkRequest {
+        url,
+        title: query.description.clone().unwrap_or_default(),
+        description: Some(query.extended.clone().unwrap_or_default()),
+        tag_names: Some(split_tags(query.tags.as_deref())),
+        unread: query.toread.as_deref().map(|toread| toread == "yes"),
+        shared: query.shared.as_deref().map(|shared| shared == "yes"),
+        date_added: query.dt.as_deref().and_then(timestamp_before),
+        ..Default::default()
+    };
+    match upsert_bookmark(&state, bookmark).await {
+        Ok((id, _)) => (
+            Extension(AffectedIds(vec![id])),
+            result_code(&query, "done"),
+        )
+            .into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+async fn handle_posts_delete(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PinboardQuery>,
+) -> Response {
+    let Some(bookmark) =
+        get_bookmark_by_url(state.clone(), query.url.as_deref().unwrap_or_default()).await
+    else {
+        return result_code(&query, "item not found");
+    };
+
+    match delete_bookmark(&state.pool, bookmark.id).await {
+        Ok(_) => {
+            info!("deleted bookmark: {}", bookmark.id);
+            (
+                Extension(AffectedIds(vec![bookmark.id])),
+                result_code(&query, "done"),
+            )
+                .into_response()
+        }
+        Err(err) => {
+            error!("Failed to delete bookmark: {} ({})", bookmark.id, err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+// The bookmarks added on the day, the most recent day with bookmarks unless given, or the
+// bookmark with the url
+async fn handle_posts_get(
+    State(state): State<Arc<AppState>>,
+    Extension(Identity(user)): Extension<Identity>,
+    Query(query): Query<PinboardQuery>,
+) -> Response {
+    let mut tags = split_tags(query.tag.as_deref());
+    tags.truncate(MAX_FILTER_TAGS);
+    let mut bookmark_query = BookmarkQuery {
+        tag: tags,
+        limit: Some(0),
+        ..Default::default()
+    };
+
+    if query.url.is_some() {
+        bookmark_query.url.clone_from(&query.url);
+    } else {
+        let day = match &query.dt {
+            Some(dt) => NaiveDate::parse_from_str(dt.get(..10).unwrap_or(dt), "%Y-%m-%d").ok(),
+            None => get_bookmarks(
+                &state.pool,
+                BookmarkQuery {
+                    limit: Some(1),
+                    ..bookmark_query.clone()
+                },
+            )
+            .await
+            .first()
+            .and_then(|latest| DateTime::parse_from_rfc3339(&latest.date_added).ok())
+            .map(|latest| latest.with_timezone(&Utc).date_naive()),
+        };
+        let Some(day) = day else {
+            return posts(
+                &query,
+                PinboardPosts {
+                    date: String::new(),
+                    user,
+                    posts: vec![],
+                },
+            );
+        };
+        bookmark_query.added_since = Some(format!("{day}T00:00:00Z"));
+        bookmark_query.added_before = day
+            .checked_add_days(Days::new(1))
+            .map(|next| format!("{next}T00:00:00Z"));
+    }
+
+    let bookmarks = get_bookmarks(&state.pool, bookmark_query).await;
+    let date = bookmarks
+        .first()
+        .map(|bookmark| pinboard_time(&bookmark.date_added))
+        .unwrap_or_default();
+    posts(
+        &query,
+        PinboardPosts {
+            date,
+            user,
+            posts: bookmarks.into_iter().map(Into::into).collect(),
+        },
+    )
+}
+
+async fn handle_posts_recent(
+    State(state): State<Arc<AppState>>,
+    Extension(Identity(user)): Extension<Identity>,
+    Query(query): Query<PinboardQuery>,
+) -> Response {
+    let mut tags = split_tags(query.tag.as_deref());
+    tags.truncate(MAX_FILTER_TAGS);
+    let bookmarks = get_bookmarks(
+        &state.pool,
+        BookmarkQuery {
+            tag: tags,
+            limit: Some(query.count.unwrap_or(DEFAULT_RECENT).clamp(1, MAX_RECENT)),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let date = bookmarks
+        .first()
+        .map(|bookmark| pinboard_time(&bookmark.date_added))
+        .unwrap_or_default();
+    posts(
+        &query,
+        PinboardPosts {
+            date,
+            user,
+            posts: bookmarks.into_iter().map(Into::into).collect(),
+        },
+    )
+}
+
+// The tags with the number of bookmarks having them
+async fn handle_tags_get(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PinboardQuery>,
+) -> Response {
+    let tags: Vec<(String, i64)> = match sqlx::query_as(
+        r"
+            SELECT tags.name, COUNT(post_tag.post_id)
+                FROM tags
+                JOIN post_tag ON post_tag.tag_id = tags.id
+                GROUP BY tags.id
+                ORDER BY tags.name
+        ",
+    )
+    .fetch_all(&state.pool)
+    .await
+    {
+        Ok(tags) => tags,
+        Err(err) => {
+            error!("Failed to get tags: {}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if query.json() {
+        return Json(tags.into_iter().collect::<BTreeMap<_, _>>()).into_response();
+    }
+    let mut result = vec!["<tags>".to_owned()];
+    for (tag, count) in tags {
+        result.push(format!(
+            r#"<tag count="{count}" tag="{}" />"#,
+            escape_html(&tag)
+        ));
+    }
+    result.push("</tags>".to_owned());
+    xml(&result.join("\n"))
+}
+
+// Renames the tag, or merges it into the one with the new name, as PUT /api/tags/<name>
+async fn handle_tags_rename(
+    State(state): State<Arc<AppState>>,
+    Extension(Identity(user)): Extension<Identity>,
+    Query(query): Query<PinboardQuery>,
+) -> Response {
+    let old = query.old.as_deref().unwrap_or_default().trim();
+    let new = query.new.as_deref().unwrap_or_default().trim();
+    if !is_valid_tag_name(new) {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    if get_smart_tags(&state.pool).await.contains_key(new) {
+        return StatusCode::CONFLICT.into_response();
+    }
+    let tag = match get_tag(&state, old).await {
+        Ok(Some(tag)) => tag,
+        Ok(None) => return result_code(&query, "item not found"),
+        Err(status) => return status.into_response(),
+    };
+
+    match rename_tag(&state, &user, &tag, new).await {
+        Ok(_) => {
+            info!("renamed tag: {} -> {}", old, new);
+            // unlike the other changes, without a code
+            if query.json() {
+                Json(BTreeMap::from([("result", "done")])).into_response()
+            } else {
+                xml("<result>done</result>")
+            }
+        }
+        Err(err) => {
+            error!("Failed to rename tag: {} ({})", old, err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app, setup_db};
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_pinboard_api() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        let auth = format!("auth_token=me:{TOKEN}");
+
+        let (status, body) = get(format!(
+            "/pinboard/v1/posts/add?{auth}&url=https://example.com/a&description=A&extended=d&tags=x+y&toread=yes"
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.ends_with(r#"<result code="done" />"#), "{body}");
+
+        let (_, body) = get(format!(
+            "/pinboard/v1/posts/add?{auth}&url=https://example.com/a&description=B&replace=no&format=json"
+        ))
+        .await;
+        assert_eq!(body, r#"{"result_code":"item already exists"}"#);
+
+        let (_, body) = get(format!("/pinboard/v1/posts/recent?{auth}&format=json")).await;
+        let recent: PinboardPosts = serde_json::from_str(&body).unwrap();
+        assert_eq!(recent.user, "token");
+        assert_eq!(recent.posts.len(), 1);
+        assert_eq!(recent.posts[0].href, "https://example.com/a");
+        assert_eq!(recent.posts[0].description, "A");
+        assert_eq!(recent.posts[0].extended, "d");
+        assert_eq!(recent.posts[0].tags, "x y");
+        assert_eq!(recent.posts[0].toread, "yes");
+        assert_eq!(recent.posts[0].hash.len(), 32);
+
+        let (_, body) = get(format!("/pinboard/v1/posts/get?{auth}&tag=x&format=json")).await;
+        let on_day: PinboardPosts = serde_json::from_str(&body).unwrap();
+        assert_eq!(on_day.posts.len(), 1);
+        assert_eq!(on_day.date, recent.posts[0].time);
+        let (_, body) = get(format!("/pinboard/v1/posts/get?{auth}&dt=2000-01-01")).await;
+        assert!(body.contains(r#"<posts dt="" user="token">"#), "{body}");
+        assert!(!body.contains("<post "), "{body}");
+
+        let (_, body) = get(format!("/pinboard/v1/tags/rename?{auth}&old=x&new=z")).await;
+        assert!(body.ends_with("<result>done</result>"), "{body}");
+        let (_, body) = get(format!("/pinboard/v1/tags/get?{auth}&format=json")).await;
+        assert_eq!(body, r#"{"y":1,"z":1}"#);
+        let (_, body) = get(format!("/pinboard/v1/tags/get?{auth}")).await;
+        assert!(body.contains(r#"<tag count="1" tag="z" />"#), "{body}");
+
+        for code in ["done", "item not found"] {
+            let (_, body) = get(format!(
+                "/pinboard/v1/posts/delete?{auth}&url=https://example.com/a&format=json"
+            ))
+            .await;
+            assert_eq!(body, format!(r#"{{"result_code":"{code}"}}"#));
+        }
+
+        // the changes are audited, and the delete can be undone
+        let routes: Vec<(String, String)> =
+            sqlx::query_as("SELECT route, ids FROM audit ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        // the only bookmark
+        let id = "1".to_owned();
+        assert_eq!(
+            routes,
+            vec![
+                ("/pinboard/v1/posts/add".to_owned(), id.clone()),
+                ("/pinboard/v1/posts/add".to_owned(), String::new()),
+                ("/pinboard/v1/tags/rename".to_owned(), String::new()),
+                ("/pinboard/v1/posts/delete".to_owned(), id.clone()),
+                ("/pinboard/v1/posts/delete".to_owned(), String::new()),
+            ]
+        );
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/undo")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let (_, body) = get(format!("/pinboard/v1/posts/recent?{auth}&format=json")).await;
+        assert_eq!(
+            serde_json::from_str::<PinboardPosts>(&body)
+                .unwrap()
+                .posts
+                .len(),
+            1
+        );
+
+        let (status, _) = get("/pinboard/v1/posts/recent?auth_token=me:wrong".to_owned()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,468 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Smart tags are virtual tags backed by a listing query, e.g. `long-unread` for
+//! `unread=yes&added_before=90d`. They're listed with the other tags and filtering on one
+//! matches the bookmarks matching its query.
+
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use chrono::{TimeZone, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+
+use super::bookmarks::{push_filters, BookmarkQuery};
+use super::tags::{is_valid_tag_name, TagResponse, TagsResponse};
+
+#[derive(Debug, sqlx::FromRow)]
+struct SmartTagDb {
+    id: i64,
+    name: String,
+    query: String,
+    date_added: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, sqlx::FromRow)]
+pub(crate) struct SmartTagCount {
+    pub(crate) name: String,
+    pub(crate) bookmark_count: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct SmartTagCountsResponse {
+    pub(crate) count: usize,
+    pub(crate) results: Vec<SmartTagCount>,
+}
+
+// smart tags counted per query, SQLite limits the number of SELECTs in a UNION
+const COUNTS_PER_QUERY: usize = 100;
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct SmartTagRequest {
+    pub(crate) name: String,
+    pub(crate) query: String,
+}
+
+impl From<SmartTagDb> for TagResponse {
+    fn from(val: SmartTagDb) -> Self {
+        let added = Utc.timestamp_opt(val.date_added, 0).unwrap();
+
+        TagResponse {
+            // negative to not clash with the ids of the real tags
+            id: -val.id,
+            name: val.name,
+            date_added: added.to_rfc3339(),
+            is_virtual: true,
+            query: Some(val.query),
+            last_used: None,
+            orphaned: false,
+        }
+    }
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(handle_get_smart_tags).post(handle_post_smart_tag))
+        .route("/counts", get(handle_get_smart_tag_counts))
+        .route("/{name}", delete(handle_delete_smart_tag))
+        .with_state(state)
+}
+
+// The smart tags changed after the revision, see tags::handle_get_tags
+#[instrument(level = "debug", name = "db.fetch_smart_tags", skip(pool))]
+async fn fetch_smart_tags(
+    pool: &SqlitePool,
+    since_revision: i64,
+) -> Result<Vec<SmartTagDb>, sqlx::Error> {
+    sqlx::query_as::<_, SmartTagDb>(
+        r"
+            SELECT smart_tags.*
+                FROM smart_tags
+                LEFT JOIN tag_revisions ON tag_revisions.name = smart_tags.name
+                WHERE COALESCE(tag_revisions.revision, 0) > $1
+                ORDER BY smart_tags.name
+        ",
+    )
+    .bind(since_revision)
+    .fetch_all(pool)
+    .await
+}
+
+pub(crate) async fn list_smart_tags(
+    pool: &SqlitePool,
+    since_revision: i64,
+) -> Result<Vec<TagResponse>, StatusCode> {
+    match fetch_smart_tags(pool, since_revision).await {
+        Ok(rows) => Ok(rows.into_iter().map(Into::into).collect()),
+        Err(err) => {
+            error!("Failed to get smart tags: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The queries of the smart tags by name. A smart tag with a query that no longer parses is
+/// left out, i.e. filtering on it matches it as a normal tag.
+pub(crate) async fn get_smart_tags(pool: &SqlitePool) -> HashMap<String, BookmarkQuery> {
+    match fetch_smart_tags(pool, -1).await {
+        Ok(rows) => rows
+            .into_iter()
+            .filter_map(|row| Some((row.name, BookmarkQuery::parse(&row.query)?)))
+            .collect(),
+        Err(err) => {
+            error!("Failed to get smart tags: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+/// The query with the tag renamed in `tag`, `tag_any` and as `#tag` in `q`, or None if the tag
+/// isn't used in it.
+pub(crate) fn rename_tag_in_query(query: &str, old: &str, new: &str) -> Option<String> {
+    let mut changed = false;
+    let mut rename = |tag: &str| {
+        if tag == old {
+            changed = true;
+            new.to_owned()
+        } else {
+            tag.to_owned()
+        }
+    };
+
+    let pairs: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+        .map(|(key, value)| {
+            let value = match key.as_ref() {
+                "tag" => rename(&value),
+                "tag_any" => value
+                    .split(',')
+                    .map(&mut rename)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                "q" => value
+                    .split_whitespace()
+                    .map(|token| match token.strip_prefix('#') {
+                        Some(tag) => format!("#{}", rename(tag)),
+                        None => token.to_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => value.into_owned(),
+            };
+            (key.into_owned(), value)
+        })
+        .collect();
+
+    changed.then(|| {
+        url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish()
+    })
+}
+
+/// Renames the tag in the queries of the smart tags, returning the names of those changed and
+/// their queries before.
+pub(crate) async fn rename_tag(
+    conn: &mut SqliteConnection,
+    old: &str,
+    new: &str,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, SmartTagDb>("SELECT * FROM smart_tags ORDER BY name")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut renamed = vec![];
+    for row in rows {
+        if let Some(query) = rename_tag_in_query(&row.query, old, new) {
+            sqlx::query("UPDATE smart_tags SET query = $1 WHERE id = $2")
+                .bind(query)
+                .bind(row.id)
+                .execute(&mut *conn)
+                .await?;
+            renamed.push((row.name, row.query));
+        }
+    }
+    Ok(renamed)
+}
+
+async fn handle_get_smart_tags(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TagsResponse>, StatusCode> {
+    let tags = list_smart_tags(&state.pool, -1).await?;
+    Ok(Json(TagsResponse {
+        count: tags.len(),
+        results: tags,
+        deleted: None,
+    }))
+}
+
+// The number of bookmarks matching each smart tag, as listed when filtering on it, counted with
+// a UNION of one SELECT per smart tag
+async fn handle_get_smart_tag_counts(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<SmartTagCountsResponse>, StatusCode> {
+    let mut smart_tags: Vec<(String, BookmarkQuery)> =
+        get_smart_tags(&state.pool).await.into_iter().collect();
+    smart_tags.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut counts = vec![];
+    for chunk in smart_tags.chunks_mut(COUNTS_PER_QUERY) {
+        let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new("");
+        for (i, (name, query)) in chunk.iter_mut().enumerate() {
+            if i > 0 {
+                sql.push(" UNION ALL ");
+            }
+            // the main listing only has the bookmarks that aren't archived
+            query.archived.get_or_insert(false);
+            sql.push("SELECT ");
+            sql.push_bind(name.clone());
+            sql.push(" AS name, COUNT(*) AS bookmark_count FROM posts");
+            push_filters(&mut sql, query);
+        }
+
+        match sql
+            .build_query_as::<SmartTagCount>()
+            .fetch_all(&state.pool)
+            .await
+        {
+            Ok(rows) => counts.extend(rows),
+            Err(err) => {
+                error!("Failed to count smart tags: {}", err);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    Ok(Json(SmartTagCountsResponse {
+        count: counts.len(),
+        results: counts,
+    }))
+}
+
+// Creates or replaces a smart tag. The name can't be used by a real tag.
+async fn handle_post_smart_tag(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SmartTagRequest>,
+) -> Result<(StatusCode, Json<TagResponse>), StatusCode> {
+    let name = payload.name.trim();
+    let query = payload.query.trim().trim_start_matches('?');
+    if !is_valid_tag_name(name) || BookmarkQuery::parse(query).is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tags WHERE name = $1")
+        .bind(name)
+        .fetch_one(&state.pool)
+        .await
+    {
+        Ok(0) => {}
+        Ok(_) => return Err(StatusCode::CONFLICT),
+        Err(err) => {
+            error!("Failed to get tag: {} ({})", name, err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    match sqlx::query_as::<_, SmartTagDb>(
+        r"
+            INSERT INTO smart_tags (name, query, date_added) VALUES ($1, $2, unixepoch())
+                ON CONFLICT(name) DO UPDATE SET query = excluded.query
+                RETURNING *
+        ",
+    )
+    .bind(name)
+    .bind(query)
+    .fetch_one(&state.pool)
+    .await
+    {
+        Ok(tag) => {
+            info!("added smart tag: {} ({})", tag.name, tag.query);
+            Ok((StatusCode::CREATED, Json(tag.into())))
+        }
+        Err(err) => {
+            error!("Failed to add smart tag: {} ({})", name, err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn handle_delete_smart_tag(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    match sqlx::query("DELETE FROM smart_tags WHERE name = $1")
+        .bind(&name)
+        .execute(&state.pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND,
+        Ok(_) => {
+            info!("deleted smart tag: {}", name);
+            StatusCode::NO_CONTENT
+        }
+        Err(err) => {
+            error!("Failed to delete smart tag: {} ({})", name, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::bookmarks::tests::{add_post, get_random_string};
+    use crate::api::handlers::bookmarks::BookmarksResponse;
+    use crate::{app, setup_db};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_smart_tags() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let tag = get_random_string(5);
+        let old_unread = add_post(app.clone(), None, true).await;
+        add_post(app.clone(), None, true).await;
+        let old_read = add_post(app.clone(), Some(vec![tag.clone()]), false).await;
+        sqlx::query(
+            "UPDATE posts SET date_added = unixepoch('now', '-100 days') WHERE url IN ($1, $2)",
+        )
+        .bind(&old_unread.bookmark.url)
+        .bind(&old_read.bookmark.url)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let post_smart_tag = |app: Router, name: &str, query: &str| {
+            let body = serde_json::json!({"name": name, "query": query}).to_string();
+            async move {
+                app.oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/smart_tags")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let response =
+            post_smart_tag(app.clone(), "long-unread", "unread=yes&added_before=90d").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = post_smart_tag(app.clone(), &tag, "unread=yes").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = post_smart_tag(app.clone(), "two words", "unread=yes").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/tags")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let tags: TagsResponse = serde_json::from_slice(&body).unwrap();
+        let smart = tags
+            .results
+            .iter()
+            .find(|t| t.name == "long-unread")
+            .unwrap();
+        assert!(smart.is_virtual);
+        assert_eq!(smart.query.as_deref(), Some("unread=yes&added_before=90d"));
+        assert!(tags.results.iter().any(|t| t.name == tag && !t.is_virtual));
+
+        for (params, urls) in [
+            ("tag=long-unread", vec![&old_unread.bookmark.url]),
+            ("q=%23long-unread", vec![&old_unread.bookmark.url]),
+            (
+                &format!("tag_any=long-unread,{tag}"),
+                vec![&old_unread.bookmark.url, &old_read.bookmark.url],
+            ),
+            (&format!("tag=long-unread&tag={tag}"), vec![]),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/bookmarks?{params}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let posts: BookmarksResponse = serde_json::from_slice(&body).unwrap();
+            let mut found: Vec<&String> = posts.results.iter().map(|post| &post.url).collect();
+            found.sort();
+            let mut urls = urls;
+            urls.sort();
+            assert_eq!(found, urls, "{params}");
+        }
+
+        let response = post_smart_tag(app.clone(), "all-unread", "unread=yes").await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/smart_tags/counts")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let counts: SmartTagCountsResponse = serde_json::from_slice(&body).unwrap();
+        let counts: Vec<(&str, i64)> = counts
+            .results
+            .iter()
+            .map(|count| (count.name.as_str(), count.bookmark_count))
+            .collect();
+        assert_eq!(counts, vec![("all-unread", 2), ("long-unread", 1)]);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/api/smart_tags/long-unread")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+}
@@ -2,21 +2,36 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
+use crate::api::etag::etag;
+use crate::audit::Identity;
 use crate::{AppState, PostID, TagID};
-use axum::extract::State;
-use axum::routing::get;
-use axum::{Json, Router};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{middleware, Extension, Json, Router};
 use chrono::{TimeZone, Utc};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, SqlitePool};
 use std::sync::Arc;
-use tracing::error;
+use tracing::{error, info, instrument};
+
+use super::bookmarks::timestamp_before;
+use super::smart_tags;
+use super::undo::TagMerge;
 
 #[derive(Debug, sqlx::FromRow, Deserialize, Serialize)]
 pub(crate) struct TagDb {
     pub(crate) id: TagID,
     pub(crate) name: String,
     pub(crate) date_added: i64,
+    // date_added of the newest bookmark with the tag, only selected when listing tags
+    #[sqlx(default)]
+    pub(crate) last_used: Option<i64>,
+    // without bookmarks, only selected when listing tags
+    #[sqlx(default)]
+    pub(crate) orphaned: bool,
 }
 
 #[derive(sqlx::FromRow, Deserialize, Serialize, Debug, Default)]
@@ -24,47 +39,224 @@ pub(crate) struct TagResponse {
     pub(crate) id: PostID,
     pub(crate) name: String,
     pub(crate) date_added: String,
+    // smart tags, see smart_tags.rs
+    #[serde(
+        default,
+        rename = "virtual",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    #[sqlx(skip)]
+    pub(crate) is_virtual: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub(crate) query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(skip)]
+    pub(crate) last_used: Option<String>,
+    // a tag without bookmarks, left by a client creating tags or by a failed removal
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[sqlx(skip)]
+    pub(crate) orphaned: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct TagsResponse {
-    count: usize,
+    pub(crate) count: usize,
     pub(crate) results: Vec<TagResponse>,
+    // names of the tags deleted since the revision given as since_revision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) deleted: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TagsQuery {
+    since_revision: Option<i64>,
+    // sort=last_used for the least recently used first, sort=-last_used for the most
+    sort: Option<String>,
+    // unused_since=365d or unused_since=2024-01-01T00:00:00Z, tags without bookmarks included
+    unused_since: Option<String>,
+    // orphaned=hide without the tags without bookmarks, orphaned=only for only them
+    orphaned: Option<String>,
+}
+
+/// The revision of the tag list, bumped when a tag is added or deleted. Tags added before
+/// revisions were kept have revision 0.
+const X_TAGS_REVISION: HeaderName = HeaderName::from_static("x-tags-revision");
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TagCountResponse {
+    #[serde(flatten)]
+    pub(crate) tag: TagResponse,
+    pub(crate) bookmark_count: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TagCleanupResponse {
+    pub(crate) count: usize,
+    // names of the deleted tags
+    pub(crate) deleted: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, sqlx::FromRow)]
+pub(crate) struct TagNode {
+    pub(crate) name: String,
+    pub(crate) bookmark_count: i64,
+}
+
+// two tags on the same bookmarks, source before target by name
+#[derive(Deserialize, Serialize, Debug, sqlx::FromRow)]
+pub(crate) struct TagEdge {
+    pub(crate) source: String,
+    pub(crate) target: String,
+    // number of bookmarks with both tags
+    pub(crate) weight: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TagGraphResponse {
+    pub(crate) nodes: Vec<TagNode>,
+    pub(crate) edges: Vec<TagEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TagGraphQuery {
+    // leaves out the edges of tags sharing fewer bookmarks
+    min_weight: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TagRequest {
+    pub(crate) name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TagRenameResponse {
+    #[serde(flatten)]
+    pub(crate) tag: TagResponse,
+    // renamed to a tag that already existed, which now has the bookmarks of both
+    pub(crate) merged: bool,
+    // the smart tags with the tag in their query
+    pub(crate) smart_tags: Vec<String>,
+}
+
+/// Tags can't be empty or contain whitespace, which separates tags in linkding, or commas, which
+/// separate them in exports and `tag_any`.
+pub(crate) fn is_valid_tag_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace) && !name.contains(',')
 }
 
 impl From<TagDb> for TagResponse {
     fn from(val: TagDb) -> Self {
         let added = Utc.timestamp_opt(val.date_added, 0).unwrap();
+        let last_used = val
+            .last_used
+            .and_then(|last_used| Utc.timestamp_opt(last_used, 0).single());
 
         TagResponse {
             id: val.id,
             name: val.name,
             date_added: added.to_rfc3339(),
+            last_used: last_used.map(|last_used| last_used.to_rfc3339()),
+            orphaned: val.orphaned,
+            ..Default::default()
         }
     }
 }
 pub fn configure(state: Arc<AppState>) -> Router {
     Router::new()
         .route("/", get(handle_get_tags))
+        .route("/", post(handle_post_tag))
+        .route("/cleanup", post(handle_post_cleanup))
+        .route("/graph", get(handle_get_graph))
+        .route("/{name}", get(handle_get_tag))
+        .route("/{name}", put(handle_put_tag))
+        .layer(middleware::from_fn(etag))
         .with_state(state)
 }
 
 async fn handle_get_tags(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<TagsResponse>, StatusCode> {
-    let sql = "SELECT * FROM tags";
+    Query(query): Query<TagsQuery>,
+) -> Result<Response, StatusCode> {
+    // read first, a tag changed while listing is then listed again on the next sync
+    let revision =
+        sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(revision), 0) FROM tag_revisions")
+            .fetch_one(&state.pool)
+            .await
+            .map_err(|err| {
+                error!("Failed to get tag revision: {}", err);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    let since = query.since_revision.unwrap_or(-1);
+    let unused_since = match query.unused_since.as_deref() {
+        Some(unused_since) => Some(timestamp_before(unused_since).ok_or(StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+    let orphaned = match query.orphaned.as_deref() {
+        Some("hide") => Some(false),
+        Some("only") => Some(true),
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+        None => None,
+    };
 
-    match sqlx::query_as::<_, TagDb>(sql).fetch_all(&state.pool).await {
+    let mut sql: QueryBuilder<'_, sqlx::Sqlite> = QueryBuilder::new(
+        r"
+            SELECT tags.*, (
+                SELECT MAX(posts.date_added)
+                    FROM post_tag
+                    JOIN posts ON posts.id = post_tag.post_id
+                    WHERE post_tag.tag_id = tags.id
+                ) AS last_used,
+                NOT EXISTS (SELECT 1 FROM post_tag WHERE post_tag.tag_id = tags.id) AS orphaned
+                FROM tags
+                LEFT JOIN tag_revisions ON tag_revisions.name = tags.name
+                WHERE COALESCE(tag_revisions.revision, 0) >
+        ",
+    );
+    sql.push_bind(since);
+    if let Some(unused_since) = unused_since {
+        sql.push(" AND COALESCE(last_used, 0) < ");
+        sql.push_bind(unused_since);
+    }
+    if let Some(orphaned) = orphaned {
+        sql.push(" AND orphaned = ");
+        sql.push_bind(orphaned);
+    }
+    sql.push(match query.sort.as_deref() {
+        Some("last_used") => " ORDER BY last_used, tags.name",
+        Some("-last_used") => " ORDER BY last_used DESC, tags.name",
+        _ => "",
+    });
+
+    match sql.build_query_as::<TagDb>().fetch_all(&state.pool).await {
         Ok(rows) => {
             let mut tags = vec![];
             for row in rows {
                 let tag: TagResponse = row.into();
                 tags.push(tag);
             }
-            Ok(Json(TagsResponse {
+            // smart tags are never used, so not listed when looking for unused tags, but they
+            // aren't orphaned either
+            if unused_since.is_none() && orphaned != Some(true) {
+                tags.extend(smart_tags::list_smart_tags(&state.pool, since).await?);
+            }
+
+            let deleted = if query.since_revision.is_some() {
+                Some(deleted_tags(&state, since).await?)
+            } else {
+                None
+            };
+
+            let mut response = Json(TagsResponse {
                 count: tags.len(),
                 results: tags,
-            }))
+                deleted,
+            })
+            .into_response();
+            response
+                .headers_mut()
+                .insert(X_TAGS_REVISION, HeaderValue::from(revision));
+            Ok(response)
         }
 
         Err(err) => {
@@ -73,3 +265,758 @@ async fn handle_get_tags(
         }
     }
 }
+
+async fn deleted_tags(state: &AppState, since: i64) -> Result<Vec<String>, StatusCode> {
+    sqlx::query_scalar::<_, String>(
+        "SELECT name FROM tag_revisions WHERE deleted AND revision > $1 ORDER BY name",
+    )
+    .bind(since)
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|err| {
+        error!("Failed to get deleted tags: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[instrument(level = "debug", name = "db.get_tag", skip(state))]
+pub(crate) async fn get_tag(state: &AppState, name: &str) -> Result<Option<TagDb>, StatusCode> {
+    sqlx::query_as::<_, TagDb>("SELECT * FROM tags WHERE name = $1")
+        .bind(name)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|err| {
+            error!("Failed to get tag: {} ({})", name, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+// Creates a tag without any bookmarks. As in linkding, an existing tag is returned as is.
+async fn handle_post_tag(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<TagRequest>,
+) -> Result<(StatusCode, Json<TagResponse>), StatusCode> {
+    let name = payload.name.trim();
+    if !is_valid_tag_name(name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(tag) = get_tag(&state, name).await? {
+        return Ok((StatusCode::CREATED, Json(tag.into())));
+    }
+
+    match sqlx::query_as::<_, TagDb>(
+        "INSERT INTO tags (name, date_added) VALUES ($1, unixepoch()) RETURNING *",
+    )
+    .bind(name)
+    .fetch_one(&state.pool)
+    .await
+    {
+        Ok(tag) => {
+            info!("added tag: {}", tag.name);
+            Ok((StatusCode::CREATED, Json(tag.into())))
+        }
+        Err(err) => {
+            error!("Failed to add tag: {} ({})", name, err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Deletes the tags without bookmarks
+async fn handle_post_cleanup(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TagCleanupResponse>, StatusCode> {
+    match sqlx::query_scalar::<_, String>(
+        r"
+            DELETE FROM tags
+                WHERE NOT EXISTS (SELECT 1 FROM post_tag WHERE post_tag.tag_id = tags.id)
+                RETURNING name
+        ",
+    )
+    .fetch_all(&state.pool)
+    .await
+    {
+        Ok(mut deleted) => {
+            deleted.sort();
+            info!("deleted {} orphaned tags", deleted.len());
+            Ok(Json(TagCleanupResponse {
+                count: deleted.len(),
+                deleted,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to delete orphaned tags: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The tags with bookmarks, and how many bookmarks each pair of them share
+pub(crate) async fn tag_graph(
+    pool: &SqlitePool,
+    min_weight: i64,
+) -> Result<TagGraphResponse, sqlx::Error> {
+    let nodes = sqlx::query_as::<_, TagNode>(
+        r"
+            SELECT tags.name, COUNT(*) AS bookmark_count
+                FROM tags
+                JOIN post_tag ON post_tag.tag_id = tags.id
+                GROUP BY tags.id
+                ORDER BY tags.name
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let edges = sqlx::query_as::<_, TagEdge>(
+        r"
+            SELECT MIN(a.name, b.name) AS source, MAX(a.name, b.name) AS target, COUNT(*) AS weight
+                FROM post_tag AS pa
+                JOIN post_tag AS pb ON pb.post_id = pa.post_id AND pb.tag_id > pa.tag_id
+                JOIN tags AS a ON a.id = pa.tag_id
+                JOIN tags AS b ON b.id = pb.tag_id
+                GROUP BY pa.tag_id, pb.tag_id
+                HAVING weight >= $1
+                ORDER BY source, target
+        ",
+    )
+    .bind(min_weight)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(TagGraphResponse { nodes, edges })
+}
+
+/// The tag graph in the DOT language of Graphviz, the edges thicker the more bookmarks share them
+pub(crate) fn to_dot(graph: &TagGraphResponse) -> String {
+    let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut result = vec!["graph tags {".to_owned()];
+    for node in &graph.nodes {
+        result.push(format!(
+            "  {} [label={}];",
+            quote(&node.name),
+            quote(&format!("{} ({})", node.name, node.bookmark_count))
+        ));
+    }
+    for edge in &graph.edges {
+        result.push(format!(
+            "  {} -- {} [weight={}, penwidth={}];",
+            quote(&edge.source),
+            quote(&edge.target),
+            edge.weight,
+            edge.weight
+        ));
+    }
+    result.push("}".to_owned());
+    result.join("\n")
+}
+
+async fn handle_get_graph(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TagGraphQuery>,
+) -> Result<Json<TagGraphResponse>, StatusCode> {
+    match tag_graph(&state.pool, query.min_weight.unwrap_or(1)).await {
+        Ok(graph) => Ok(Json(graph)),
+        Err(err) => {
+            error!("Failed to get the tag graph: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn handle_get_tag(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<TagCountResponse>, StatusCode> {
+    let Some(tag) = get_tag(&state, &name).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM post_tag WHERE tag_id = $1")
+        .bind(tag.id)
+        .fetch_one(&state.pool)
+        .await
+    {
+        Ok(bookmark_count) => Ok(Json(TagCountResponse {
+            tag: tag.into(),
+            bookmark_count,
+        })),
+        Err(err) => {
+            error!("Failed to count bookmarks for tag: {} ({})", name, err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Renames the tag, or merges it into the tag with the new name if there is one. The smart tags
+// using the tag are updated in the same transaction.
+async fn handle_put_tag(
+    State(state): State<Arc<AppState>>,
+    Extension(Identity(identity)): Extension<Identity>,
+    Path(name): Path<String>,
+    Json(payload): Json<TagRequest>,
+) -> Result<Json<TagRenameResponse>, StatusCode> {
+    let new_name = payload.name.trim();
+    if !is_valid_tag_name(new_name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let Some(tag) = get_tag(&state, &name).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if smart_tags::get_smart_tags(&state.pool)
+        .await
+        .contains_key(new_name)
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    match rename_tag(&state, &identity, &tag, new_name).await {
+        Ok(response) => {
+            info!("renamed tag: {} -> {}", name, new_name);
+            Ok(Json(response))
+        }
+        Err(err) => {
+            error!("Failed to rename tag: {} ({})", name, err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub(crate) async fn rename_tag(
+    state: &AppState,
+    identity: &str,
+    tag: &TagDb,
+    new_name: &str,
+) -> Result<TagRenameResponse, sqlx::Error> {
+    let mut tx = state.pool.begin().await?;
+
+    let existing = sqlx::query_as::<_, TagDb>("SELECT * FROM tags WHERE name = $1")
+        .bind(new_name)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let (renamed, merge) = match existing {
+        Some(existing) if existing.id != tag.id => {
+            let merge = TagMerge::new(&mut tx, tag, &existing).await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO post_tag (post_id, tag_id) SELECT post_id, $1 FROM post_tag WHERE tag_id = $2",
+            )
+            .bind(existing.id)
+            .bind(tag.id)
+            .execute(&mut *tx)
+            .await?;
+            // the links to the old tag are removed with it
+            sqlx::query("DELETE FROM tags WHERE id = $1")
+                .bind(tag.id)
+                .execute(&mut *tx)
+                .await?;
+            (existing, Some(merge))
+        }
+        _ => {
+            let renamed =
+                sqlx::query_as::<_, TagDb>("UPDATE tags SET name = $1 WHERE id = $2 RETURNING *")
+                    .bind(new_name)
+                    .bind(tag.id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+            (renamed, None)
+        }
+    };
+
+    let smart_tags = smart_tags::rename_tag(&mut tx, &tag.name, new_name).await?;
+
+    // kept to be undone, see undo.rs
+    let merged = merge.is_some();
+    if let Some(merge) = merge {
+        merge.record(&mut tx, identity, &smart_tags).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(TagRenameResponse {
+        tag: renamed.into(),
+        merged,
+        smart_tags: smart_tags.into_iter().map(|(name, _)| name).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::bookmarks::tests::{add_post, get_random_string};
+    use crate::api::handlers::bookmarks::{BookmarkRequest, BookmarkResponse};
+    use crate::{app, setup_db};
+    use axum::{body::Body, http::Request};
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_post_and_get_tag() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let tag = get_random_string(5);
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/tags")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(format!(r#"{{"name": "{tag}"}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: TagResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created.name, tag);
+
+        let get_tag = |app: Router, name: String| async move {
+            app.oneshot(
+                Request::builder()
+                    .uri(format!("/api/tags/{name}"))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+        };
+
+        let response = get_tag(app.clone(), tag.clone()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: TagCountResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(res.tag.id, created.id);
+        assert_eq!(res.bookmark_count, 0);
+
+        add_post(app.clone(), Some(vec![tag.clone()]), false).await;
+
+        let response = get_tag(app.clone(), tag.clone()).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: TagCountResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(res.tag.id, created.id);
+        assert_eq!(res.bookmark_count, 1);
+
+        let response = get_tag(app.clone(), get_random_string(6)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/tags")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(r#"{"name": "two words"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_tag_names() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let tags = vec!["c++".to_owned(), "\"quoted\"".to_owned(), "ö".to_owned()];
+        let created = add_post(app.clone(), Some(tags.clone()), false).await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut res: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        res.tag_names.sort();
+        assert_eq!(res.tag_names, vec!["\"quoted\"", "c++", "ö"]);
+
+        // bookmarks without tags get an empty list
+        let created = add_post(app.clone(), Some(vec![]), false).await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        assert!(res.tag_names.is_empty());
+
+        for tag in ["a,b", "a b", ""] {
+            let bookmark_req = BookmarkRequest {
+                url: get_random_string(5),
+                title: get_random_string(5),
+                tag_names: Some(vec![tag.to_owned()]),
+                ..Default::default()
+            };
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                        .body(Body::from(serde_json::to_string(&bookmark_req).unwrap()))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{tag}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tags_since_revision() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let get_tags = |app: Router, params: String| async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/api/tags?{params}"))
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let revision: i64 = response.headers()["x-tags-revision"]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let tags: TagsResponse = serde_json::from_slice(&body).unwrap();
+            (revision, tags)
+        };
+
+        let tag1 = get_random_string(5);
+        let tag2 = get_random_string(6);
+        let first = add_post(app.clone(), Some(vec![tag1.clone()]), false).await;
+        let (revision, tags) = get_tags(app.clone(), String::new()).await;
+        assert_eq!(tags.count, 1);
+        assert!(tags.deleted.is_none());
+
+        let (same, tags) = get_tags(app.clone(), format!("since_revision={revision}")).await;
+        assert_eq!(same, revision);
+        assert_eq!(tags.count, 0);
+        assert_eq!(tags.deleted, Some(vec![]));
+
+        // tag1 is replaced, and as no other bookmark uses it, deleted
+        let body = axum::body::to_bytes(first.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        let bookmark = serde_json::to_string(&BookmarkRequest {
+            tag_names: Some(vec![tag2.clone()]),
+            ..first.bookmark
+        })
+        .unwrap();
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("PUT")
+                    .uri(format!("/api/bookmarks/{}", created.id))
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(bookmark))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+
+        let (newer, tags) = get_tags(app.clone(), format!("since_revision={revision}")).await;
+        assert!(newer > revision);
+        assert_eq!(tags.count, 1);
+        assert_eq!(tags.results[0].name, tag2);
+        assert_eq!(tags.deleted, Some(vec![tag1]));
+    }
+
+    #[tokio::test]
+    async fn test_tags_last_used() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let old = get_random_string(5);
+        let recent = get_random_string(6);
+        add_post(app.clone(), Some(vec![old.clone()]), false).await;
+        add_post(app.clone(), Some(vec![recent.clone()]), false).await;
+        // two years ago
+        sqlx::query(
+            r"
+                UPDATE posts SET date_added = date_added - 2 * 365 * 24 * 60 * 60
+                    WHERE id IN (
+                        SELECT post_id FROM post_tag JOIN tags ON tags.id = tag_id WHERE name = $1
+                    )
+            ",
+        )
+        .bind(&old)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let get_tags = |params: &str| {
+            let request = Request::builder()
+                .uri(format!("/api/tags?{params}"))
+                .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, serde_json::from_slice::<TagsResponse>(&body).ok())
+            }
+        };
+
+        let (_, tags) = get_tags("sort=last_used").await;
+        let names: Vec<_> = tags.unwrap().results.into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec![old.clone(), recent.clone()]);
+
+        let (_, tags) = get_tags("sort=-last_used").await;
+        let tags = tags.unwrap();
+        assert_eq!(tags.results[0].name, recent);
+        assert!(tags.results[0].last_used.is_some());
+
+        let (_, tags) = get_tags("unused_since=365d").await;
+        let names: Vec<_> = tags.unwrap().results.into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec![old]);
+
+        let (status, _) = get_tags("unused_since=yesterday").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_tags_orphaned() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let used = get_random_string(5);
+        let orphan = get_random_string(6);
+        add_post(app.clone(), Some(vec![used.clone()]), false).await;
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/tags")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(format!(r#"{{"name": "{orphan}"}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let request = |method: &str, uri: &str| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = request("GET", "/api/tags?orphaned=only").await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let tags: TagsResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(tags.results.len(), 1);
+        assert_eq!(tags.results[0].name, orphan);
+        assert!(tags.results[0].orphaned);
+
+        let response = request("GET", "/api/tags?orphaned=hide").await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let tags: TagsResponse = serde_json::from_slice(&body).unwrap();
+        let names: Vec<_> = tags.results.into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec![used]);
+
+        let response = request("GET", "/api/tags?orphaned=yes").await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = request("POST", "/api/tags/cleanup").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let cleanup: TagCleanupResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(cleanup.deleted, vec![orphan.clone()]);
+
+        let response = request("GET", &format!("/api/tags/{orphan}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_tags_graph() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for tags in [["a", "b"], ["b", "a"], ["a", "c\""]] {
+            add_post(app.clone(), Some(tags.map(str::to_owned).to_vec()), false).await;
+        }
+
+        let get_graph = |params: &str| {
+            let request = Request::builder()
+                .uri(format!("/api/tags/graph?{params}"))
+                .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<TagGraphResponse>(&body).unwrap()
+            }
+        };
+
+        let graph = get_graph("").await;
+        let nodes: Vec<_> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.bookmark_count))
+            .collect();
+        assert_eq!(nodes, vec![("a", 3), ("b", 2), ("c\"", 1)]);
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.source.as_str(), edge.target.as_str(), edge.weight))
+            .collect();
+        assert_eq!(edges, vec![("a", "b", 2), ("a", "c\"", 1)]);
+
+        let dot = to_dot(&graph);
+        assert!(dot.contains(r#"  "a" -- "c\"" [weight=1, penwidth=1];"#));
+
+        let graph = get_graph("min_weight=2").await;
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rename_tag() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        add_post(app.clone(), Some(vec!["a".to_owned()]), true).await;
+        add_post(app.clone(), Some(vec!["b".to_owned()]), true).await;
+
+        let request = |method: &str, uri: &str, body: String| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "POST",
+                "/api/smart_tags",
+                r#"{"name": "s", "query": "tag=a&unread=yes&q=%23a+text"}"#.to_owned(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app
+            .clone()
+            .oneshot(request("PUT", "/api/tags/a", r#"{"name": "c"}"#.to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let renamed: TagRenameResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(renamed.tag.name, "c");
+        assert!(!renamed.merged);
+        assert_eq!(renamed.smart_tags, vec!["s"]);
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/smart_tags", String::new()))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let smart: TagsResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            smart.results[0].query.as_deref(),
+            Some("tag=c&unread=yes&q=%23c+text")
+        );
+
+        let response = app
+            .clone()
+            .oneshot(request("PUT", "/api/tags/c", r#"{"name": "b"}"#.to_owned()))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let merged: TagRenameResponse = serde_json::from_slice(&body).unwrap();
+        assert!(merged.merged);
+
+        let response = app
+            .clone()
+            .oneshot(request("GET", "/api/tags/b", String::new()))
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: TagCountResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(res.bookmark_count, 2);
+
+        for (name, status) in [("c", StatusCode::NOT_FOUND), ("b", StatusCode::CONFLICT)] {
+            let body = if status == StatusCode::CONFLICT {
+                r#"{"name": "s"}"#
+            } else {
+                r#"{"name": "d"}"#
+            };
+            let response = app
+                .clone()
+                .oneshot(request(
+                    "PUT",
+                    &format!("/api/tags/{name}"),
+                    body.to_owned(),
+                ))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+    }
+}
@@ -0,0 +1,294 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Templates are predefined settings for new bookmarks, applied by `POST /api/bookmarks/quick`
+//! with `?template=<name>`. E.g. a `recipe` template adding the tag `recipe` and marking the
+//! bookmark as read.
+
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use chrono::{TimeZone, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::bookmarks::BookmarkRequest;
+use super::tags::is_valid_tag_name;
+
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct TemplateDb {
+    id: i64,
+    name: String,
+    // JSON array
+    tag_names: String,
+    unread: Option<bool>,
+    description_prefix: Option<String>,
+    date_added: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub(crate) struct TemplateRequest {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) tag_names: Vec<String>,
+    // the unread state of the bookmarks, as given in the request if not set
+    pub(crate) unread: Option<bool>,
+    // added before the description
+    pub(crate) description_prefix: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TemplateResponse {
+    pub(crate) id: i64,
+    pub(crate) name: String,
+    pub(crate) tag_names: Vec<String>,
+    pub(crate) unread: Option<bool>,
+    pub(crate) description_prefix: Option<String>,
+    pub(crate) date_added: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TemplatesResponse {
+    pub(crate) count: usize,
+    pub(crate) results: Vec<TemplateResponse>,
+}
+
+impl TemplateDb {
+    fn tag_names(&self) -> Vec<String> {
+        serde_json::from_str(&self.tag_names).unwrap_or_default()
+    }
+
+    /// Adds the tags, unread state and description prefix of the template to the bookmark.
+    pub(crate) fn apply(&self, bookmark: &mut BookmarkRequest) {
+        let tags = bookmark.tag_names.get_or_insert_with(Vec::new);
+        for tag in self.tag_names() {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        if let Some(unread) = self.unread {
+            bookmark.unread = Some(unread);
+        }
+
+        if let Some(prefix) = &self.description_prefix {
+            bookmark.description = Some(match bookmark.description.as_deref() {
+                Some(description) if !description.is_empty() => format!("{prefix}{description}"),
+                _ => prefix.clone(),
+            });
+        }
+    }
+}
+
+impl From<TemplateDb> for TemplateResponse {
+    fn from(val: TemplateDb) -> Self {
+        let added = Utc.timestamp_opt(val.date_added, 0).unwrap();
+
+        TemplateResponse {
+            id: val.id,
+            tag_names: val.tag_names(),
+            name: val.name,
+            unread: val.unread,
+            description_prefix: val.description_prefix,
+            date_added: added.to_rfc3339(),
+        }
+    }
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(handle_get_templates).post(handle_post_template))
+        .route("/{name}", delete(handle_delete_template))
+        .with_state(state)
+}
+
+pub(crate) async fn get_template(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<Option<TemplateDb>, StatusCode> {
+    sqlx::query_as::<_, TemplateDb>("SELECT * FROM templates WHERE name = $1")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .map_err(|err| {
+            error!("Failed to get template: {} ({})", name, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn handle_get_templates(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TemplatesResponse>, StatusCode> {
+    match sqlx::query_as::<_, TemplateDb>("SELECT * FROM templates ORDER BY name")
+        .fetch_all(&state.pool)
+        .await
+    {
+        Ok(rows) => {
+            let templates: Vec<TemplateResponse> = rows.into_iter().map(Into::into).collect();
+            Ok(Json(TemplatesResponse {
+                count: templates.len(),
+                results: templates,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to get templates: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Creates or replaces a template
+async fn handle_post_template(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<TemplateRequest>,
+) -> Result<(StatusCode, Json<TemplateResponse>), StatusCode> {
+    let name = payload.name.trim();
+    let tag_names: Vec<&str> = payload.tag_names.iter().map(|tag| tag.trim()).collect();
+    if name.is_empty() || !tag_names.iter().all(|tag| is_valid_tag_name(tag)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match sqlx::query_as::<_, TemplateDb>(
+        r"
+            INSERT INTO templates (name, tag_names, unread, description_prefix, date_added)
+                VALUES ($1, $2, $3, $4, unixepoch())
+                ON CONFLICT(name) DO UPDATE SET
+                    tag_names = excluded.tag_names,
+                    unread = excluded.unread,
+                    description_prefix = excluded.description_prefix
+                RETURNING *
+        ",
+    )
+    .bind(name)
+    .bind(serde_json::to_string(&tag_names).unwrap_or_default())
+    .bind(payload.unread)
+    .bind(&payload.description_prefix)
+    .fetch_one(&state.pool)
+    .await
+    {
+        Ok(template) => {
+            info!("added template: {}", template.name);
+            Ok((StatusCode::CREATED, Json(template.into())))
+        }
+        Err(err) => {
+            error!("Failed to add template: {} ({})", name, err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn handle_delete_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> StatusCode {
+    match sqlx::query("DELETE FROM templates WHERE name = $1")
+        .bind(&name)
+        .execute(&state.pool)
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => StatusCode::NOT_FOUND,
+        Ok(_) => {
+            info!("deleted template: {}", name);
+            StatusCode::NO_CONTENT
+        }
+        Err(err) => {
+            error!("Failed to delete template: {} ({})", name, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::handlers::bookmarks::BookmarkResponse;
+    use crate::{app, setup_db};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_templates() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let post = |uri: &str, body: serde_json::Value| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+        };
+
+        let response = post(
+            "/api/templates",
+            serde_json::json!({
+                "name": "recipe",
+                "tag_names": ["recipe", "food"],
+                "unread": false,
+                "description_prefix": "Recipe: ",
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = post(
+            "/api/templates",
+            serde_json::json!({"name": "bad", "tag_names": ["two words"]}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = post(
+            "/api/bookmarks/quick?template=recipe",
+            serde_json::json!({
+                "url": "https://example.com/pancakes",
+                "description": "pancakes",
+                "tag_names": ["food", "breakfast"],
+                "unread": true,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut created: BookmarkResponse = serde_json::from_slice(&body).unwrap();
+        created.tag_names.sort();
+        assert_eq!(created.tag_names, vec!["breakfast", "food", "recipe"]);
+        assert!(!created.unread);
+        assert_eq!(created.description.as_deref(), Some("Recipe: pancakes"));
+
+        let response = post(
+            "/api/bookmarks/quick?template=unknown",
+            serde_json::json!({"url": "https://example.com/waffles"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = post(
+            "/api/bookmarks/quick",
+            serde_json::json!({"url": "https://example.com/waffles"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use crate::AppState;
+use axum::extract::State;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{TimeZone, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(sqlx::FromRow, Debug)]
+struct TokenUsageDb {
+    token: String,
+    user_agent: String,
+    ip: String,
+    first_used: i64,
+    last_used: i64,
+    requests: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TokenUsageResponse {
+    // "token" or "trusted network"
+    pub(crate) token: String,
+    pub(crate) user_agent: String,
+    pub(crate) ip: String,
+    pub(crate) first_used: String,
+    pub(crate) last_used: String,
+    pub(crate) requests: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TokensResponse {
+    count: usize,
+    pub(crate) results: Vec<TokenUsageResponse>,
+}
+
+impl From<TokenUsageDb> for TokenUsageResponse {
+    fn from(val: TokenUsageDb) -> Self {
+        let first_used = Utc.timestamp_opt(val.first_used, 0).unwrap();
+        let last_used = Utc.timestamp_opt(val.last_used, 0).unwrap();
+
+        TokenUsageResponse {
+            token: val.token,
+            user_agent: val.user_agent,
+            ip: val.ip,
+            first_used: first_used.to_rfc3339(),
+            last_used: last_used.to_rfc3339(),
+            requests: val.requests,
+        }
+    }
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(handle_get_tokens))
+        .with_state(state)
+}
+
+// The user agents and addresses that have used the token, most recently used first
+async fn handle_get_tokens(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TokensResponse>, StatusCode> {
+    match sqlx::query_as::<_, TokenUsageDb>("SELECT * FROM token_usage ORDER BY last_used DESC")
+        .fetch_all(&state.pool)
+        .await
+    {
+        Ok(rows) => {
+            let results: Vec<TokenUsageResponse> = rows.into_iter().map(Into::into).collect();
+            Ok(Json(TokensResponse {
+                count: results.len(),
+                results,
+            }))
+        }
+        Err(err) => {
+            error!("Failed to get token usage: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app, setup_db};
+    use axum::{body::Body, extract::ConnectInfo, http::Request};
+    use hyper::header;
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_get_tokens() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        for user_agent in ["phone", "laptop", "phone"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/api/tags")
+                        .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                        .header(header::USER_AGENT, user_agent)
+                        .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234))))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/tokens")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::USER_AGENT, "laptop")
+                    .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234))))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let res: TokensResponse = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(res.count, 2);
+        let phone = res
+            .results
+            .iter()
+            .find(|usage| usage.user_agent == "phone")
+            .unwrap();
+        assert_eq!(phone.token, "token");
+        assert_eq!(phone.ip, "10.0.0.1");
+        assert_eq!(phone.requests, 2);
+        let laptop = res
+            .results
+            .iter()
+            .find(|usage| usage.user_agent == "laptop")
+            .unwrap();
+        assert_eq!(laptop.requests, 2);
+    }
+}
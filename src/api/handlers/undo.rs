@@ -0,0 +1,535 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! `POST /api/undo` reverts the last delete, bulk delete or tag merge made by the caller. The
+//! deletes are found in the audit log and the bookmarks brought back from the graveyard, the
+//! merges are kept in `tag_merges`. Calling it again undoes the action before that, as long as
+//! it's within `UNDO_WINDOW`.
+
+use crate::audit::{AffectedIds, Identity};
+use crate::{AppState, PostID};
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use super::bookmarks::{get_bookmark_by_id, BookmarkResponse};
+use super::graveyard;
+use super::tags::{TagDb, TagResponse};
+
+// seconds after an action that it can be undone
+const UNDO_WINDOW: i64 = 10 * 60;
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", post(handle_post_undo))
+        .with_state(state)
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct UndoResponse {
+    // delete or merge
+    pub(crate) undone: String,
+    // the bookmarks brought back, or given the merged tag back
+    pub(crate) bookmarks: Vec<BookmarkResponse>,
+    // the merged tag, added back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tag: Option<TagResponse>,
+}
+
+// A merge as kept in tag_merges, the ids as JSON arrays
+#[derive(Debug, sqlx::FromRow)]
+struct TagMergeDb {
+    id: i64,
+    name: String,
+    into_name: String,
+    date_added: i64,
+    revision: i64,
+    post_ids: String,
+    added_ids: String,
+    smart_tags: String,
+    audit_id: i64,
+}
+
+/// A tag merged into another, kept for undoing the merge. Made before the merge by
+/// `tags::rename_tag`, and recorded after it.
+pub(crate) struct TagMerge {
+    name: String,
+    into_name: String,
+    date_added: i64,
+    // the bookmarks with the tag, and those of them without the tag it's merged into
+    post_ids: Vec<PostID>,
+    added_ids: Vec<PostID>,
+}
+
+impl TagMerge {
+    pub(crate) async fn new(
+        conn: &mut SqliteConnection,
+        tag: &TagDb,
+        into: &TagDb,
+    ) -> Result<Self, sqlx::Error> {
+        let post_ids = sqlx::query_scalar("SELECT post_id FROM post_tag WHERE tag_id = $1")
+            .bind(tag.id)
+            .fetch_all(&mut *conn)
+            .await?;
+        let added_ids = sqlx::query_scalar(
+            r"
+                SELECT post_id FROM post_tag WHERE tag_id = $1
+                    AND post_id NOT IN (SELECT post_id FROM post_tag WHERE tag_id = $2)
+            ",
+        )
+        .bind(tag.id)
+        .bind(into.id)
+        .fetch_all(&mut *conn)
+        .await?;
+        Ok(TagMerge {
+            name: tag.name.clone(),
+            into_name: into.name.clone(),
+            date_added: tag.date_added,
+            post_ids,
+            added_ids,
+        })
+    }
+
+    /// Keeps the merge with the revision the tag was deleted in and the queries, by name, of the
+    /// smart tags changed by it. Merges too old to be undone are removed.
+    pub(crate) async fn record(
+        self,
+        conn: &mut SqliteConnection,
+        identity: &str,
+        smart_tags: &[(String, String)],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM tag_merges WHERE date < unixepoch() - $1")
+            .bind(UNDO_WINDOW)
+            .execute(&mut *conn)
+            .await?;
+        let smart_tags: HashMap<&str, &str> = smart_tags
+            .iter()
+            .map(|(name, query)| (name.as_str(), query.as_str()))
+            .collect();
+        // the last audit entry orders the merge among the deletes, which are audited after
+        sqlx::query(
+            r"
+                INSERT INTO tag_merges (token, name, into_name, date_added, revision, post_ids, added_ids, smart_tags, audit_id, date)
+                    VALUES (
+                        $1, $2, $3, $4,
+                        (SELECT revision FROM tag_revisions WHERE name = $2),
+                        $5, $6, $7,
+                        (SELECT COALESCE(MAX(id), 0) FROM audit),
+                        unixepoch()
+                    )
+            ",
+        )
+        .bind(identity)
+        .bind(&self.name)
+        .bind(&self.into_name)
+        .bind(self.date_added)
+        .bind(serde_json::to_string(&self.post_ids).unwrap_or_default())
+        .bind(serde_json::to_string(&self.added_ids).unwrap_or_default())
+        .bind(serde_json::to_string(&smart_tags).unwrap_or_default())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+enum Undone {
+    Nothing,
+    // the tag merged, or the one merged into, has been changed since
+    Conflict,
+    Bookmarks(Vec<PostID>),
+    Merge(TagDb, Vec<PostID>),
+}
+
+// The graves of the bookmarks in the last delete by the identity, and its audit entry. A delete
+// of several bookmarks has them all in its entry.
+async fn last_graves(
+    conn: &mut SqliteConnection,
+    identity: &str,
+) -> Result<Option<(i64, Vec<i64>)>, sqlx::Error> {
+    let graves: Vec<(i64, PostID, i64)> = sqlx::query_as(
+        r"
+            SELECT audit.id, graveyard.post_id, graveyard.id
+                FROM audit
+                JOIN graveyard ON ',' || audit.ids || ',' LIKE '%,' || graveyard.post_id || ',%'
+                WHERE audit.token = $1
+                    AND (
+                        (audit.method = 'DELETE' AND audit.route LIKE '/api/bookmarks/%')
+                        OR (audit.method = 'GET' AND audit.route = '/pinboard/v1/posts/delete')
+                        OR (audit.method = 'POST' AND audit.route = '/api/admin/retention')
+                    )
+                    AND audit.status BETWEEN 200 AND 299
+                    AND audit.date >= unixepoch() - $2
+                    AND graveyard.deleted_at <= audit.date
+                ORDER BY audit.id DESC, graveyard.id DESC
+        ",
+    )
+    .bind(identity)
+    .bind(UNDO_WINDOW)
+    .fetch_all(conn)
+    .await?;
+
+    let Some(&(audit_id, _, _)) = graves.first() else {
+        return Ok(None);
+    };
+    // the last grave of each bookmark, the id may have been used again
+    let mut post_ids = vec![];
+    let mut grave_ids = vec![];
+    for (_, post_id, grave_id) in graves.into_iter().take_while(|grave| grave.0 == audit_id) {
+        if !post_ids.contains(&post_id) {
+            post_ids.push(post_id);
+            grave_ids.push(grave_id);
+        }
+    }
+    Ok(Some((audit_id, grave_ids)))
+}
+
+async fn last_merge(
+    conn: &mut SqliteConnection,
+    identity: &str,
+) -> Result<Option<TagMergeDb>, sqlx::Error> {
+    sqlx::query_as(
+        r"
+            SELECT id, name, into_name, date_added, revision, post_ids, added_ids, smart_tags, audit_id
+                FROM tag_merges
+                WHERE token = $1 AND date >= unixepoch() - $2
+                ORDER BY id DESC
+                LIMIT 1
+        ",
+    )
+    .bind(identity)
+    .bind(UNDO_WINDOW)
+    .fetch_optional(conn)
+    .await
+}
+
+// Adds the tag back to the bookmarks that had it, and removes the tag it was merged into from
+// the bookmarks that got it by the merge
+async fn unmerge(conn: &mut SqliteConnection, merge: TagMergeDb) -> Result<Undone, sqlx::Error> {
+    // not added again since, and the tag it was merged into still there
+    let revision: Option<(i64, bool)> =
+        sqlx::query_as("SELECT revision, deleted FROM tag_revisions WHERE name = $1")
+            .bind(&merge.name)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let into_id: Option<i64> = sqlx::query_scalar("SELECT id FROM tags WHERE name = $1")
+        .bind(&merge.into_name)
+        .fetch_optional(&mut *conn)
+        .await?;
+    let Some(into_id) = into_id.filter(|_| revision == Some((merge.revision, true))) else {
+        return Ok(Undone::Conflict);
+    };
+
+    let tag = sqlx::query_as::<_, TagDb>(
+        "INSERT INTO tags (name, date_added) VALUES ($1, $2) RETURNING *",
+    )
+    .bind(&merge.name)
+    .bind(merge.date_added)
+    .fetch_one(&mut *conn)
+    .await?;
+    let post_ids: Vec<PostID> = sqlx::query_scalar(
+        r"
+            INSERT INTO post_tag (post_id, tag_id)
+                SELECT posts.id, $2 FROM json_each($1) JOIN posts ON posts.id = json_each.value
+                RETURNING post_id
+        ",
+    )
+    .bind(&merge.post_ids)
+    .bind(tag.id)
+    .fetch_all(&mut *conn)
+    .await?;
+    sqlx::query(
+        "DELETE FROM post_tag WHERE tag_id = $1 AND post_id IN (SELECT value FROM json_each($2))",
+    )
+    .bind(into_id)
+    .bind(&merge.added_ids)
+    .execute(&mut *conn)
+    .await?;
+
+    let smart_tags: HashMap<String, String> =
+        serde_json::from_str(&merge.smart_tags).unwrap_or_default();
+    for (name, query) in smart_tags {
+        sqlx::query("UPDATE smart_tags SET query = $1 WHERE name = $2")
+            .bind(query)
+            .bind(name)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sqlx::query("DELETE FROM tag_merges WHERE id = $1")
+        .bind(merge.id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(Undone::Merge(tag, post_ids))
+}
+
+// Undoes the later of the last delete and the last merge
+async fn undo(pool: &SqlitePool, identity: &str) -> Result<Undone, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let graves = last_graves(&mut tx, identity).await?;
+    let merge = last_merge(&mut tx, identity).await?;
+
+    let undone = match (graves, merge) {
+        (Some((audit_id, graves)), merge)
+            if merge.as_ref().is_none_or(|merge| merge.audit_id < audit_id) =>
+        {
+            let mut post_ids = vec![];
+            for grave in graves {
+                post_ids.push(graveyard::raise(&mut tx, grave).await?);
+            }
+            Undone::Bookmarks(post_ids)
+        }
+        (_, Some(merge)) => unmerge(&mut tx, merge).await?,
+        (_, None) => Undone::Nothing,
+    };
+    tx.commit().await?;
+    Ok(undone)
+}
+
+async fn handle_post_undo(
+    State(state): State<Arc<AppState>>,
+    Extension(Identity(identity)): Extension<Identity>,
+) -> Result<(Extension<AffectedIds>, Json<UndoResponse>), StatusCode> {
+    let (undone, post_ids, tag) = match undo(&state.pool, &identity).await {
+        Ok(Undone::Bookmarks(post_ids)) => ("delete", post_ids, None),
+        Ok(Undone::Merge(tag, post_ids)) => ("merge", post_ids, Some(tag)),
+        Ok(Undone::Nothing) => return Err(StatusCode::NOT_FOUND),
+        Ok(Undone::Conflict) => {
+            info!("Not undoing the merge, the tags have changed since");
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            info!(
+                "Not undoing the delete, the url has been added again: {}",
+                err
+            );
+            return Err(StatusCode::CONFLICT);
+        }
+        Err(err) => {
+            error!("Failed to undo: {}", err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    info!("undid {} of bookmarks: {:?}", undone, post_ids);
+    let mut bookmarks = vec![];
+    for post_id in &post_ids {
+        if let Some(bookmark) = get_bookmark_by_id(state.clone(), *post_id).await {
+            bookmarks.push(bookmark);
+        }
+    }
+    Ok((
+        Extension(AffectedIds(post_ids)),
+        Json(UndoResponse {
+            undone: undone.to_owned(),
+            bookmarks,
+            tag: tag.map(Into::into),
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::bookmarks::tests::{add_post, get_random_string};
+    use crate::retention::RetentionRules;
+    use crate::{app, app_with_state, setup_db};
+    use axum::{body::Body, http::Request};
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    async fn request(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Vec<u8>) {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(body.to_owned()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, body.to_vec())
+    }
+
+    async fn add_bookmark(app: &Router, tags: Vec<String>) -> BookmarkResponse {
+        let created = add_post(app.clone(), Some(tags), true).await;
+        let body = axum::body::to_bytes(created.response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn undo(app: &Router) -> UndoResponse {
+        let (status, body) = request(app, "POST", "/api/undo", "").await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_undo() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let tag = get_random_string(8);
+        let mut ids = vec![];
+        for _ in 0..2 {
+            ids.push(add_bookmark(&app, vec![tag.clone()]).await.id);
+        }
+
+        let (status, _) = request(&app, "POST", "/api/undo", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        for id in &ids {
+            let (status, _) = request(&app, "DELETE", &format!("/api/bookmarks/{id}"), "").await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        // the last delete is undone first
+        for id in ids.iter().rev() {
+            let undone = undo(&app).await;
+            assert_eq!(undone.undone, "delete");
+            assert!(undone.tag.is_none());
+            assert_eq!(undone.bookmarks.len(), 1);
+            let bookmark = &undone.bookmarks[0];
+            assert_eq!(bookmark.id, *id);
+            assert_eq!(bookmark.tag_names, vec![tag.clone()]);
+            assert!(bookmark.unread);
+        }
+
+        let (status, _) = request(&app, "POST", "/api/undo", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_undo_bulk_delete() {
+        let pool = setup_db(true).await;
+        let mut state = AppState::new(pool.clone(), TOKEN.to_owned());
+        state.retention_rules = RetentionRules::parse("temp delete 30d").unwrap();
+        let app = app_with_state(state);
+
+        let mut ids = vec![];
+        for _ in 0..3 {
+            ids.push(add_bookmark(&app, vec!["temp".to_owned()]).await.id);
+        }
+        sqlx::query("UPDATE posts SET date_added = unixepoch('now', '-100 days')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let (status, _) = request(&app, "POST", "/api/admin/retention", "").await;
+        assert_eq!(status, StatusCode::OK);
+
+        // all the bookmarks deleted by the rules are brought back at once
+        let undone = undo(&app).await;
+        assert_eq!(undone.undone, "delete");
+        let mut undone_ids: Vec<PostID> = undone.bookmarks.iter().map(|b| b.id).collect();
+        undone_ids.sort_unstable();
+        assert_eq!(undone_ids, ids);
+
+        let (status, _) = request(&app, "POST", "/api/undo", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_undo_merge() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), TOKEN.to_owned());
+
+        let (a, b) = (get_random_string(8), get_random_string(8));
+        let only_a = add_bookmark(&app, vec![a.clone()]).await;
+        let both = add_bookmark(&app, vec![a.clone(), b.clone()]).await;
+        let only_b = add_bookmark(&app, vec![b.clone()]).await;
+        sqlx::query(
+            "INSERT INTO smart_tags (name, query, date_added) VALUES ('s', $1, unixepoch())",
+        )
+        .bind(format!("tag={a}"))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let (status, _) = request(
+            &app,
+            "PUT",
+            &format!("/api/tags/{a}"),
+            &serde_json::json!({"name": b}).to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) =
+            request(&app, "DELETE", &format!("/api/bookmarks/{}", only_b.id), "").await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the delete was after the merge
+        let undone = undo(&app).await;
+        assert_eq!(undone.undone, "delete");
+        assert_eq!(undone.bookmarks[0].id, only_b.id);
+
+        let undone = undo(&app).await;
+        assert_eq!(undone.undone, "merge");
+        assert_eq!(undone.tag.unwrap().name, a);
+        let mut tag_names: Vec<(PostID, Vec<String>)> = undone
+            .bookmarks
+            .into_iter()
+            .map(|mut bookmark| {
+                bookmark.tag_names.sort_unstable();
+                (bookmark.id, bookmark.tag_names)
+            })
+            .collect();
+        tag_names.sort_unstable();
+        let mut expected = vec![a.clone(), b.clone()];
+        expected.sort_unstable();
+        assert_eq!(
+            tag_names,
+            vec![(only_a.id, vec![a.clone()]), (both.id, expected)],
+            "only the bookmarks without the tag lose it"
+        );
+
+        let query: String = sqlx::query_scalar("SELECT query FROM smart_tags WHERE name = 's'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(query, format!("tag={a}"));
+
+        let (status, _) = request(&app, "POST", "/api/undo", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_undo_merge_conflict() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let (a, b) = (get_random_string(8), get_random_string(8));
+        add_bookmark(&app, vec![a.clone()]).await;
+        add_bookmark(&app, vec![b.clone()]).await;
+        let (status, _) = request(
+            &app,
+            "PUT",
+            &format!("/api/tags/{a}"),
+            &serde_json::json!({"name": b}).to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        // the merged tag is used again
+        add_bookmark(&app, vec![a.clone()]).await;
+        let (status, _) = request(&app, "POST", "/api/undo", "").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+}
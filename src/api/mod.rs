@@ -6,8 +6,13 @@ use crate::AppState;
 use axum::Router;
 use std::sync::Arc;
 
+pub(crate) mod etag;
+pub(crate) mod format;
+pub(crate) mod fuzzy;
 pub mod handlers;
 
 pub fn configure(state: &Arc<AppState>) -> Router {
-    Router::new().nest("/api/", handlers::configure(state))
+    Router::new()
+        .nest("/api/", handlers::configure(state))
+        .nest("/pinboard/v1", handlers::pinboard::configure(state.clone()))
 }
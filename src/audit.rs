@@ -0,0 +1,71 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use tracing::error;
+
+use crate::read_only::is_write;
+use crate::{AppState, PostID};
+
+/// Who made the request, set by the auth middleware
+#[derive(Clone, Debug)]
+pub(crate) struct Identity(pub(crate) String);
+
+/// Ids affected by a request that aren't part of its path, e.g. the id of a created bookmark.
+/// Handlers add it to the response extensions.
+#[derive(Clone, Debug)]
+pub(crate) struct AffectedIds(pub(crate) Vec<PostID>);
+
+fn ids_from_path(path: &str) -> Vec<PostID> {
+    path.split('/')
+        .filter_map(|segment| segment.parse::<PostID>().ok())
+        .collect()
+}
+
+pub(crate) async fn audit(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let method = req.method().clone();
+    if !is_write(&method, req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    let route = req.uri().path().to_owned();
+    let identity = req
+        .extensions()
+        .get::<Identity>()
+        .map(|identity| identity.0.clone())
+        .unwrap_or_default();
+
+    let response = next.run(req).await;
+
+    let mut ids = ids_from_path(&route);
+    if let Some(AffectedIds(affected)) = response.extensions().get::<AffectedIds>() {
+        ids.extend(affected);
+    }
+    let ids: Vec<String> = ids.iter().map(ToString::to_string).collect();
+
+    if let Err(err) = sqlx::query(
+        "INSERT INTO audit (date, token, method, route, status, ids) VALUES (unixepoch(), $1, $2, $3, $4, $5)",
+    )
+    .bind(identity)
+    .bind(method.as_str())
+    .bind(&route)
+    .bind(response.status().as_u16())
+    .bind(ids.join(","))
+    .execute(&state.pool)
+    .await
+    {
+        error!("Failed to add audit entry for {} {}: {}", method, route, err);
+    }
+
+    response
+}
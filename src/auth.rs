@@ -0,0 +1,400 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::post,
+    Extension, Router,
+};
+use hyper::header::{self, HeaderName};
+use ipnet::IpNet;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+use crate::audit::Identity;
+use crate::AppState;
+
+/// Parses a comma separated list of networks, e.g. `127.0.0.1/8,::1/128`. A bare address is
+/// treated as a network containing only that address.
+pub(crate) fn parse_trusted_networks(networks: &str) -> Result<Vec<IpNet>, String> {
+    networks
+        .split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(|network| {
+            network
+                .parse::<IpNet>()
+                .or_else(|_| network.parse::<std::net::IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("Invalid network: {network}"))
+        })
+        .collect()
+}
+
+// Address of the client
+pub(crate) fn client_ip(req: &Request) -> Option<IpAddr> {
+    req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical())
+}
+
+// User agent and address of the client
+fn client_of(req: &Request) -> (String, String) {
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let ip = client_ip(req).map(|ip| ip.to_string()).unwrap_or_default();
+    (user_agent, ip)
+}
+
+fn in_networks(networks: &[IpNet], req: &Request) -> bool {
+    client_ip(req).is_some_and(|ip| networks.iter().any(|network| network.contains(&ip)))
+}
+
+// Keeps track of the user agents and addresses using the token, see GET /api/tokens
+async fn record_usage(state: &AppState, identity: &str, (user_agent, ip): (String, String)) {
+    if let Err(err) = sqlx::query(
+        r"
+            INSERT INTO token_usage (token, user_agent, ip, first_used, last_used, requests)
+                VALUES ($1, $2, $3, unixepoch(), unixepoch(), 1)
+                ON CONFLICT (token, user_agent, ip)
+                DO UPDATE SET last_used = unixepoch(), requests = requests + 1
+        ",
+    )
+    .bind(identity)
+    .bind(user_agent)
+    .bind(ip)
+    .execute(&state.pool)
+    .await
+    {
+        error!("Failed to record token usage: {}", err);
+    }
+}
+
+/// A way of authenticating requests. The providers in `AppState` are tried in turn until one
+/// knows the client.
+pub(crate) trait AuthProvider: Send + Sync {
+    /// The identity of the client, recorded in the audit log and the token usage, or None to
+    /// leave the request to the next provider
+    fn authenticate(&self, req: &Request) -> Option<String>;
+}
+
+// Headers a reverse proxy adds with the address of the client it forwards the request for
+const FORWARDED_HEADERS: [&str; 3] = ["forwarded", "x-forwarded-for", "x-real-ip"];
+
+/// Requests from the networks, see `PINRS_TRUSTED_NETWORKS`. Requests forwarded by a reverse
+/// proxy aren't trusted, they come from the address of the proxy whoever the client is.
+pub(crate) struct TrustedNetworks(pub(crate) Vec<IpNet>);
+
+impl AuthProvider for TrustedNetworks {
+    fn authenticate(&self, req: &Request) -> Option<String> {
+        if !in_networks(&self.0, req) {
+            return None;
+        }
+        if FORWARDED_HEADERS
+            .iter()
+            .any(|name| req.headers().contains_key(*name))
+        {
+            debug!("Request forwarded by a proxy, not trusting the network");
+            return None;
+        }
+        debug!("Request from trusted network, skipping authentication");
+        Some("trusted network".to_owned())
+    }
+}
+
+/// The user in a header set by a reverse proxy doing the authentication, e.g. `X-Remote-User`
+/// from Authelia. Only requests from the proxies are trusted with the header.
+pub(crate) struct TrustedHeader {
+    header: HeaderName,
+    proxies: Vec<IpNet>,
+}
+
+impl TrustedHeader {
+    /// From `PINRS_AUTH_HEADER` and `PINRS_AUTH_HEADER_PROXIES`, the networks of the proxies
+    pub(crate) fn parse(header: &str, proxies: &str) -> Result<Self, String> {
+        let header = HeaderName::from_str(header.trim())
+            .map_err(|_| format!("Invalid PINRS_AUTH_HEADER: {header}"))?;
+        let proxies = parse_trusted_networks(proxies)?;
+        if proxies.is_empty() {
+            return Err("PINRS_AUTH_HEADER_PROXIES has no networks".to_owned());
+        }
+        Ok(TrustedHeader { header, proxies })
+    }
+}
+
+impl AuthProvider for TrustedHeader {
+    fn authenticate(&self, req: &Request) -> Option<String> {
+        let user = req
+            .headers()
+            .get(&self.header)
+            .and_then(|user| user.to_str().ok())
+            .map(str::trim)
+            .filter(|user| !user.is_empty())?;
+        if !in_networks(&self.proxies, req) {
+            error!("Ignoring {} from an untrusted address", self.header);
+            return None;
+        }
+        Some(format!("user:{user}"))
+    }
+}
+
+// Query parameters that can hold the token, kept out of the logs
+const QUERY_TOKENS: [&str; 2] = ["token", "auth_token"];
+
+// The bookmark lists feed readers fetch, the only routes taking `?token=`
+const FEED_ROUTES: [&str; 2] = ["/api/bookmarks", "/api/bookmarks/archived"];
+
+/// The token in `PINRS_TOKEN`, given as `Authorization: Token ...` or `Bearer ...`, as `?token=`
+/// on a GET of a feed for clients that can't set headers, e.g. feed readers, or as
+/// `?auth_token=user:...` on the Pinboard API like Pinboard clients do, the user is ignored
+pub(crate) struct StaticToken(pub(crate) String);
+
+impl StaticToken {
+    // the token in the query, only where clients need it since the url ends up in logs and
+    // browser history
+    fn query_token(req: &Request) -> Option<String> {
+        let path = req.uri().path();
+        let param = if path.starts_with("/pinboard/v1/") {
+            "auth_token"
+        } else if req.method() == Method::GET && FEED_ROUTES.contains(&path) {
+            "token"
+        } else {
+            return None;
+        };
+
+        url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+            .find(|(key, _)| key == param)
+            .and_then(|(key, token)| match key.as_ref() {
+                "auth_token" => token.rsplit_once(':').map(|(_, token)| token.to_owned()),
+                _ => Some(token.into_owned()),
+            })
+    }
+}
+
+impl AuthProvider for StaticToken {
+    fn authenticate(&self, req: &Request) -> Option<String> {
+        let header_token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|auth_header| auth_header.to_str().ok())
+            .and_then(|auth_value| {
+                auth_value
+                    .strip_prefix("Token ")
+                    .or_else(|| auth_value.strip_prefix("Bearer "))
+            })
+            .map(str::to_owned);
+        let Some(token) = header_token.or_else(|| Self::query_token(req)) else {
+            error!("No token");
+            return None;
+        };
+
+        if token == self.0 {
+            Some("token".to_owned())
+        } else {
+            error!("Failed to authenticate with a wrong token");
+            None
+        }
+    }
+}
+
+/// The uri with the values of the token parameters replaced, for the request log
+pub(crate) fn redact_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+
+    // the keys are compared decoded like `query_token` reads them
+    let pairs =
+        query.split('&').map(
+            |pair| match url::form_urlencoded::parse(pair.as_bytes()).next() {
+                Some((key, _)) if QUERY_TOKENS.contains(&key.as_ref()) => format!("{key}=redacted"),
+                _ => pair.to_owned(),
+            },
+        );
+    format!("{}?{}", uri.path(), pairs.collect::<Vec<_>>().join("&"))
+}
+
+// name of the cookie with the session id
+const SESSION_COOKIE: &str = "pinrs_session";
+
+// a session ends this long after logging in
+const SESSION_LIFETIME: Duration = Duration::from_hours(30 * 24);
+
+/// Sessions of the browsers logged in with `POST /auth/login`, by id. They're only kept in
+/// memory, i.e. restarting pinrs logs out all browsers.
+#[derive(Default)]
+pub(crate) struct Sessions(Mutex<HashMap<String, (String, Instant)>>);
+
+impl Sessions {
+    // A new session for the identity, returning its id
+    fn create(&self, identity: String, now: Instant) -> Option<String> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let id = bytes.iter().fold(String::new(), |mut id, byte| {
+            let _ = write!(id, "{byte:02x}");
+            id
+        });
+
+        let mut sessions = self.0.lock().ok()?;
+        sessions.retain(|_, (_, started)| now.duration_since(*started) < SESSION_LIFETIME);
+        sessions.insert(id.clone(), (identity, now));
+        Some(id)
+    }
+
+    fn remove(&self, id: &str) {
+        if let Ok(mut sessions) = self.0.lock() {
+            sessions.remove(id);
+        }
+    }
+
+    // The identity that logged in, unless the session has ended
+    fn identity(&self, id: &str, now: Instant) -> Option<String> {
+        let sessions = self.0.lock().ok()?;
+        let (identity, started) = sessions.get(id)?;
+        (now.duration_since(*started) < SESSION_LIFETIME).then(|| identity.clone())
+    }
+}
+
+// The session id in the cookie of the request
+fn session_id(req: &Request) -> Option<String> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|cookie| cookie.to_str().ok())
+        .flat_map(|cookie| cookie.split(';'))
+        .find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(SESSION_COOKIE)?
+                .strip_prefix('=')
+                .map(str::to_owned)
+        })
+}
+
+/// The session cookie set by `POST /auth/login`, with the identity that logged in
+pub(crate) struct SessionCookie(pub(crate) Arc<Sessions>);
+
+impl AuthProvider for SessionCookie {
+    fn authenticate(&self, req: &Request) -> Option<String> {
+        self.0.identity(&session_id(req)?, Instant::now())
+    }
+}
+
+// Set-Cookie for the session, or clearing it without an id
+fn session_cookie(id: Option<&str>, secure: bool) -> Option<HeaderValue> {
+    let max_age = if id.is_some() {
+        SESSION_LIFETIME.as_secs()
+    } else {
+        0
+    };
+    let secure = if secure { "; Secure" } else { "" };
+    HeaderValue::from_str(&format!(
+        "{SESSION_COOKIE}={}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Strict{secure}",
+        id.unwrap_or_default()
+    ))
+    .ok()
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/auth/login", post(handle_post_login))
+        .route("/auth/logout", post(handle_post_logout))
+        .with_state(state)
+}
+
+// Starts a session for a client authenticated in any other way, e.g. with the token
+async fn handle_post_login(
+    State(state): State<Arc<AppState>>,
+    Extension(Identity(identity)): Extension<Identity>,
+) -> Response {
+    let Some(cookie) = state
+        .sessions
+        .create(identity, Instant::now())
+        .and_then(|id| session_cookie(Some(&id), state.security_headers.https()))
+    else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    info!("logged in");
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response()
+}
+
+async fn handle_post_logout(State(state): State<Arc<AppState>>, req: Request) -> Response {
+    if let Some(id) = session_id(&req) {
+        state.sessions.remove(&id);
+    }
+    match session_cookie(None, state.security_headers.https()) {
+        Some(cookie) => (StatusCode::NO_CONTENT, [(header::SET_COOKIE, cookie)]).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Adds the providers configured with `PINRS_TRUSTED_NETWORKS` and `PINRS_AUTH_HEADER`, tried
+/// before the token in that order
+pub(crate) fn add_providers_from_env(
+    providers: &mut Vec<Box<dyn AuthProvider>>,
+) -> Result<(), String> {
+    let mut proxies = vec![];
+    if let Ok(header) = env::var("PINRS_AUTH_HEADER") {
+        let networks = env::var("PINRS_AUTH_HEADER_PROXIES")
+            .map_err(|_| "Need to set PINRS_AUTH_HEADER_PROXIES with PINRS_AUTH_HEADER")?;
+        let provider = TrustedHeader::parse(&header, &networks)?;
+        proxies.clone_from(&provider.proxies);
+        providers.insert(0, Box::new(provider));
+    }
+    if let Ok(networks) = env::var("PINRS_TRUSTED_NETWORKS") {
+        let networks = parse_trusted_networks(&networks)?;
+        check_trusted_networks(&networks, &proxies)?;
+        providers.insert(0, Box::new(TrustedNetworks(networks)));
+    }
+    Ok(())
+}
+
+/// Fails if the trusted networks overlap those of the proxies, all requests through them would
+/// be trusted
+pub(crate) fn check_trusted_networks(networks: &[IpNet], proxies: &[IpNet]) -> Result<(), String> {
+    match networks.iter().find(|network| {
+        proxies
+            .iter()
+            .any(|proxy| network.contains(proxy) || proxy.contains(*network))
+    }) {
+        Some(network) => Err(format!(
+            "PINRS_TRUSTED_NETWORKS can't include the proxies in PINRS_AUTH_HEADER_PROXIES: {network}"
+        )),
+        None => Ok(()),
+    }
+}
+
+pub(crate) async fn auth(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let Some(identity) = state
+        .auth_providers
+        .iter()
+        .find_map(|provider| provider.authenticate(&req))
+    else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    // the database of a read-only mirror can't be written to
+    if !state.read_only {
+        record_usage(&state, &identity, client_of(&req)).await;
+    }
+    req.extensions_mut().insert(Identity(identity));
+    Ok(next.run(req).await)
+}
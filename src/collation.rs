@@ -0,0 +1,65 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The `title` collation used for `sort=title`. Case and accents are ignored, e.g. é sorts as e,
+//! except for the letters that the language in `PINRS_LOCALE` sorts as their own letters after
+//! z, e.g. å, ä and ö in Swedish.
+
+use std::cmp::Ordering;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+// the letters after z, in order, with the letters sorted as the same
+fn letters_after_z(locale: &str) -> &'static [&'static [char]] {
+    match locale.split(['_', '-']).next().unwrap_or_default() {
+        "sv" | "fi" => &[&['å'], &['ä', 'æ'], &['ö', 'ø']],
+        "da" | "nb" | "nn" | "no" => &[&['æ', 'ä'], &['ø', 'ö'], &['å']],
+        _ => &[],
+    }
+}
+
+// A letter after z is (z, its position), any other is (the letter without accents, 0)
+fn sort_key(text: &str, letters: &[&[char]]) -> Vec<(char, usize)> {
+    let mut key = vec![];
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if let Some(position) = letters.iter().position(|same| same.contains(&c)) {
+            key.push(('z', position + 1));
+        } else {
+            key.extend(c.nfd().filter(|c| !is_combining_mark(*c)).map(|c| (c, 0)));
+        }
+    }
+    key
+}
+
+/// Compares titles for the language of the locale, e.g. `sv_SE.UTF-8`.
+pub(crate) fn compare(locale: &str) -> impl Fn(&str, &str) -> Ordering + Send + Sync + 'static {
+    let letters = letters_after_z(locale);
+    move |a, b| {
+        sort_key(a, letters)
+            .cmp(&sort_key(b, letters))
+            .then_with(|| a.cmp(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let mut titles = vec!["Öl", "apa", "Zebra", "Äpple", "ål", "École", "banan"];
+
+        titles.sort_by(|a, b| compare("sv_SE.UTF-8")(a, b));
+        assert_eq!(
+            titles,
+            vec!["apa", "banan", "École", "Zebra", "ål", "Äpple", "Öl"]
+        );
+
+        titles.sort_by(|a, b| compare("")(a, b));
+        assert_eq!(
+            titles,
+            vec!["ål", "apa", "Äpple", "banan", "École", "Öl", "Zebra"]
+        );
+    }
+}
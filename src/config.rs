@@ -0,0 +1,278 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! `--export-config` and `--import-config`, the configuration kept in the database as JSON: the
+//! smart tags, the templates and which clients have used the token. Secrets, i.e. the TOTP
+//! secret, are left out. The rewrite and retention rules are files already and not included.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::api::handlers::bookmarks::BookmarkQuery;
+use crate::api::handlers::tags::is_valid_tag_name;
+use crate::output::Summary;
+
+#[derive(Debug, Default, Deserialize, Serialize, sqlx::FromRow)]
+pub(crate) struct SmartTagConfig {
+    pub(crate) name: String,
+    pub(crate) query: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct TemplateConfig {
+    pub(crate) name: String,
+    pub(crate) tag_names: Vec<String>,
+    pub(crate) unread: Option<bool>,
+    pub(crate) description_prefix: Option<String>,
+}
+
+// a row of token_usage, the identity and not the token itself
+#[derive(Debug, Default, Deserialize, Serialize, sqlx::FromRow)]
+pub(crate) struct TokenUsageConfig {
+    pub(crate) token: String,
+    pub(crate) user_agent: String,
+    pub(crate) ip: String,
+    pub(crate) first_used: i64,
+    pub(crate) last_used: i64,
+    pub(crate) requests: i64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) smart_tags: Vec<SmartTagConfig>,
+    #[serde(default)]
+    pub(crate) templates: Vec<TemplateConfig>,
+    #[serde(default)]
+    pub(crate) token_usage: Vec<TokenUsageConfig>,
+}
+
+/// Outcome of `--import-config`
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ConfigReport {
+    pub(crate) smart_tags: usize,
+    pub(crate) templates: usize,
+    pub(crate) token_usage: usize,
+    // smart tags and templates with an invalid name or query, or a smart tag named as a real tag
+    pub(crate) skipped: Vec<String>,
+}
+
+impl Summary for ConfigReport {
+    fn plain(&self) -> String {
+        let summary = format!(
+            "Imported {} smart tags, {} templates and {} token usages",
+            self.smart_tags, self.templates, self.token_usage
+        );
+        if self.skipped.is_empty() {
+            summary
+        } else {
+            format!("{summary}, skipped {}", self.skipped.join(", "))
+        }
+    }
+
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("smart_tags", self.smart_tags.to_string()),
+            ("templates", self.templates.to_string()),
+            ("token_usage", self.token_usage.to_string()),
+            ("skipped", self.skipped.join(", ")),
+        ]
+    }
+}
+
+pub(crate) async fn get_config(pool: &SqlitePool) -> Result<Config> {
+    let templates: Vec<(String, String, Option<bool>, Option<String>)> = sqlx::query_as(
+        "SELECT name, tag_names, unread, description_prefix FROM templates ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Config {
+        smart_tags: sqlx::query_as("SELECT name, query FROM smart_tags ORDER BY name")
+            .fetch_all(pool)
+            .await?,
+        templates: templates
+            .into_iter()
+            .map(
+                |(name, tag_names, unread, description_prefix)| TemplateConfig {
+                    name,
+                    tag_names: serde_json::from_str(&tag_names).unwrap_or_default(),
+                    unread,
+                    description_prefix,
+                },
+            )
+            .collect(),
+        token_usage: sqlx::query_as(
+            r"
+                SELECT token, user_agent, ip, first_used, last_used, requests
+                    FROM token_usage
+                    ORDER BY last_used DESC
+            ",
+        )
+        .fetch_all(pool)
+        .await?,
+    })
+}
+
+/// Prints the configuration as JSON
+pub(crate) async fn export_config(pool: &SqlitePool) -> Result<()> {
+    let config = get_config(pool).await?;
+    println!("{}", serde_json::to_string_pretty(&config)?);
+    Ok(())
+}
+
+/// Adds the configuration in a single transaction. Smart tags and templates with the same name
+/// are replaced, the token usage is merged with the one already recorded.
+pub(crate) async fn import_config(pool: &SqlitePool, input: &str) -> Result<ConfigReport> {
+    let config: Config = serde_json::from_str(input)?;
+    let mut report = ConfigReport::default();
+    let mut tx = pool.begin().await?;
+
+    for smart_tag in config.smart_tags {
+        let name = smart_tag.name.trim();
+        let query = smart_tag.query.trim().trim_start_matches('?');
+        let is_tag: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tags WHERE name = $1")
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+        if !is_valid_tag_name(name) || BookmarkQuery::parse(query).is_none() || is_tag > 0 {
+            report.skipped.push(smart_tag.name);
+            continue;
+        }
+
+        sqlx::query(
+            r"
+                INSERT INTO smart_tags (name, query, date_added) VALUES ($1, $2, unixepoch())
+                    ON CONFLICT(name) DO UPDATE SET query = excluded.query
+            ",
+        )
+        .bind(name)
+        .bind(query)
+        .execute(&mut *tx)
+        .await?;
+        report.smart_tags += 1;
+    }
+
+    for template in config.templates {
+        let name = template.name.trim();
+        let tag_names: Vec<&str> = template.tag_names.iter().map(|tag| tag.trim()).collect();
+        if name.is_empty() || !tag_names.iter().all(|tag| is_valid_tag_name(tag)) {
+            report.skipped.push(template.name);
+            continue;
+        }
+
+        sqlx::query(
+            r"
+                INSERT INTO templates (name, tag_names, unread, description_prefix, date_added)
+                    VALUES ($1, $2, $3, $4, unixepoch())
+                    ON CONFLICT(name) DO UPDATE SET
+                        tag_names = excluded.tag_names,
+                        unread = excluded.unread,
+                        description_prefix = excluded.description_prefix
+            ",
+        )
+        .bind(name)
+        .bind(serde_json::to_string(&tag_names)?)
+        .bind(template.unread)
+        .bind(&template.description_prefix)
+        .execute(&mut *tx)
+        .await?;
+        report.templates += 1;
+    }
+
+    for usage in config.token_usage {
+        sqlx::query(
+            r"
+                INSERT INTO token_usage (token, user_agent, ip, first_used, last_used, requests)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (token, user_agent, ip) DO UPDATE SET
+                        first_used = MIN(first_used, excluded.first_used),
+                        last_used = MAX(last_used, excluded.last_used),
+                        requests = requests + excluded.requests
+            ",
+        )
+        .bind(&usage.token)
+        .bind(&usage.user_agent)
+        .bind(&usage.ip)
+        .bind(usage.first_used)
+        .bind(usage.last_used)
+        .bind(usage.requests)
+        .execute(&mut *tx)
+        .await?;
+        report.token_usage += 1;
+    }
+
+    tx.commit().await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_db;
+
+    #[tokio::test]
+    async fn test_export_import_config() {
+        let pool = setup_db(true).await;
+        sqlx::query(
+            "INSERT INTO smart_tags (name, query, date_added) VALUES ('later', 'unread=yes', 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO templates (name, tag_names, unread, description_prefix, date_added) VALUES ('recipe', '["food"]', 1, 'Recipe: ', 0)"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO token_usage VALUES ('token', 'curl', '10.0.0.1', 10, 20, 3)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO settings (name, value) VALUES ('totp_secret', 'secret')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let exported = serde_json::to_string(&get_config(&pool).await.unwrap()).unwrap();
+        assert!(!exported.contains("secret"));
+
+        let other = setup_db(true).await;
+        sqlx::query("INSERT INTO token_usage VALUES ('token', 'curl', '10.0.0.1', 5, 15, 2)")
+            .execute(&other)
+            .await
+            .unwrap();
+        let report = import_config(&other, &exported).await.unwrap();
+        assert_eq!(report.smart_tags, 1);
+        assert_eq!(report.templates, 1);
+        assert_eq!(report.token_usage, 1);
+        assert!(report.skipped.is_empty());
+
+        let config = get_config(&other).await.unwrap();
+        assert_eq!(config.smart_tags[0].name, "later");
+        assert_eq!(config.smart_tags[0].query, "unread=yes");
+        assert_eq!(config.templates[0].tag_names, vec!["food"]);
+        assert_eq!(config.templates[0].unread, Some(true));
+        assert_eq!(
+            config.templates[0].description_prefix.as_deref(),
+            Some("Recipe: ")
+        );
+        let usage = &config.token_usage[0];
+        assert_eq!(
+            (usage.first_used, usage.last_used, usage.requests),
+            (5, 20, 5)
+        );
+
+        let report = import_config(
+            &other,
+            r#"{"smart_tags": [{"name": "bad name", "query": "unread=yes"}]}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.skipped, vec!["bad name"]);
+        assert!(import_config(&other, "{").await.is_err());
+    }
+}
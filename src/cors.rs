@@ -0,0 +1,37 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use axum::http::{HeaderName, HeaderValue};
+use hyper::header;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+/// Parses a comma separated list of origins allowed to make cross-origin requests, e.g.
+/// `https://bookmarks.example.com,http://localhost:8080`.
+pub(crate) fn parse_origins(origins: &str) -> Result<Vec<HeaderValue>, String> {
+    origins
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            HeaderValue::from_str(origin).map_err(|_| format!("Invalid origin: {origin}"))
+        })
+        .collect()
+}
+
+/// CORS for a group of routes: any origin if `origins` is `None`, otherwise only the listed ones.
+pub(crate) fn layer(origins: Option<&[HeaderValue]>) -> CorsLayer {
+    let Some(origins) = origins else {
+        return CorsLayer::permissive();
+    };
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins.iter().cloned()))
+        .allow_methods(AllowMethods::mirror_request())
+        .allow_headers(AllowHeaders::mirror_request())
+        .expose_headers([
+            header::ETAG,
+            header::LINK,
+            HeaderName::from_static("x-total-count"),
+        ])
+}
@@ -4,21 +4,34 @@
 
 use anyhow::Result;
 use chrono::DateTime;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 use sqlx::SqlitePool;
-use std::fs::File;
-use std::io::BufReader;
-use tracing::error;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
 
-use crate::api::handlers::bookmarks::{BookmarkQuery, BookmarkRequest};
+use crate::api::format::{render_csv, CsvOptions};
+use crate::api::handlers::bookmarks::{url_key, BookmarkQuery, BookmarkRequest, BookmarkResponse};
+use crate::api::handlers::smart_tags::get_smart_tags;
+use crate::api::handlers::tags::{tag_graph, to_dot};
+use crate::output::Summary;
+use crate::PostID;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) struct LinkDing {
     pub(crate) url: String,
     pub(crate) title: String,
     pub(crate) description: Option<String>,
     pub(crate) notes: Option<String>,
     pub(crate) unread: bool,
+    // missing in exports from older versions of linkding and pinrs
+    #[serde(default)]
+    pub(crate) shared: bool,
+    #[serde(default)]
+    pub(crate) is_archived: bool,
     pub(crate) tag_names: Option<Vec<String>>,
     pub(crate) date_added: String,
     pub(crate) date_modified: String,
@@ -34,48 +47,550 @@ impl From<LinkDing> for BookmarkRequest {
             title: val.title,
             description: val.description,
             notes: val.notes,
+            private_notes: None,
             unread: Some(val.unread),
+            shared: Some(val.shared),
+            is_archived: Some(val.is_archived),
             tag_names: val.tag_names,
+            original_url: None,
+            source: None,
+            referrer: None,
+            selected_text: None,
             date_added: added.map(|a| a.timestamp()),
             date_modified: modified.map(|a| a.timestamp()),
         }
     }
 }
 
-pub(crate) async fn import(path: String, pool: &SqlitePool) -> Result<()> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+impl From<BookmarkResponse> for LinkDing {
+    fn from(val: BookmarkResponse) -> Self {
+        LinkDing {
+            url: val.url,
+            title: val.title,
+            description: val.description,
+            notes: val.notes,
+            unread: val.unread,
+            shared: val.shared,
+            is_archived: val.is_archived,
+            tag_names: Some(val.tag_names),
+            date_added: val.date_added,
+            date_modified: val.date_modified,
+        }
+    }
+}
 
-    let bookmarks: Vec<LinkDing> = serde_json::from_reader(reader)?;
+#[derive(Debug, Serialize)]
+struct ReportDuplicate {
+    line: usize,
+    url: String,
+    // the bookmark already in the database, it's kept as is
+    id: PostID,
+}
 
-    let mut success = 0;
-    let mut failed = vec![];
-    for bookmark in bookmarks {
-        match crate::api::handlers::bookmarks::add_bookmark(pool, bookmark.clone().into()).await {
-            Ok(_id) => success += 1,
-            Err(_err) => {
-                failed.push(bookmark.url);
+#[derive(Debug, Serialize)]
+struct ReportFailed {
+    line: usize,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ReportParseError {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) error: String,
+}
+
+/// Outcome of an import, written as JSON with `--report`
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ImportReport {
+    imported: usize,
+    duplicates: Vec<ReportDuplicate>,
+    failed: Vec<ReportFailed>,
+    pub(crate) parse_errors: Vec<ReportParseError>,
+    // the tags changed by normalize_tag, from the original to the normalized
+    normalized_tags: BTreeMap<String, String>,
+}
+
+impl Summary for ImportReport {
+    fn plain(&self) -> String {
+        format!("Imported {} entries", self.imported)
+    }
+
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("imported", self.imported.to_string()),
+            ("duplicates", self.duplicates.len().to_string()),
+            ("failed", self.failed.len().to_string()),
+            ("parse_errors", self.parse_errors.len().to_string()),
+            ("normalized_tags", self.normalized_tags.len().to_string()),
+        ]
+    }
+}
+
+/// The tag in lowercase, with the words joined by `-` since tags can't have whitespace or commas,
+/// e.g. `Home Lab` becomes `home-lab`.
+fn normalize_tag(tag: &str) -> String {
+    tag.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+}
+
+/// Supported formats for `--import`
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ImportFormat {
+    // the first of IMPORTERS detecting the input
+    Auto,
+    LinkDing,
+    Instapaper,
+}
+
+impl ImportFormat {
+    fn importer(self, input: &str) -> Option<&'static dyn Importer> {
+        match self {
+            ImportFormat::Auto => IMPORTERS
+                .iter()
+                .copied()
+                .find(|importer| importer.detect(input)),
+            ImportFormat::LinkDing => Some(&LinkDingImporter),
+            ImportFormat::Instapaper => Some(&InstapaperImporter),
+        }
+    }
+}
+
+/// A format bookmarks can be imported from. A new format implements this and is added to
+/// `IMPORTERS`, and the rest of the import is the same for all formats.
+trait Importer: Sync {
+    /// Used in the source of the bookmarks, e.g. `import:linkding`
+    fn name(&self) -> &'static str;
+    /// Whether the input looks like this format, without parsing all of it
+    fn detect(&self, input: &str) -> bool;
+    /// The bookmarks in the input, the entries that can't be parsed are added to the report
+    fn parse(&self, input: &str, report: &mut ImportReport) -> Vec<Entry>;
+}
+
+// Tried in order when the format isn't given
+static IMPORTERS: [&dyn Importer; 3] = [&LinkDingImporter, &NdjsonImporter, &InstapaperImporter];
+
+// A bookmark parsed from the input, with the line where it starts
+struct Entry {
+    line: usize,
+    bookmark: BookmarkRequest,
+}
+
+// Line in the input where the entry starts, the entry is a slice of the input
+fn line_of(input: &str, entry: &str) -> usize {
+    let offset = entry.as_ptr() as usize - input.as_ptr() as usize;
+    input[..offset].matches('\n').count() + 1
+}
+
+// linkding JSON, an array of bookmarks as from /api/bookmarks
+struct LinkDingImporter;
+
+impl Importer for LinkDingImporter {
+    fn name(&self) -> &'static str {
+        "linkding"
+    }
+
+    fn detect(&self, input: &str) -> bool {
+        input.trim_start().starts_with('[')
+    }
+
+    fn parse(&self, input: &str, report: &mut ImportReport) -> Vec<Entry> {
+        parse_linkding(input, report)
+    }
+}
+
+fn parse_linkding(input: &str, report: &mut ImportReport) -> Vec<Entry> {
+    let entries: Vec<&RawValue> = match serde_json::from_str(input) {
+        Ok(entries) => entries,
+        Err(err) => {
+            report.parse_errors.push(ReportParseError {
+                line: err.line(),
+                column: err.column(),
+                error: err.to_string(),
+            });
+            return vec![];
+        }
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            let line = line_of(input, entry.get());
+            match serde_json::from_str::<LinkDing>(entry.get()) {
+                Ok(bookmark) => Some(Entry {
+                    line,
+                    bookmark: bookmark.into(),
+                }),
+                Err(err) => {
+                    report.parse_errors.push(ReportParseError {
+                        line: line + err.line() - 1,
+                        column: err.column(),
+                        error: err.to_string(),
+                    });
+                    None
+                }
             }
-        };
+        })
+        .collect()
+}
+
+// One linkding bookmark per line, e.g. from jq -c '.results[]'
+struct NdjsonImporter;
+
+impl Importer for NdjsonImporter {
+    fn name(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn detect(&self, input: &str) -> bool {
+        input.trim_start().starts_with('{')
+    }
+
+    fn parse(&self, input: &str, report: &mut ImportReport) -> Vec<Entry> {
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, entry)| !entry.trim().is_empty())
+            .filter_map(|(i, entry)| match serde_json::from_str::<LinkDing>(entry) {
+                Ok(bookmark) => Some(Entry {
+                    line: i + 1,
+                    bookmark: bookmark.into(),
+                }),
+                Err(err) => {
+                    report.parse_errors.push(ReportParseError {
+                        line: i + 1,
+                        column: err.column(),
+                        error: err.to_string(),
+                    });
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+// Records of a CSV file with the line each one starts at. Quoted fields can contain commas,
+// newlines and quotes written as "".
+fn parse_csv(input: &str) -> Vec<(usize, Vec<String>)> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut record_line = 1;
+
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                // skip empty lines
+                record.clear();
+                record_line = line;
+            }
+            _ => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        }
+    }
+
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push((record_line, record));
     }
 
-    println!("Imported {success} entries");
+    records
+}
+
+// Instapaper exports CSV with the columns URL, Title, Selection, Folder and Timestamp. The
+// Unread and Archive folders become flags, other folders tags.
+struct InstapaperImporter;
+
+impl Importer for InstapaperImporter {
+    fn name(&self) -> &'static str {
+        "instapaper"
+    }
+
+    fn detect(&self, input: &str) -> bool {
+        let header = input.trim_start().lines().next().unwrap_or_default();
+        let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+        columns.contains(&"URL") && columns.contains(&"Folder")
+    }
+
+    fn parse(&self, input: &str, report: &mut ImportReport) -> Vec<Entry> {
+        parse_instapaper(input, report)
+    }
+}
+
+fn parse_instapaper(input: &str, report: &mut ImportReport) -> Vec<Entry> {
+    let mut records = parse_csv(input).into_iter();
+    let Some((_, header)) = records.next() else {
+        return vec![];
+    };
+    let column = |name: &str| header.iter().position(|column| column.trim() == name);
+    let Some(url_column) = column("URL") else {
+        report.parse_errors.push(ReportParseError {
+            line: 1,
+            column: 1,
+            error: "Missing URL column".to_owned(),
+        });
+        return vec![];
+    };
+    let title_column = column("Title");
+    let selection_column = column("Selection");
+    let folder_column = column("Folder");
+    let timestamp_column = column("Timestamp");
+
+    records
+        .filter_map(|(line, record)| {
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|column| record.get(column))
+                    .map(|field| field.trim())
+                    .filter(|field| !field.is_empty())
+            };
 
-    if !failed.is_empty() {
+            let Some(url) = field(Some(url_column)) else {
+                report.parse_errors.push(ReportParseError {
+                    line,
+                    column: url_column + 1,
+                    error: "Missing URL".to_owned(),
+                });
+                return None;
+            };
+            let folder = field(folder_column);
+            // a folder isn't always a valid tag, so it's normalized even with preserve_tags
+            let tag_names = folder
+                .filter(|folder| !matches!(*folder, "Unread" | "Archive"))
+                .map(|folder| {
+                    let tag_name = normalize_tag(folder);
+                    if tag_name != folder {
+                        report
+                            .normalized_tags
+                            .insert(folder.to_owned(), tag_name.clone());
+                    }
+                    vec![tag_name]
+                });
+            let date_added = field(timestamp_column).and_then(|timestamp| timestamp.parse().ok());
+
+            Some(Entry {
+                line,
+                bookmark: BookmarkRequest {
+                    url: url.to_owned(),
+                    title: field(title_column).unwrap_or(url).to_owned(),
+                    notes: field(selection_column).map(String::from),
+                    unread: Some(folder == Some("Unread")),
+                    is_archived: Some(folder == Some("Archive")),
+                    tag_names,
+                    date_added,
+                    date_modified: date_added,
+                    ..Default::default()
+                },
+            })
+        })
+        .collect()
+}
+
+/// Imports the entries in the input. Unless `preserve_tags`, the tags are normalized, see
+/// `normalize_tag`.
+pub(crate) async fn import_entries(
+    input: &str,
+    format: ImportFormat,
+    pool: &SqlitePool,
+    preserve_tags: bool,
+) -> ImportReport {
+    let mut report = ImportReport::default();
+
+    let Some(importer) = format.importer(input) else {
+        let names: Vec<&str> = IMPORTERS.iter().map(|importer| importer.name()).collect();
+        report.parse_errors.push(ReportParseError {
+            line: 1,
+            column: 1,
+            error: format!("Unknown format, expected one of: {}", names.join(", ")),
+        });
+        return report;
+    };
+    let entries = importer.parse(input, &mut report);
+
+    for Entry { line, mut bookmark } in entries {
+        bookmark.source = Some(format!("import:{}", importer.name()));
+        if let Some(tag_names) = bookmark.tag_names.as_mut().filter(|_| !preserve_tags) {
+            let mut normalized: Vec<String> = vec![];
+            for tag in tag_names.iter() {
+                let tag_name = normalize_tag(tag);
+                if tag_name != *tag {
+                    report.normalized_tags.insert(tag.clone(), tag_name.clone());
+                }
+                // e.g. Rust and rust
+                if !tag_name.is_empty() && !normalized.contains(&tag_name) {
+                    normalized.push(tag_name);
+                }
+            }
+            *tag_names = normalized;
+        }
+
+        // the same page with another url too, e.g. with the host in uppercase
+        if let Ok(Some(id)) =
+            sqlx::query_scalar::<_, PostID>("SELECT id FROM posts WHERE url = $1 OR url_key = $2")
+                .bind(&bookmark.url)
+                .bind(url_key(&bookmark.url))
+                .fetch_optional(pool)
+                .await
+        {
+            report.duplicates.push(ReportDuplicate {
+                line,
+                url: bookmark.url,
+                id,
+            });
+            continue;
+        }
+
+        let url = bookmark.url.clone();
+        match crate::api::handlers::bookmarks::add_bookmark(pool, bookmark).await {
+            Ok(_id) => report.imported += 1,
+            Err(_err) => report.failed.push(ReportFailed { line, url }),
+        }
+    }
+
+    report
+}
+
+/// Imports the bookmarks from a linkding JSON export or an Instapaper CSV export, see
+/// `IMPORTERS`, read from stdin if the path is `-`. Bookmarks with an url, or `url_key`, that
+/// already exists are skipped. The tags are normalized unless `preserve_tags`. With `report`, the
+/// outcome is also written there as JSON.
+pub(crate) async fn import(
+    path: String,
+    format: ImportFormat,
+    pool: &SqlitePool,
+    report: Option<&Path>,
+    preserve_tags: bool,
+) -> Result<ImportReport> {
+    let input = if path == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path)?
+    };
+
+    let result = import_entries(&input, format, pool, preserve_tags).await;
+
+    if !result.normalized_tags.is_empty() {
+        info!(
+            "Normalized {} tags, see --report for the mapping",
+            result.normalized_tags.len()
+        );
+    }
+    if !result.duplicates.is_empty() {
+        info!(
+            "Skipped {} already existing entries",
+            result.duplicates.len()
+        );
+    }
+    if !result.failed.is_empty() {
+        let failed: Vec<&str> = result
+            .failed
+            .iter()
+            .map(|failed| failed.url.as_str())
+            .collect();
         error!("Failed to import:\n{}", failed.join("\n"));
     }
+    for parse_error in &result.parse_errors {
+        error!(
+            "Failed to parse entry at line {}: {}",
+            parse_error.line, parse_error.error
+        );
+    }
 
-    Ok(())
+    if let Some(report) = report {
+        fs::write(report, serde_json::to_string_pretty(&result)?)?;
+    }
+
+    Ok(result)
 }
 
-pub(crate) async fn export_html(pool: &SqlitePool) -> Result<()> {
+// Private notes are only exported when asked for, appended to the notes
+fn notes_for_export(bookmark: &mut BookmarkResponse, include_private_notes: bool) {
+    let private_notes = bookmark.private_notes.take().unwrap_or_default();
+    if !include_private_notes || private_notes.is_empty() {
+        return;
+    }
+
+    bookmark.notes = match bookmark.notes.take().filter(|notes| !notes.is_empty()) {
+        Some(notes) => Some(format!("{notes}\n---\n{private_notes}")),
+        None => Some(private_notes),
+    };
+}
+
+/// Which bookmarks to export, and where
+#[derive(Default)]
+pub(crate) struct ExportOptions {
+    pub(crate) include_private_notes: bool,
+    // only the bookmarks matching, e.g. with tag, unread or added_since
+    pub(crate) query: BookmarkQuery,
+    // a file per tag in the directory instead of stdout, bookmarks without tags are left out
+    pub(crate) split_by_tag: Option<PathBuf>,
+}
+
+// Writes the bookmarks rendered by the exporter to stdout, or a file per tag
+async fn export(
+    pool: &SqlitePool,
+    options: ExportOptions,
+    extension: &str,
+    render: impl Fn(Vec<BookmarkResponse>) -> Result<String>,
+) -> Result<()> {
     let query = BookmarkQuery {
         limit: Some(0),
-        ..Default::default()
+        smart_tags: get_smart_tags(pool).await,
+        include: Some("notes".to_owned()),
+        ..options.query
+    };
+
+    let mut bookmarks = crate::api::handlers::bookmarks::get_bookmarks(pool, query).await;
+    for bookmark in &mut bookmarks {
+        notes_for_export(bookmark, options.include_private_notes);
+    }
+
+    let Some(dir) = options.split_by_tag else {
+        println!("{}", render(bookmarks)?);
+        return Ok(());
     };
 
-    let bookmarks = crate::api::handlers::bookmarks::get_bookmarks(pool, query).await;
+    let mut by_tag: BTreeMap<String, Vec<BookmarkResponse>> = BTreeMap::new();
+    for bookmark in bookmarks {
+        for tag in &bookmark.tag_names {
+            by_tag
+                .entry(tag.clone())
+                .or_default()
+                .push(bookmark.clone());
+        }
+    }
+    fs::create_dir_all(&dir)?;
+    for (tag, bookmarks) in by_tag {
+        // tags can't have whitespace, but can have path separators
+        let name = tag.replace(['/', '\\'], "_");
+        fs::write(dir.join(format!("{name}.{extension}")), render(bookmarks)?)?;
+    }
+    Ok(())
+}
 
+fn render_html(bookmarks: Vec<BookmarkResponse>) -> String {
     let mut result = vec![
         "<!DOCTYPE NETSCAPE-Bookmark-file-1>".to_owned(),
         "<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">".to_owned(),
@@ -119,6 +634,351 @@ pub(crate) async fn export_html(pool: &SqlitePool) -> Result<()> {
     }
     result.push("</DL><p>".to_owned());
 
-    println!("{}", result.join("\n"));
+    result.join("\n")
+}
+
+pub(crate) async fn export_html(pool: &SqlitePool, options: ExportOptions) -> Result<()> {
+    export(pool, options, "html", |bookmarks| {
+        Ok(render_html(bookmarks))
+    })
+    .await
+}
+
+// The same JSON as linkding's /api/bookmarks results, which --import reads back
+fn render_linkding(bookmarks: Vec<BookmarkResponse>) -> Result<String> {
+    let bookmarks: Vec<LinkDing> = bookmarks.into_iter().map(LinkDing::from).collect();
+    Ok(serde_json::to_string(&bookmarks)?)
+}
+
+pub(crate) async fn export_linkding(pool: &SqlitePool, options: ExportOptions) -> Result<()> {
+    export(pool, options, "json", render_linkding).await
+}
+
+pub(crate) async fn export_csv(
+    pool: &SqlitePool,
+    options: ExportOptions,
+    csv: CsvOptions,
+) -> Result<()> {
+    export(pool, options, "csv", |bookmarks| {
+        Ok(render_csv(&bookmarks, &csv)
+            .trim_end_matches("\r\n")
+            .to_owned())
+    })
+    .await
+}
+
+/// Prints the tags and how many bookmarks they share as a Graphviz graph
+pub(crate) async fn export_graphviz(pool: &SqlitePool) -> Result<()> {
+    let graph = tag_graph(pool, 1).await?;
+    println!("{}", to_dot(&graph));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_db;
+
+    #[tokio::test]
+    async fn test_import_report() {
+        let pool = setup_db(true).await;
+
+        let input = r#"[
+    {"url": "https://a.example", "title": "a", "unread": false, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"},
+    {"url": "https://A.example", "title": "a", "unread": false, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"},
+    {
+        "url": "https://b.example",
+        "unread": false
+    }
+]"#;
+
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, false).await;
+
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].line, 3);
+        assert_eq!(report.duplicates[0].url, "https://A.example");
+        assert!(report.failed.is_empty());
+        assert_eq!(report.parse_errors.len(), 1);
+        assert_eq!(report.parse_errors[0].line, 7);
+
+        let report = import_entries("[{", ImportFormat::LinkDing, &pool, false).await;
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.parse_errors.len(), 1);
+        assert_eq!(report.parse_errors[0].line, 1);
+    }
+
+    #[tokio::test]
+    async fn test_import_linkding_flags() {
+        let pool = setup_db(true).await;
+
+        let input = r#"[
+    {"url": "https://a.example", "title": "a", "unread": false, "shared": true, "is_archived": true, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"},
+    {"url": "https://b.example", "title": "b", "unread": true, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+]"#;
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, false).await;
+        assert_eq!(report.imported, 2);
+
+        let query = BookmarkQuery {
+            limit: Some(0),
+            ..Default::default()
+        };
+        let mut bookmarks = crate::api::handlers::bookmarks::get_bookmarks(&pool, query).await;
+        bookmarks.sort_by(|a, b| a.url.cmp(&b.url));
+        assert!(bookmarks[0].shared && bookmarks[0].is_archived);
+        assert!(!bookmarks[1].shared && !bookmarks[1].is_archived);
+
+        let exported = LinkDing::from(bookmarks.remove(0));
+        assert!(exported.shared && exported.is_archived);
+    }
+
+    #[tokio::test]
+    async fn test_import_detect() {
+        let pool = setup_db(true).await;
+
+        let linkding = r#"[{"url": "https://a.example", "title": "a", "unread": false, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}]"#;
+        let instapaper =
+            "URL,Title,Selection,Folder,Timestamp\nhttps://b.example,B,,Unread,1700000000\n";
+
+        assert_eq!(
+            ImportFormat::Auto.importer(linkding).map(|i| i.name()),
+            Some("linkding")
+        );
+        assert_eq!(
+            ImportFormat::Auto.importer(instapaper).map(|i| i.name()),
+            Some("instapaper")
+        );
+
+        for input in [linkding, instapaper] {
+            let report = import_entries(input, ImportFormat::Auto, &pool, false).await;
+            assert_eq!(report.imported, 1);
+        }
+
+        let report = import_entries("<html>", ImportFormat::Auto, &pool, false).await;
+        assert_eq!(report.imported, 0);
+        assert_eq!(
+            report.parse_errors[0].error,
+            "Unknown format, expected one of: linkding, ndjson, instapaper"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_ndjson() {
+        let pool = setup_db(true).await;
+
+        let input = r#"{"url": "https://a.example", "title": "a", "unread": true, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+
+{"url": "https://b.example"}
+{"url": "https://c.example", "title": "c", "unread": false, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+"#;
+        assert_eq!(
+            ImportFormat::Auto.importer(input).map(|i| i.name()),
+            Some("ndjson")
+        );
+
+        let report = import_entries(input, ImportFormat::Auto, &pool, false).await;
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.parse_errors.len(), 1);
+        assert_eq!(report.parse_errors[0].line, 3);
+    }
+
+    #[tokio::test]
+    async fn test_import_normalize_tags() {
+        let pool = setup_db(true).await;
+
+        let input = r#"[
+    {"url": "https://a.example", "title": "a", "unread": false, "tag_names": ["Rust", "rust", " Home  Lab ", "web"], "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+]"#;
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, false).await;
+        assert_eq!(report.imported, 1);
+        assert_eq!(
+            report.normalized_tags,
+            BTreeMap::from([
+                (" Home  Lab ".to_owned(), "home-lab".to_owned()),
+                ("Rust".to_owned(), "rust".to_owned()),
+            ])
+        );
+
+        let input = r#"[
+    {"url": "https://b.example", "title": "b", "unread": false, "tag_names": ["Rust"], "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+]"#;
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, true).await;
+        assert_eq!(report.imported, 1);
+        assert!(report.normalized_tags.is_empty());
+
+        let query = BookmarkQuery {
+            limit: Some(0),
+            ..Default::default()
+        };
+        let mut bookmarks = crate::api::handlers::bookmarks::get_bookmarks(&pool, query).await;
+        bookmarks.sort_by(|a, b| a.url.cmp(&b.url));
+        assert_eq!(bookmarks[0].tag_names, vec!["rust", "home-lab", "web"]);
+        assert_eq!(bookmarks[1].tag_names, vec!["Rust"]);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let records = parse_csv("a,\"b, \"\"c\"\"\"\r\n\n\"d\ne\",f\ng");
+
+        assert_eq!(
+            records,
+            vec![
+                (1, vec!["a".to_owned(), "b, \"c\"".to_owned()]),
+                (3, vec!["d\ne".to_owned(), "f".to_owned()]),
+                (5, vec!["g".to_owned()]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_instapaper() {
+        let pool = setup_db(true).await;
+
+        let input = "URL,Title,Selection,Folder,Timestamp
+https://a.example,A,,Unread,1700000000
+https://b.example,B,\"quoted, text\",Archive,1700000000
+https://c.example,,,Home Lab,1700000000
+,missing url,,Unread,
+";
+
+        let report = import_entries(input, ImportFormat::Instapaper, &pool, false).await;
+        assert_eq!(report.imported, 3);
+        assert_eq!(report.parse_errors.len(), 1);
+        assert_eq!(report.parse_errors[0].line, 5);
+
+        let query = BookmarkQuery {
+            limit: Some(0),
+            ..Default::default()
+        };
+        let mut bookmarks = crate::api::handlers::bookmarks::get_bookmarks(&pool, query).await;
+        bookmarks.sort_by(|a, b| a.url.cmp(&b.url));
+
+        assert!(bookmarks[0].unread);
+        assert!(bookmarks[0].tag_names.is_empty());
+        assert_eq!(bookmarks[0].date_added, "2023-11-14T22:13:20+00:00");
+        assert!(bookmarks[1].is_archived);
+        assert_eq!(bookmarks[1].notes.as_deref(), Some("quoted, text"));
+        assert_eq!(bookmarks[2].title, "https://c.example");
+        assert_eq!(bookmarks[2].tag_names, vec!["home-lab"]);
+        assert_eq!(report.normalized_tags["Home Lab"], "home-lab");
+        assert_eq!(bookmarks[2].source.as_deref(), Some("import:instapaper"));
+    }
+
+    #[tokio::test]
+    async fn test_export_split_by_tag() {
+        let pool = setup_db(true).await;
+
+        let input = r#"[
+    {"url": "https://a.example", "title": "a", "unread": true, "tag_names": ["rust", "web/dev"], "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"},
+    {"url": "https://b.example", "title": "b", "unread": false, "tag_names": ["rust"], "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"},
+    {"url": "https://c.example", "title": "c", "unread": true, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+]"#;
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, false).await;
+        assert_eq!(report.imported, 3);
+
+        let dir = std::env::temp_dir().join(format!("pinrs-test-export-{}", std::process::id()));
+        let options = ExportOptions {
+            query: BookmarkQuery {
+                unread: Some("yes".to_owned()),
+                ..Default::default()
+            },
+            split_by_tag: Some(dir.clone()),
+            ..Default::default()
+        };
+        export_linkding(&pool, options).await.unwrap();
+
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["rust.json", "web_dev.json"]);
+
+        let rust: Vec<LinkDing> =
+            serde_json::from_str(&fs::read_to_string(dir.join("rust.json")).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rust.len(), 1);
+        assert_eq!(rust[0].url, "https://a.example");
+    }
+
+    #[tokio::test]
+    async fn test_export_linkding() {
+        let pool = setup_db(true).await;
+
+        let input = r#"[
+    {"url": "https://a.example", "title": "a", "description": "d", "notes": "n", "unread": true, "shared": true, "is_archived": true, "tag_names": ["x", "y"], "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-02T00:00:00Z"},
+    {"url": "https://b.example", "title": "b", "unread": false, "date_added": "2025-01-01T00:00:00Z", "date_modified": "2025-01-01T00:00:00Z"}
+]"#;
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, false).await;
+        assert_eq!(report.imported, 2);
+
+        let query = BookmarkQuery {
+            limit: Some(0),
+            include: Some("notes".to_owned()),
+            ..Default::default()
+        };
+        let bookmarks = crate::api::handlers::bookmarks::get_bookmarks(&pool, query).await;
+        let output = render_linkding(bookmarks).unwrap();
+
+        let mut exported: Vec<serde_json::Value> = serde_json::from_str(&output).unwrap();
+        exported.sort_by_key(|bookmark| bookmark["url"].to_string());
+        assert_eq!(
+            exported[0],
+            serde_json::json!({
+                "url": "https://a.example",
+                "title": "a",
+                "description": "d",
+                "notes": "n",
+                "unread": true,
+                "shared": true,
+                "is_archived": true,
+                "tag_names": ["x", "y"],
+                "date_added": "2025-01-01T00:00:00+00:00",
+                "date_modified": "2025-01-02T00:00:00+00:00",
+            })
+        );
+        assert_eq!(exported[1]["url"], "https://b.example");
+        assert_eq!(exported[1]["tag_names"], serde_json::json!([]));
+
+        // and imported again as is
+        let pool = setup_db(true).await;
+        let report = import_entries(&output, ImportFormat::LinkDing, &pool, false).await;
+        assert_eq!(report.imported, 2);
+        assert!(report.parse_errors.is_empty());
+    }
+
+    #[test]
+    fn test_export_csv_columns() {
+        use crate::api::format::CsvColumn;
+
+        let bookmarks = vec![BookmarkResponse {
+            url: "https://a.example".to_owned(),
+            title: "a; \"quoted\"".to_owned(),
+            tag_names: vec!["x".to_owned(), "y".to_owned()],
+            date_added: "2025-01-01T00:00:00+00:00".to_owned(),
+            ..Default::default()
+        }];
+
+        let csv = CsvOptions {
+            columns: vec![
+                CsvColumn::Url,
+                CsvColumn::Title,
+                CsvColumn::TagNames,
+                CsvColumn::DateAdded,
+            ],
+            delimiter: ';',
+            header: true,
+        };
+        assert_eq!(
+            render_csv(&bookmarks, &csv),
+            "url;title;tag_names;date_added\r\nhttps://a.example;\"a; \"\"quoted\"\"\";x y;2025-01-01T00:00:00+00:00\r\n"
+        );
+
+        let csv = CsvOptions {
+            columns: vec![CsvColumn::Title],
+            delimiter: '\t',
+            header: false,
+        };
+        assert_eq!(render_csv(&bookmarks, &csv), "\"a; \"\"quoted\"\"\"\r\n");
+    }
+}
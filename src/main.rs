@@ -2,98 +2,194 @@
 //
 // SPDX-License-Identifier: AGPL-3.0-or-later
 
-use axum::{
-    extract::{Request, State},
-    http::StatusCode,
-    middleware::{self, Next},
-    response::Response,
-    Router, ServiceExt,
-};
-use clap::Parser;
+use api::format::{CsvColumn, CsvOptions};
+use axum::{extract::DefaultBodyLimit, http::HeaderValue, middleware, Router};
+use clap::{ArgGroup, Parser};
 use directories::ProjectDirs;
-use hyper::header::{self};
+use import::ImportFormat;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use sqlx::ConnectOptions;
 use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::{env, path::Path};
-use tower::Layer;
-use tower_http::cors::CorsLayer;
-use tower_http::normalize_path::NormalizePathLayer;
 use tower_http::trace::TraceLayer;
-use tracing::error;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
 pub mod api;
+mod audit;
+mod auth;
+mod collation;
+mod config;
+mod cors;
 mod import;
+mod metrics;
+mod notes;
+mod output;
+mod public;
+mod publish;
+mod read_only;
+mod restore;
+mod retention;
+mod rewrite;
+mod security_headers;
+mod server;
+mod stats;
+#[cfg(target_os = "linux")]
+mod systemd;
+mod totp;
 
 type PostID = i64;
 type TagID = PostID;
 
 pub struct AppState {
     pool: SqlitePool,
-    token: String,
+    // tried in turn, see auth::AuthProvider
+    auth_providers: Vec<Box<dyn auth::AuthProvider>>,
+    // also in auth_providers, to check the cookie
+    sessions: Arc<auth::Sessions>,
+    rewrite_rules: rewrite::RewriteRules,
+    retention_rules: retention::RetentionRules,
+    // origins allowed to use the API from a browser, None allows any
+    api_cors_origins: Option<Vec<HeaderValue>>,
+    metrics: metrics::Metrics,
+    security_headers: security_headers::SecurityHeaders,
+    // the public API is only served with a rate limit
+    public_rate_limit: Option<public::RateLimit>,
+    // only GET requests are served, see read_only.rs
+    read_only: bool,
+    // longest notes accepted in bytes, see notes.rs
+    max_notes_size: Option<usize>,
 }
 
+impl AppState {
+    fn new(pool: SqlitePool, token: String) -> Self {
+        let sessions = Arc::new(auth::Sessions::default());
+        AppState {
+            pool,
+            // the cookie first, the token logs an error when missing
+            auth_providers: vec![
+                Box::new(auth::SessionCookie(sessions.clone())),
+                Box::new(auth::StaticToken(token)),
+            ],
+            sessions,
+            rewrite_rules: rewrite::RewriteRules::default(),
+            retention_rules: retention::RetentionRules::default(),
+            api_cors_origins: None,
+            metrics: metrics::Metrics::default(),
+            security_headers: security_headers::SecurityHeaders::default(),
+            public_rate_limit: None,
+            read_only: false,
+            max_notes_size: None,
+        }
+    }
+}
+
+// the flags are the command line options
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
+#[command(group(ArgGroup::new("input").args(["import", "import_instapaper"])))]
+#[command(group(ArgGroup::new("export").args(["export_html", "export_linkding", "export_csv"])))]
 pub struct Arguments {
     #[arg(long)]
     import: Option<String>,
+    #[arg(long = "import-instapaper")]
+    import_instapaper: Option<String>,
+    #[arg(long, requires = "input")]
+    report: Option<String>,
+    // the summary of an import or restore
+    #[arg(long, value_enum, default_value_t)]
+    format: output::OutputFormat,
+    // keep the imported tags as they are instead of normalizing them
+    #[arg(long = "preserve-tags", requires = "input")]
+    preserve_tags: bool,
     #[arg(long = "export-html")]
     export_html: bool,
+    #[arg(long = "export-linkding")]
+    export_linkding: bool,
+    #[arg(long = "export-csv")]
+    export_csv: bool,
+    // with --export-csv, the columns in order, e.g. url,title,tags,date_added
+    #[arg(long, value_enum, value_delimiter = ',', requires = "export_csv")]
+    columns: Vec<CsvColumn>,
+    #[arg(long, default_value_t = ',', requires = "export_csv")]
+    delimiter: char,
+    #[arg(long = "no-header", requires = "export_csv")]
+    no_header: bool,
+    #[arg(long = "export-graphviz")]
+    export_graphviz: bool,
+    // the smart tags, templates and token usage, without secrets
+    #[arg(long = "export-config")]
+    export_config: bool,
+    #[arg(long = "import-config")]
+    import_config: Option<String>,
+    #[arg(long = "include-private-notes")]
+    include_private_notes: bool,
+    // the export filters, all tags have to match
+    #[arg(long, requires = "export")]
+    tag: Vec<String>,
+    #[arg(long, requires = "export")]
+    unread: bool,
+    #[arg(long, requires = "export")]
+    since: Option<String>,
+    #[arg(long = "split-by-tag", requires = "export")]
+    split_by_tag: Option<String>,
+    #[arg(long)]
+    publish: Option<String>,
+    #[arg(long = "publish-url", default_value = "/")]
+    publish_url: String,
+    #[arg(long)]
+    restore: Option<String>,
+    // with --restore, remove all bookmarks and tags first instead of merging
+    #[arg(long, requires = "restore")]
+    replace: bool,
+    #[arg(long = "totp-setup")]
+    totp_setup: bool,
+    #[arg(long = "totp-disable")]
+    totp_disable: bool,
 }
 
-async fn auth(
-    State(state): State<Arc<AppState>>,
-    req: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    let mut token = req
-        .headers()
-        .get(header::AUTHORIZATION)
-        .and_then(|auth_header| auth_header.to_str().ok())
-        .and_then(|auth_value| {
-            auth_value
-                .strip_prefix("Token ")
-                .map(std::borrow::ToOwned::to_owned)
-        });
-
-    if token.is_none() {
-        token = req
-            .headers()
-            .get(header::AUTHORIZATION)
-            .and_then(|auth_header| auth_header.to_str().ok())
-            .and_then(|auth_value| {
-                auth_value
-                    .strip_prefix("Bearer ")
-                    .map(std::borrow::ToOwned::to_owned)
-            });
-    }
-
-    if token.is_none() {
-        error!("No token");
-        return Err(StatusCode::UNAUTHORIZED);
+fn csv_options(args: &Arguments) -> CsvOptions {
+    let mut csv = CsvOptions {
+        delimiter: args.delimiter,
+        header: !args.no_header,
+        ..Default::default()
+    };
+    if !args.columns.is_empty() {
+        csv.columns.clone_from(&args.columns);
     }
+    csv
+}
 
-    let token = token.unwrap();
-
-    if token == state.token {
-        Ok(next.run(req).await)
-    } else {
-        error!("Failed to authenticate with token: {}", token);
-        Err(StatusCode::UNAUTHORIZED)
+fn export_options(args: Arguments) -> Result<import::ExportOptions, anyhow::Error> {
+    if let Some(since) = &args.since {
+        api::handlers::bookmarks::timestamp_before(since)
+            .ok_or_else(|| anyhow::anyhow!("Invalid --since: {since}"))?;
     }
+    Ok(import::ExportOptions {
+        include_private_notes: args.include_private_notes,
+        query: api::handlers::bookmarks::BookmarkQuery {
+            tag: args.tag,
+            unread: args.unread.then(|| "yes".to_owned()),
+            added_since: args.since,
+            ..Default::default()
+        },
+        split_by_tag: args.split_by_tag.map(PathBuf::from),
+    })
 }
 
-#[allow(clippy::too_many_lines)]
 pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
+    let read_only = !memory && server::env_opt("PINRS_READ_ONLY").unwrap_or(false);
+    let mode = if read_only { "ro" } else { "rwc" };
     let db_path = if memory {
         "sqlite::memory:".to_owned()
     } else if let Ok(env_db) = env::var("PINRS_DB") {
         let path = Path::new(&env_db);
         let dir = path.parent().expect("Couldn't get directory of database");
         match fs::create_dir_all(dir) {
-            Ok(()) => format!("sqlite://{env_db}?mode=rwc"),
+            Ok(()) => format!("sqlite://{env_db}?mode={mode}"),
             Err(err) => panic!("Failed to create database: {err}"),
         }
     } else {
@@ -102,21 +198,36 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
                 let dir = base_dirs.data_dir();
                 match fs::create_dir_all(dir) {
                     Ok(()) => format!(
-                        "sqlite://{}/pinrs.db?mode=rwc",
+                        "sqlite://{}/pinrs.db?mode={mode}",
                         dir.to_string_lossy().into_owned()
                     ),
-                    Err(_) => "sqlite://pinrs.db?mode=rwc".to_owned(),
+                    Err(_) => format!("sqlite://pinrs.db?mode={mode}"),
                 }
             }
-            None => "sqlite://pinrs.db?mode=rwc".to_owned(),
+            None => format!("sqlite://pinrs.db?mode={mode}"),
         }
     };
 
-    println!("Using database: {db_path}");
+    // not in the output of the commands, e.g. an export to stdout
+    eprintln!("Using database: {db_path}");
+
+    open_db(&db_path, read_only).await
+}
+
+/// Connects to the database at `db_path`, e.g. `sqlite::memory:`, creating the schema unless
+/// it's read-only.
+pub(crate) async fn open_db(db_path: &str, read_only: bool) -> SqlitePool {
+    // the language titles are sorted for, as in the environment of the locale settings
+    let locale = ["PINRS_LOCALE", "LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .find_map(|name| env::var(name).ok())
+        .unwrap_or_default();
 
-    let options = SqliteConnectOptions::from_str(&db_path)
+    let options = SqliteConnectOptions::from_str(db_path)
         .expect("Failed to parse database string")
-        .create_if_missing(true)
+        .create_if_missing(!read_only)
+        .read_only(read_only)
+        .collation("title", collation::compare(&locale))
         .log_statements(tracing::log::LevelFilter::Debug);
 
     let pool = SqlitePoolOptions::new()
@@ -125,6 +236,17 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
         .await
         .expect("Failed to connect to database");
 
+    // a replica has the schema of the primary
+    if !read_only {
+        create_schema(&pool).await;
+    }
+
+    pool
+}
+
+/// Creates the tables, or adds what's missing to those of an older version.
+#[allow(clippy::too_many_lines)]
+pub(crate) async fn create_schema(pool: &SqlitePool) {
     let _ = sqlx::query(
         r"
             CREATE TABLE IF NOT EXISTS posts (
@@ -133,13 +255,84 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
                 title TEXT NOT NULL,
                 description TEXT,
                 notes TEXT,
+                private_notes TEXT,
                 unread BOOLEAN,
+                shared BOOLEAN,
+                is_archived BOOLEAN,
+                original_url TEXT,
+                source TEXT,
+                referrer TEXT,
+                uuid TEXT,
+                url_key TEXT,
                 date_added INTEGER,
                 date_modified INTEGER
             );
         ",
     )
-    .execute(&pool)
+    .execute(pool)
+    .await;
+
+    // columns added after the table was first created
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN shared BOOLEAN")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN private_notes TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN is_archived BOOLEAN")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN original_url TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN source TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN referrer TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN uuid TEXT")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE posts ADD COLUMN url_key TEXT")
+        .execute(pool)
+        .await;
+
+    // duplicates of the same page with another url, see bookmarks::url_key
+    let _ = sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS posts_url_key ON posts(url_key)")
+        .execute(pool)
+        .await;
+    // also made again when the keys are made differently, e.g. by an older version
+    if let Ok(mut conn) = pool.acquire().await {
+        let _ = api::handlers::bookmarks::rebuild_url_keys(&mut conn).await;
+    }
+
+    // a random (version 4) uuid for every bookmark, set by a trigger to cover all the ways
+    // bookmarks are added
+    let uuid = r"
+        lower(hex(randomblob(4))) || '-' || lower(hex(randomblob(2))) || '-4' ||
+        substr(lower(hex(randomblob(2))), 2) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(lower(hex(randomblob(2))), 2) || '-' ||
+        lower(hex(randomblob(6)))
+    ";
+    let _ = sqlx::query(&format!(
+        "UPDATE posts SET uuid = {uuid} WHERE uuid IS NULL"
+    ))
+    .execute(pool)
+    .await;
+    let _ = sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS posts_uuid ON posts(uuid)")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(&format!(
+        r"
+            CREATE TRIGGER IF NOT EXISTS posts_uuid_ai AFTER INSERT ON posts
+                WHEN new.uuid IS NULL
+                BEGIN
+                    UPDATE posts SET uuid = {uuid} WHERE id = new.id;
+                END;
+        "
+    ))
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
@@ -151,7 +344,7 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
              );
         ",
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
@@ -165,7 +358,110 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
             );
         ",
     )
-    .execute(&pool)
+    .execute(pool)
+    .await;
+
+    // post_tag is looked up by tag when filtering on tags, the unique index only helps by post
+    let _ = sqlx::query("CREATE INDEX IF NOT EXISTS post_tag_tag_id ON post_tag(tag_id, post_id)")
+        .execute(pool)
+        .await;
+
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS audit (
+                id INTEGER PRIMARY KEY,
+                date INTEGER NOT NULL,
+                token TEXT NOT NULL,
+                method TEXT NOT NULL,
+                route TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                ids TEXT NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    // devices using the token, see auth::record_usage
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS token_usage (
+                token TEXT NOT NULL,
+                user_agent TEXT NOT NULL,
+                ip TEXT NOT NULL,
+                first_used INTEGER NOT NULL,
+                last_used INTEGER NOT NULL,
+                requests INTEGER NOT NULL,
+                UNIQUE(token, user_agent, ip)
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS settings (
+                name TEXT PRIMARY KEY,
+                value BLOB NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    // virtual tags matching the bookmarks of a listing query, see api::handlers::smart_tags
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS smart_tags (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                query TEXT NOT NULL,
+                date_added INTEGER NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    // deleted bookmarks, see api::handlers::graveyard
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS graveyard (
+                id INTEGER PRIMARY KEY,
+                url TEXT NOT NULL,
+                title TEXT NOT NULL,
+                tag_names TEXT NOT NULL,
+                deleted_at INTEGER NOT NULL,
+                post_id INTEGER,
+                post TEXT
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+    // the whole bookmark for POST /api/undo
+    let _ = sqlx::query("ALTER TABLE graveyard ADD COLUMN post_id INTEGER")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE graveyard ADD COLUMN post TEXT")
+        .execute(pool)
+        .await;
+
+    // settings for new bookmarks, see api::handlers::templates
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS templates (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                tag_names TEXT NOT NULL,
+                unread BOOLEAN,
+                description_prefix TEXT,
+                date_added INTEGER NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
     .await;
 
     // ---------------------- FTS
@@ -184,7 +480,7 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
             );
         ",
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
@@ -196,7 +492,7 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
                 END;
     ",
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
@@ -208,7 +504,7 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
                 END;
     ",
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
     let _ = sqlx::query(
@@ -222,52 +518,385 @@ pub(crate) async fn setup_db(memory: bool) -> SqlitePool {
                 END;
     ",
     )
-    .execute(&pool)
+    .execute(pool)
     .await;
 
-    pool
+    // ---------------------- notes revisions
+    // every version of the notes of a bookmark, see api::handlers::bookmarks
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS notes_revisions (
+                id INTEGER PRIMARY KEY,
+                post_id INTEGER NOT NULL,
+                notes TEXT NOT NULL,
+                date INTEGER NOT NULL,
+                FOREIGN KEY(post_id) REFERENCES posts(id) ON DELETE CASCADE
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        r"
+            CREATE TRIGGER IF NOT EXISTS notes_revisions_ai AFTER INSERT ON posts
+                WHEN COALESCE(new.notes, '') != ''
+                BEGIN
+                    INSERT INTO notes_revisions (post_id, notes, date)
+                    VALUES (new.id, new.notes, COALESCE(new.date_added, unixepoch()));
+                END;
+    ",
+    )
+    .execute(pool)
+    .await;
+
+    // the notes as they were are kept first for bookmarks saved before the revisions were. Large
+    // notes are left out of the bookmark, their revisions are added by notes::store. Recreated
+    // to have that condition in older databases too.
+    let _ = sqlx::query("DROP TRIGGER IF EXISTS notes_revisions_au")
+        .execute(pool)
+        .await;
+    let _ = sqlx::query(
+        r"
+            CREATE TRIGGER IF NOT EXISTS notes_revisions_au AFTER UPDATE OF notes ON posts
+                WHEN COALESCE(old.notes, '') != COALESCE(new.notes, '')
+                    AND NOT EXISTS (SELECT 1 FROM large_notes WHERE uuid = new.uuid)
+                BEGIN
+                    INSERT INTO notes_revisions (post_id, notes, date)
+                    SELECT old.id, COALESCE(old.notes, ''), COALESCE(old.date_modified, unixepoch())
+                    WHERE NOT EXISTS (SELECT 1 FROM notes_revisions WHERE post_id = old.id);
+                    INSERT INTO notes_revisions (post_id, notes, date)
+                    VALUES (new.id, COALESCE(new.notes, ''), unixepoch());
+                END;
+    ",
+    )
+    .execute(pool)
+    .await;
+
+    // large notes compressed, by the uuid of the bookmark, see notes.rs
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS large_notes (
+                uuid TEXT PRIMARY KEY,
+                notes BLOB NOT NULL,
+                size INTEGER NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    // the full text search of large notes, by the rowid in large_notes. Without the text, the
+    // columns of posts_fts to take the same queries.
+    let _ = sqlx::query(
+        r"
+            CREATE VIRTUAL TABLE IF NOT EXISTS large_notes_fts USING fts5(
+                url,
+                title,
+                description,
+                notes,
+                content='',
+                contentless_delete=1
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    let _ = sqlx::query(
+        r"
+            CREATE TRIGGER IF NOT EXISTS large_notes_ad AFTER DELETE ON large_notes
+                BEGIN
+                    DELETE FROM large_notes_fts WHERE rowid = old.rowid;
+                END;
+    ",
+    )
+    .execute(pool)
+    .await;
+    if let Ok(mut conn) = pool.acquire().await {
+        let _ = notes::index_missing(&mut conn).await;
+    }
+
+    // ---------------------- tag revisions
+    // the last revision a tag, or smart tag, was added or deleted in, see api::handlers::tags
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS tag_revisions (
+                name TEXT PRIMARY KEY,
+                revision INTEGER NOT NULL,
+                deleted BOOLEAN NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
+
+    for trigger in [
+        r"
+            CREATE TRIGGER IF NOT EXISTS tags_revision_ai AFTER INSERT ON tags
+                BEGIN
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (new.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), FALSE);
+                END;
+        ",
+        r"
+            CREATE TRIGGER IF NOT EXISTS tags_revision_ad AFTER DELETE ON tags
+                BEGIN
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (old.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), TRUE);
+                END;
+        ",
+        r"
+            CREATE TRIGGER IF NOT EXISTS tags_revision_au AFTER UPDATE OF name ON tags
+                BEGIN
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (old.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), TRUE);
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (new.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), FALSE);
+                END;
+        ",
+        r"
+            CREATE TRIGGER IF NOT EXISTS smart_tags_revision_ai AFTER INSERT ON smart_tags
+                BEGIN
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (new.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), FALSE);
+                END;
+        ",
+        r"
+            CREATE TRIGGER IF NOT EXISTS smart_tags_revision_au AFTER UPDATE ON smart_tags
+                BEGIN
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (new.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), FALSE);
+                END;
+        ",
+        r"
+            CREATE TRIGGER IF NOT EXISTS smart_tags_revision_ad AFTER DELETE ON smart_tags
+                BEGIN
+                    INSERT OR REPLACE INTO tag_revisions (name, revision, deleted)
+                    VALUES (old.name, (SELECT COALESCE(MAX(revision), 0) + 1 FROM tag_revisions), TRUE);
+                END;
+        ",
+    ] {
+        let _ = sqlx::query(trigger).execute(pool).await;
+    }
+
+    // tags merged into another, kept to undo the merge, see api::handlers::undo
+    let _ = sqlx::query(
+        r"
+            CREATE TABLE IF NOT EXISTS tag_merges (
+                id INTEGER PRIMARY KEY,
+                token TEXT NOT NULL,
+                name TEXT NOT NULL,
+                into_name TEXT NOT NULL,
+                date_added INTEGER NOT NULL,
+                revision INTEGER NOT NULL,
+                post_ids TEXT NOT NULL,
+                added_ids TEXT NOT NULL,
+                smart_tags TEXT NOT NULL,
+                audit_id INTEGER NOT NULL,
+                date INTEGER NOT NULL
+            );
+        ",
+    )
+    .execute(pool)
+    .await;
 }
 
+#[cfg(test)]
 pub(crate) fn app(pool: SqlitePool, token: String) -> Router {
-    let state = Arc::new(AppState { pool, token });
+    app_with_state(AppState::new(pool, token))
+}
+
+pub(crate) fn app_with_state(state: AppState) -> Router {
+    let state = Arc::new(state);
 
-    let router = crate::api::configure(&state);
+    // CORS is set per group of routes, outside of the authentication to answer preflight requests
+    let api = crate::api::configure(&state)
+        .route_layer(middleware::from_fn_with_state(state.clone(), audit::audit))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::auth))
+        .layer(cors::layer(state.api_cors_origins.as_deref()));
+
+    // pages meant for the browser and metrics, not for other sites
+    let pages = stats::configure(state.clone())
+        .merge(metrics::configure(state.clone()))
+        .merge(auth::configure(state.clone()))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::auth));
+
+    let mut router = Router::new().merge(api).merge(pages);
+    // the shared bookmarks for any site, without authentication
+    if state.public_rate_limit.is_some() {
+        router = router.merge(
+            public::configure(state.clone())
+                .route_layer(middleware::from_fn(api::etag::etag))
+                .route_layer(middleware::from_fn_with_state(state.clone(), public::limit))
+                .layer(cors::layer(None)),
+        );
+    }
+
+    if state.read_only {
+        router = router.layer(middleware::from_fn(read_only::reject_writes));
+    }
 
     router
-        .route_layer(middleware::from_fn_with_state(state, auth))
-        .layer(TraceLayer::new_for_http())
-        .layer(CorsLayer::permissive())
+        .layer(middleware::from_fn_with_state(state, security_headers::set))
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|req: &axum::extract::Request| {
+                // like the default span, but without the token in the query
+                tracing::debug_span!(
+                    "request",
+                    method = %req.method(),
+                    uri = %auth::redact_uri(req.uri()),
+                    version = ?req.version(),
+                )
+            }),
+        )
+}
+
+// The state configured by the environment variables besides the token and where to listen
+fn state_from_env(pool: SqlitePool, token: String) -> AppState {
+    let mut state = AppState::new(pool, token);
+    state.read_only = server::env_opt("PINRS_READ_ONLY").unwrap_or(false);
+    auth::add_providers_from_env(&mut state.auth_providers)
+        .expect("Failed to parse the authentication");
+    if let Ok(path) = env::var("PINRS_REWRITE_RULES") {
+        let rules = fs::read_to_string(&path).expect("Failed to read PINRS_REWRITE_RULES");
+        state.rewrite_rules =
+            rewrite::RewriteRules::parse(&rules).expect("Failed to parse PINRS_REWRITE_RULES");
+    }
+    if let Ok(path) = env::var("PINRS_RETENTION_RULES") {
+        let rules = fs::read_to_string(&path).expect("Failed to read PINRS_RETENTION_RULES");
+        state.retention_rules = retention::RetentionRules::parse(&rules)
+            .expect("Failed to parse PINRS_RETENTION_RULES");
+    }
+
+    if let Ok(max) = env::var("PINRS_MAX_NOTES_SIZE") {
+        state.max_notes_size =
+            Some(notes::parse_max_size(&max).expect("Failed to parse PINRS_MAX_NOTES_SIZE"));
+    }
+
+    if let Ok(origins) = env::var("PINRS_CORS_ORIGINS") {
+        state.api_cors_origins =
+            Some(cors::parse_origins(&origins).expect("Failed to parse PINRS_CORS_ORIGINS"));
+    }
+
+    if let Ok(per_minute) = env::var("PINRS_PUBLIC_API_RATE_LIMIT") {
+        state.public_rate_limit = Some(
+            public::RateLimit::parse(&per_minute)
+                .expect("Failed to parse PINRS_PUBLIC_API_RATE_LIMIT"),
+        );
+    }
+
+    state.security_headers = security_headers::SecurityHeaders::parse(
+        env::var("PINRS_CSP").ok().as_deref(),
+        env::var("PINRS_HSTS_MAX_AGE").ok().as_deref(),
+    )
+    .expect("Failed to parse the security headers");
+    state
 }
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    env_logger::init();
+    // span events log the time spent in them, e.g. in the db.* spans with RUST_LOG=pinrs=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
 
     let pool = setup_db(false).await;
 
     let args = Arguments::parse();
-    if args.import.is_some() {
-        import::import(args.import.unwrap(), &pool).await?;
-        return Ok(());
+    let report = args.report.as_deref().map(Path::new);
+    if let Some(path) = args.import {
+        let summary =
+            import::import(path, ImportFormat::Auto, &pool, report, args.preserve_tags).await?;
+        return output::print(&summary, args.format);
+    } else if let Some(path) = args.import_instapaper {
+        let summary = import::import(
+            path,
+            ImportFormat::Instapaper,
+            &pool,
+            report,
+            args.preserve_tags,
+        )
+        .await?;
+        return output::print(&summary, args.format);
     } else if args.export_html {
-        import::export_html(&pool).await?;
+        import::export_html(&pool, export_options(args)?).await?;
+        return Ok(());
+    } else if args.export_linkding {
+        import::export_linkding(&pool, export_options(args)?).await?;
+        return Ok(());
+    } else if args.export_csv {
+        let csv = csv_options(&args);
+        import::export_csv(&pool, export_options(args)?, csv).await?;
+        return Ok(());
+    } else if args.export_graphviz {
+        import::export_graphviz(&pool).await?;
+        return Ok(());
+    } else if args.export_config {
+        config::export_config(&pool).await?;
+        return Ok(());
+    } else if let Some(path) = args.import_config {
+        let report = config::import_config(&pool, &fs::read_to_string(path)?).await?;
+        return output::print(&report, args.format);
+    } else if let Some(dir) = args.publish {
+        publish::publish(&pool, Path::new(&dir), &args.publish_url).await?;
+        return Ok(());
+    } else if let Some(path) = args.restore {
+        let mode = if args.replace {
+            restore::RestoreMode::Replace
+        } else {
+            restore::RestoreMode::Merge
+        };
+        let report = restore::restore(&pool, &fs::read(path)?, mode).await?;
+        return output::print(&report, args.format);
+    } else if args.totp_setup {
+        println!("{}", totp::setup(&pool).await?);
+        return Ok(());
+    } else if args.totp_disable {
+        totp::disable(&pool).await?;
         return Ok(());
     }
 
     let token = env::var("PINRS_TOKEN").expect("Need to set environment variable PINRS_TOKEN");
-    let port = env::var("PINRS_PORT").unwrap_or("3000".to_owned());
+    let port = env::var("PINRS_PORT")
+        .unwrap_or("3000".to_owned())
+        .parse()
+        .expect("Failed to parse PINRS_PORT");
+    // IPv4 and IPv6 unless given, IPv6 is skipped on hosts without it
+    let default_bind = env::var("PINRS_BIND").is_err();
+    let addresses = server::parse_bind(
+        &env::var("PINRS_BIND").unwrap_or("0.0.0.0,::".to_owned()),
+        port,
+    )
+    .expect("Failed to parse PINRS_BIND");
 
-    let app = app(pool, token);
+    let state = state_from_env(pool, token);
 
-    let app = NormalizePathLayer::trim_trailing_slash().layer(app);
+    let config = server::ServerConfig::from_env();
+    let app = app_with_state(state).layer(DefaultBodyLimit::max(config.max_body_size));
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
-        .await
-        .expect("Failed to bind to port");
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, ServiceExt::<Request>::into_make_service(app))
-        .await
-        .expect("Failed to create server");
+    let mut listeners = vec![];
+    for address in addresses {
+        let listener = match server::bind(address) {
+            Ok(listener) => listener,
+            Err(err) if default_bind && address.is_ipv6() => {
+                tracing::warn!("Not listening on {}: {}", address, err);
+                continue;
+            }
+            Err(err) => panic!("Failed to bind to {address}: {err}"),
+        };
+        tracing::debug!("listening on {}", listener.local_addr().unwrap());
+        listeners.push(listener);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        systemd::ready();
+    }
+
+    server::serve(listeners, app, &config).await;
 
     Ok(())
 }
@@ -275,11 +904,14 @@ async fn main() -> Result<(), anyhow::Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::handlers::bookmarks::{add_bookmark, BookmarkRequest};
     use axum::{
         body::Body,
+        extract::ConnectInfo,
         http::{Request, StatusCode},
     };
     use hyper::header;
+    use std::net::SocketAddr;
     use tower::ServiceExt;
 
     #[tokio::test]
@@ -292,7 +924,7 @@ mod tests {
             .oneshot(
                 Request::builder()
                     .uri("/api/bookmarks")
-                    .header(header::AUTHORIZATION, format!("Token 123"))
+                    .header(header::AUTHORIZATION, "Token 123")
                     .body(Body::empty())
                     .unwrap(),
             )
@@ -304,7 +936,7 @@ mod tests {
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri(format!("/api/bookmarks"))
+                    .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, "Token abc")
                     .body(Body::empty())
                     .unwrap(),
@@ -325,7 +957,7 @@ mod tests {
             .oneshot(
                 Request::builder()
                     .uri("/api/bookmarks")
-                    .header(header::AUTHORIZATION, format!("Bearer 123"))
+                    .header(header::AUTHORIZATION, "Bearer 123")
                     .body(Body::empty())
                     .unwrap(),
             )
@@ -337,7 +969,7 @@ mod tests {
         let response = app
             .oneshot(
                 Request::builder()
-                    .uri(format!("/api/bookmarks"))
+                    .uri("/api/bookmarks")
                     .header(header::AUTHORIZATION, "Token abc")
                     .body(Body::empty())
                     .unwrap(),
@@ -347,4 +979,449 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn auth_trusted_network() {
+        let pool = setup_db(true).await;
+        let mut state = AppState::new(pool, "abc".to_owned());
+        let networks = auth::parse_trusted_networks("127.0.0.1/8, ::1").unwrap();
+        state
+            .auth_providers
+            .insert(0, Box::new(auth::TrustedNetworks(networks)));
+        let app = app_with_state(state);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks")
+                    .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 2], 1234))))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks")
+                    .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234))))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // from anywhere through a reverse proxy on the same host
+        for (name, value) in [
+            ("X-Forwarded-For", "203.0.113.1"),
+            ("Forwarded", "for=203.0.113.1"),
+            ("X-Real-IP", "203.0.113.1"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/api/bookmarks")
+                        .extension(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 1234))))
+                        .header(name, value)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{name}");
+        }
+
+        let proxies = auth::parse_trusted_networks("127.0.0.1").unwrap();
+        let networks = auth::parse_trusted_networks("10.0.0.0/8").unwrap();
+        assert!(auth::check_trusted_networks(&networks, &proxies).is_ok());
+        let networks = auth::parse_trusted_networks("127.0.0.1/8").unwrap();
+        assert!(auth::check_trusted_networks(&networks, &proxies).is_err());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/api/bookmarks")
+                    .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234))))
+                    .header(header::AUTHORIZATION, "Token abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn auth_trusted_header() {
+        let pool = setup_db(true).await;
+        let mut state = AppState::new(pool, "abc".to_owned());
+        let provider = auth::TrustedHeader::parse("X-Remote-User", "10.0.0.1").unwrap();
+        state.auth_providers.insert(0, Box::new(provider));
+        let app = app_with_state(state);
+
+        for (proxy, user, status) in [
+            ([10, 0, 0, 1], Some("alice"), StatusCode::OK),
+            ([10, 0, 0, 1], None, StatusCode::UNAUTHORIZED),
+            ([10, 0, 0, 2], Some("alice"), StatusCode::UNAUTHORIZED),
+        ] {
+            let mut request = Request::builder()
+                .uri("/api/bookmarks")
+                .extension(ConnectInfo(SocketAddr::from((proxy, 1234))));
+            if let Some(user) = user {
+                request = request.header("X-Remote-User", user);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), status, "{proxy:?} {user:?}");
+        }
+
+        assert!(auth::TrustedHeader::parse("X-Remote-User", "").is_err());
+    }
+
+    #[tokio::test]
+    async fn auth_query_token_and_session() {
+        let pool = setup_db(true).await;
+        let app = app(pool, "abc".to_owned());
+
+        for (method, uri, status) in [
+            ("GET", "/api/bookmarks?token=abc", StatusCode::OK),
+            ("GET", "/api/bookmarks/archived?token=abc", StatusCode::OK),
+            (
+                "GET",
+                "/api/bookmarks?token=wrong",
+                StatusCode::UNAUTHORIZED,
+            ),
+            // only the feeds take the token in the query
+            ("GET", "/api/tags?token=abc", StatusCode::UNAUTHORIZED),
+            ("POST", "/api/bookmarks?token=abc", StatusCode::UNAUTHORIZED),
+            (
+                "DELETE",
+                "/api/bookmarks/1?token=abc",
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                "GET",
+                "/api/bookmarks?auth_token=me:abc",
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                "GET",
+                "/pinboard/v1/posts/recent?token=abc",
+                StatusCode::UNAUTHORIZED,
+            ),
+            ("POST", "/auth/login", StatusCode::UNAUTHORIZED),
+            ("POST", "/auth/login?token=abc", StatusCode::UNAUTHORIZED),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{method} {uri}");
+        }
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/auth/login")
+                    .header(header::AUTHORIZATION, "Token abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.contains("HttpOnly"));
+        let cookie = set_cookie.split(';').next().unwrap().to_owned();
+
+        for (method, uri, status) in [
+            ("GET", "/api/bookmarks", StatusCode::OK),
+            ("POST", "/auth/logout", StatusCode::NO_CONTENT),
+            ("GET", "/api/bookmarks", StatusCode::UNAUTHORIZED),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header(header::COOKIE, format!("theme=dark; {cookie}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{method} {uri}");
+        }
+
+        for (uri, redacted) in [
+            ("/api/bookmarks", "/api/bookmarks"),
+            (
+                "/api/bookmarks?q=a&token=abc",
+                "/api/bookmarks?q=a&token=redacted",
+            ),
+            (
+                "/pinboard/v1/posts/recent?auth_token=me:abc&format=json",
+                "/pinboard/v1/posts/recent?auth_token=redacted&format=json",
+            ),
+            (
+                "/api/bookmarks?to%6Ben=abc",
+                "/api/bookmarks?token=redacted",
+            ),
+        ] {
+            assert_eq!(auth::redact_uri(&uri.parse().unwrap()), redacted);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only() {
+        let pool = setup_db(true).await;
+        add_bookmark(
+            &pool,
+            BookmarkRequest {
+                url: "https://a.example".to_owned(),
+                title: "a".to_owned(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut state = AppState::new(pool.clone(), "abc".to_owned());
+        state.read_only = true;
+        let app = app_with_state(state);
+
+        for (method, uri, status) in [
+            ("GET", "/api/bookmarks", StatusCode::OK),
+            ("POST", "/auth/login", StatusCode::NO_CONTENT),
+            ("GET", "/api/bookmarks/1", StatusCode::OK),
+            ("POST", "/api/bookmarks", StatusCode::METHOD_NOT_ALLOWED),
+            ("DELETE", "/api/bookmarks/1", StatusCode::METHOD_NOT_ALLOWED),
+            ("POST", "/api/tags/cleanup", StatusCode::METHOD_NOT_ALLOWED),
+            ("GET", "/pinboard/v1/posts/recent", StatusCode::OK),
+            (
+                "GET",
+                "/pinboard/v1/posts/add?url=https://b.example",
+                StatusCode::METHOD_NOT_ALLOWED,
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header(header::AUTHORIZATION, "Token abc")
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(r#"{"url": "https://b.example", "title": "b"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{method} {uri}");
+        }
+
+        let usage: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM token_usage")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(usage, 0, "the token usage isn't recorded");
+    }
+
+    #[tokio::test]
+    async fn public_api() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), "abc".to_owned());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/public/api/bookmarks")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        // not served without a rate limit
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut state = AppState::new(pool, "abc".to_owned());
+        state.public_rate_limit = Some(public::RateLimit::parse("2").unwrap());
+        let app = app_with_state(state);
+        for (url, shared) in [("https://a.example", true), ("https://b.example", false)] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/api/bookmarks")
+                        .header(header::AUTHORIZATION, "Token abc")
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(format!(
+                            r#"{{"url": "{url}", "title": "a", "shared": {shared}, "private_notes": "secret"}}"#
+                        )))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        for status in [
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS,
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/public/api/bookmarks")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+            if status != StatusCode::OK {
+                continue;
+            }
+
+            assert!(response.headers().contains_key(header::CACHE_CONTROL));
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            let bookmarks: public::PublicBookmarksResponse = serde_json::from_str(&body).unwrap();
+            assert_eq!(bookmarks.total, 1);
+            assert_eq!(bookmarks.results[0].url, "https://a.example");
+            assert!(!body.contains("secret"));
+        }
+    }
+
+    #[tokio::test]
+    async fn cors_origins() {
+        let pool = setup_db(true).await;
+        let mut state = AppState::new(pool, "abc".to_owned());
+        state.api_cors_origins = Some(cors::parse_origins("https://ok.example").unwrap());
+        let app = app_with_state(state);
+
+        // preflight requests don't need the token
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("OPTIONS")
+                    .uri("/api/bookmarks")
+                    .header(header::ORIGIN, "https://ok.example")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://ok.example"
+        );
+
+        for (uri, origin) in [
+            ("/api/bookmarks", "https://other.example"),
+            ("/stats", "https://ok.example"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header(header::AUTHORIZATION, "Token abc")
+                        .header(header::ORIGIN, origin)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(!response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        }
+    }
+
+    #[tokio::test]
+    async fn security_headers() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), "abc".to_owned());
+
+        // also on responses from the authentication
+        for (uri, status) in [
+            ("/stats", StatusCode::OK),
+            ("/api/bookmarks", StatusCode::UNAUTHORIZED),
+        ] {
+            let mut request = Request::builder().uri(uri);
+            if status == StatusCode::OK {
+                request = request.header(header::AUTHORIZATION, "Token abc");
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+            let headers = response.headers();
+            assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+            assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+            assert!(headers.contains_key(header::CONTENT_SECURITY_POLICY));
+            assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+        }
+
+        let mut state = AppState::new(pool, "abc".to_owned());
+        state.security_headers =
+            security_headers::SecurityHeaders::parse(Some("default-src 'self'"), Some("31536000"))
+                .unwrap();
+        let response = app_with_state(state)
+            .oneshot(
+                Request::builder()
+                    .uri("/stats")
+                    .header(header::AUTHORIZATION, "Token abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'"
+        );
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000"
+        );
+
+        assert!(security_headers::SecurityHeaders::parse(None, Some("a year")).is_err());
+    }
 }
@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! `/metrics` in the Prometheus text format: the size of the database and the full text index,
+//! and the latency of searches by kind of query.
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use hyper::StatusCode;
+use sqlx::SqlitePool;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, warn};
+
+use crate::AppState;
+
+// upper bounds of the histogram buckets in seconds
+const BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum SearchKind {
+    // only #tags
+    Tag,
+    // only words, searched in the full text index
+    Text,
+    Combined,
+}
+
+impl SearchKind {
+    const ALL: [SearchKind; 3] = [SearchKind::Tag, SearchKind::Text, SearchKind::Combined];
+
+    pub(crate) fn of(has_tags: bool, has_text: bool) -> Option<Self> {
+        match (has_tags, has_text) {
+            (true, false) => Some(SearchKind::Tag),
+            (false, true) => Some(SearchKind::Text),
+            (true, true) => Some(SearchKind::Combined),
+            (false, false) => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SearchKind::Tag => "tag",
+            SearchKind::Text => "text",
+            SearchKind::Combined => "combined",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    // not cumulative, summed up when written
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn write(&self, out: &mut String, name: &str, label: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{{label},le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{name}_bucket{{{label},le=\"+Inf\"}} {count}");
+        #[allow(clippy::cast_precision_loss)]
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{name}_sum{{{label}}} {sum}");
+        let _ = writeln!(out, "{name}_count{{{label}}} {count}");
+    }
+}
+
+/// Collected while serving, kept in `AppState`
+#[derive(Default)]
+pub(crate) struct Metrics {
+    search: [Histogram; SearchKind::ALL.len()],
+}
+
+impl Metrics {
+    pub(crate) fn observe_search(&self, kind: SearchKind, duration: Duration) {
+        self.search[kind as usize].observe(duration);
+    }
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/metrics", get(handle_get_metrics))
+        .with_state(state)
+}
+
+async fn database_size(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()")
+        .fetch_one(pool)
+        .await
+}
+
+// The tables of the FTS5 index, from the dbstat virtual table
+async fn fts_size(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COALESCE(SUM(pgsize), 0) FROM dbstat WHERE name LIKE 'posts_fts%'")
+        .fetch_one(pool)
+        .await
+}
+
+async fn handle_get_metrics(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut out = String::new();
+
+    let database = database_size(&state.pool).await.map_err(|err| {
+        error!("Failed to get the database size: {}", err);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let _ = writeln!(
+        out,
+        "# HELP pinrs_database_size_bytes Size of the database."
+    );
+    let _ = writeln!(out, "# TYPE pinrs_database_size_bytes gauge");
+    let _ = writeln!(out, "pinrs_database_size_bytes {database}");
+
+    // left out if SQLite is built without dbstat
+    match fts_size(&state.pool).await {
+        Ok(fts) => {
+            let _ = writeln!(
+                out,
+                "# HELP pinrs_fts_index_size_bytes Size of the full text search index."
+            );
+            let _ = writeln!(out, "# TYPE pinrs_fts_index_size_bytes gauge");
+            let _ = writeln!(out, "pinrs_fts_index_size_bytes {fts}");
+        }
+        Err(err) => warn!("Failed to get the size of the full text index: {}", err),
+    }
+
+    let name = "pinrs_search_duration_seconds";
+    let _ = writeln!(
+        out,
+        "# HELP {name} Latency of bookmark searches by kind of query."
+    );
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    for kind in SearchKind::ALL {
+        state.metrics.search[kind as usize].write(
+            &mut out,
+            name,
+            &format!("kind=\"{}\"", kind.name()),
+        );
+    }
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app_with_state, setup_db};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_get_metrics() {
+        let pool = setup_db(true).await;
+        let state = AppState::new(pool, "abc".to_owned());
+        state
+            .metrics
+            .observe_search(SearchKind::Text, Duration::from_millis(3));
+        let app = app_with_state(state);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .header(header::AUTHORIZATION, "Token abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("pinrs_database_size_bytes "));
+        assert!(body.contains("pinrs_fts_index_size_bytes "));
+        assert!(body.contains("pinrs_search_duration_seconds_bucket{kind=\"text\",le=\"0.001\"} 0"));
+        assert!(body.contains("pinrs_search_duration_seconds_bucket{kind=\"text\",le=\"0.005\"} 1"));
+        assert!(body.contains("pinrs_search_duration_seconds_count{kind=\"tag\"} 0"));
+    }
+}
@@ -0,0 +1,236 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Large notes, e.g. a whole article pasted in, are kept compressed in `large_notes` instead of
+//! in the bookmark, by the uuid of the bookmark to follow it into the graveyard and backups.
+//! Listings leave them out unless asked for with `?include=notes`, a single bookmark has them.
+//! The triggers on `posts` only see them left out, so their revisions and full text search, in
+//! `large_notes_fts`, are kept here.
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use tracing::error;
+
+use crate::api::handlers::bookmarks::BookmarkResponse;
+use crate::PostID;
+
+// notes longer than this, in bytes, are large
+pub(crate) const LARGE_NOTES_SIZE: usize = 64 * 1024;
+
+/// From `PINRS_MAX_NOTES_SIZE`, the longest notes accepted in bytes
+pub(crate) fn parse_max_size(max: &str) -> Result<usize, String> {
+    match max.trim().parse() {
+        Ok(max) if max > 0 => Ok(max),
+        _ => Err(format!("Invalid PINRS_MAX_NOTES_SIZE: {max}")),
+    }
+}
+
+pub(crate) fn is_large(notes: Option<&str>) -> bool {
+    notes.is_some_and(|notes| notes.len() > LARGE_NOTES_SIZE)
+}
+
+fn compress(notes: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+    encoder.write_all(notes.as_bytes())?;
+    encoder.finish()
+}
+
+fn decompress(compressed: &[u8]) -> std::io::Result<String> {
+    let mut notes = String::new();
+    DeflateDecoder::new(compressed).read_to_string(&mut notes)?;
+    Ok(notes)
+}
+
+/// The large notes of the bookmark, if it has
+pub(crate) async fn get(
+    conn: &mut SqliteConnection,
+    post_id: PostID,
+) -> Result<Option<String>, sqlx::Error> {
+    let compressed: Option<Vec<u8>> = sqlx::query_scalar(
+        "SELECT large_notes.notes FROM large_notes JOIN posts ON posts.uuid = large_notes.uuid WHERE posts.id = $1",
+    )
+    .bind(post_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    compressed
+        .map(|compressed| decompress(&compressed))
+        .transpose()
+        .map_err(|err| sqlx::Error::Decode(Box::new(err)))
+}
+
+// Adds the notes as a revision, after the notes as they were if there are no revisions yet, as
+// the notes_revisions_au trigger does
+async fn add_revision(
+    conn: &mut SqliteConnection,
+    post_id: PostID,
+    old: &str,
+    notes: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    if !old.is_empty() {
+        sqlx::query(
+            r"
+                INSERT INTO notes_revisions (post_id, notes, date)
+                    SELECT id, $2, COALESCE(date_modified, unixepoch()) FROM posts
+                    WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM notes_revisions WHERE post_id = $1)
+            ",
+        )
+        .bind(post_id)
+        .bind(old)
+        .execute(&mut *conn)
+        .await?;
+    }
+    if let Some(notes) = notes {
+        sqlx::query(
+            "INSERT INTO notes_revisions (post_id, notes, date) VALUES ($1, $2, unixepoch())",
+        )
+        .bind(post_id)
+        .bind(notes)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+// Replaces the notes of the large_notes row in the full text search
+async fn index(conn: &mut SqliteConnection, rowid: i64, notes: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM large_notes_fts WHERE rowid = $1")
+        .bind(rowid)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("INSERT INTO large_notes_fts (rowid, notes) VALUES ($1, $2)")
+        .bind(rowid)
+        .bind(notes)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Adds the large notes missing from the full text search, e.g. restored from a backup
+pub(crate) async fn index_missing(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let rows: Vec<(i64, Vec<u8>)> = sqlx::query_as(
+        "SELECT rowid, notes FROM large_notes WHERE rowid NOT IN (SELECT rowid FROM large_notes_fts)",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for (rowid, compressed) in rows {
+        match decompress(&compressed) {
+            Ok(notes) => index(conn, rowid, &notes).await?,
+            Err(err) => error!("Failed to decompress large notes {}: {}", rowid, err),
+        }
+    }
+    Ok(())
+}
+
+/// Keeps the notes of the bookmark in `large_notes` if they're large, or removes the ones kept
+/// there if not, and adds the revisions the triggers miss. Called when the notes are set: after
+/// the bookmark is added, or before it's updated, with the notes left out of the bookmark itself.
+pub(crate) async fn store(
+    conn: &mut SqliteConnection,
+    post_id: PostID,
+    notes: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let large = notes.filter(|notes| is_large(Some(notes)));
+    let old = match get(conn, post_id).await? {
+        Some(old) => old,
+        // nothing large, the triggers keep the revisions
+        None if large.is_none() => return Ok(()),
+        None => sqlx::query_scalar::<_, Option<String>>("SELECT notes FROM posts WHERE id = $1")
+            .bind(post_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .flatten()
+            .unwrap_or_default(),
+    };
+
+    let notes = notes.unwrap_or_default();
+    if old != notes {
+        // the trigger sees a change from large notes to other notes, but not when they're cleared
+        let revision = (large.is_some() || notes.is_empty()).then_some(notes);
+        add_revision(conn, post_id, &old, revision).await?;
+    }
+
+    let Some(notes) = large else {
+        // large_notes_ad removes them from the full text search
+        sqlx::query("DELETE FROM large_notes WHERE uuid = (SELECT uuid FROM posts WHERE id = $1)")
+            .bind(post_id)
+            .execute(&mut *conn)
+            .await?;
+        return Ok(());
+    };
+
+    let compressed = compress(notes).map_err(|err| sqlx::Error::Encode(Box::new(err)))?;
+    let rowid: i64 = sqlx::query_scalar(
+        r"
+            INSERT INTO large_notes (uuid, notes, size)
+                SELECT uuid, $2, $3 FROM posts WHERE id = $1
+                ON CONFLICT (uuid) DO UPDATE SET notes = excluded.notes, size = excluded.size
+                RETURNING rowid
+        ",
+    )
+    .bind(post_id)
+    .bind(compressed)
+    .bind(i64::try_from(notes.len()).unwrap_or(i64::MAX))
+    .fetch_one(&mut *conn)
+    .await?;
+    index(conn, rowid, notes).await
+}
+
+/// Sets the notes of the bookmarks with large notes
+pub(crate) async fn load(pool: &SqlitePool, bookmarks: &mut [BookmarkResponse]) {
+    let uuids: Vec<&str> = bookmarks
+        .iter()
+        .filter_map(|bookmark| bookmark.uuid.as_deref())
+        .collect();
+    if uuids.is_empty() {
+        return;
+    }
+
+    let rows: Vec<(String, Vec<u8>)> = match sqlx::query_as(
+        "SELECT uuid, notes FROM large_notes WHERE uuid IN (SELECT value FROM json_each($1))",
+    )
+    .bind(serde_json::to_string(&uuids).unwrap_or_default())
+    .fetch_all(pool)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(err) => {
+            error!("Failed to get large notes: {}", err);
+            return;
+        }
+    };
+
+    let mut notes: HashMap<String, Vec<u8>> = rows.into_iter().collect();
+    for bookmark in bookmarks {
+        let Some(compressed) = bookmark.uuid.as_ref().and_then(|uuid| notes.remove(uuid)) else {
+            continue;
+        };
+        match decompress(&compressed) {
+            Ok(large) => bookmark.notes = Some(large),
+            Err(err) => error!("Failed to decompress the notes of {}: {}", bookmark.id, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        let notes = "an article pasted in, ".repeat(LARGE_NOTES_SIZE);
+        let compressed = compress(&notes).unwrap();
+        assert!(compressed.len() < notes.len() / 10);
+        assert_eq!(decompress(&compressed).unwrap(), notes);
+
+        assert!(is_large(Some(&notes)));
+        assert!(!is_large(Some("short")));
+        assert!(!is_large(None));
+        assert!(parse_max_size("0").is_err());
+        assert_eq!(parse_max_size("1048576"), Ok(1_048_576));
+    }
+}
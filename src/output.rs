@@ -0,0 +1,76 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! The summaries printed by the command line commands, in the format given by `--format`. Only
+//! the summary is printed to stdout, the logs go to stderr.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// A line of text
+    #[default]
+    Plain,
+    /// The whole summary as JSON, e.g. with the entries that failed
+    Json,
+    /// The counts, one per row
+    Table,
+}
+
+/// The outcome of a command, e.g. of an import
+pub(crate) trait Summary: Serialize {
+    fn plain(&self) -> String;
+    /// The name and value of each row of the table
+    fn rows(&self) -> Vec<(&'static str, String)>;
+}
+
+pub(crate) fn render(summary: &impl Summary, format: OutputFormat) -> Result<String> {
+    Ok(match format {
+        OutputFormat::Plain => summary.plain(),
+        OutputFormat::Json => serde_json::to_string_pretty(summary)?,
+        OutputFormat::Table => {
+            let rows = summary.rows();
+            let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            rows.iter()
+                .map(|(name, value)| format!("{name:<width$}  {value}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    })
+}
+
+pub(crate) fn print(summary: &impl Summary, format: OutputFormat) -> Result<()> {
+    println!("{}", render(summary, format)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::restore::RestoreReport;
+
+    #[test]
+    fn test_render() {
+        let report = RestoreReport {
+            in_backup: 3,
+            restored: 2,
+            bookmarks: 10,
+            tags: 4,
+        };
+
+        assert_eq!(
+            render(&report, OutputFormat::Plain).unwrap(),
+            "Restored 2 of 3 bookmarks, now 10 bookmarks and 4 tags"
+        );
+        assert_eq!(
+            render(&report, OutputFormat::Table).unwrap(),
+            "in_backup  3\nrestored   2\nbookmarks  10\ntags       4"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&report, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["restored"], 2);
+    }
+}
@@ -0,0 +1,198 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! `GET /public/api/bookmarks`, the shared bookmarks without authentication, e.g. for embedding a
+//! link list on another site. Only served when `PINRS_PUBLIC_API_RATE_LIMIT` is set, limiting the
+//! requests per minute from each address.
+
+use axum::{
+    extract::{OriginalUri, Query, Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use hyper::header;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::handlers::bookmarks::{
+    count_bookmarks, get_bookmarks, pagination_links, BookmarkQuery,
+};
+use crate::auth::client_ip;
+use crate::{AppState, PostID};
+
+const WINDOW: Duration = Duration::from_mins(1);
+
+// addresses kept before the ones with a passed window are forgotten
+const MAX_ADDRESSES: usize = 1000;
+
+// bookmarks per page, at most and unless given
+const MAX_LIMIT: u32 = 100;
+
+// the shared bookmarks rarely change, caches may keep them for a while
+const CACHE_CONTROL: &str = "public, max-age=300";
+
+pub(crate) struct RateLimit {
+    per_minute: u32,
+    // start of the window and requests in it, per address
+    windows: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl RateLimit {
+    /// From `PINRS_PUBLIC_API_RATE_LIMIT`, the requests per minute from an address
+    pub(crate) fn parse(per_minute: &str) -> Result<Self, String> {
+        match per_minute.trim().parse() {
+            Ok(per_minute) if per_minute > 0 => Ok(RateLimit {
+                per_minute,
+                windows: Mutex::new(HashMap::new()),
+            }),
+            _ => Err(format!("Invalid PINRS_PUBLIC_API_RATE_LIMIT: {per_minute}")),
+        }
+    }
+
+    // Counts the request, false if the address has used up its requests in the window
+    fn allow(&self, ip: IpAddr, now: Instant) -> bool {
+        let Ok(mut windows) = self.windows.lock() else {
+            return false;
+        };
+        // pruned on requests, there are no background tasks
+        if windows.len() >= MAX_ADDRESSES {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < WINDOW);
+        }
+
+        let (start, count) = windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= self.per_minute
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct PublicBookmarkResponse {
+    pub(crate) id: PostID,
+    pub(crate) url: String,
+    pub(crate) title: String,
+    pub(crate) description: Option<String>,
+    pub(crate) tag_names: Vec<String>,
+    pub(crate) date_added: String,
+    pub(crate) date_modified: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct PublicBookmarksResponse {
+    pub(crate) count: usize,
+    pub(crate) total: usize,
+    pub(crate) limit: u32,
+    pub(crate) offset: u32,
+    pub(crate) results: Vec<PublicBookmarkResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PublicQuery {
+    q: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/public/api/bookmarks", get(handle_get_bookmarks))
+        .with_state(state)
+}
+
+/// Answers with 429 Too Many Requests when the address is over the rate limit
+pub(crate) async fn limit(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(rate_limit) = &state.public_rate_limit {
+        let ip = client_ip(&req).unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        if !rate_limit.allow(ip, Instant::now()) {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, WINDOW.as_secs().to_string())],
+            )
+                .into_response();
+        }
+    }
+    next.run(req).await
+}
+
+async fn handle_get_bookmarks(
+    State(state): State<Arc<AppState>>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<PublicQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(MAX_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let query = BookmarkQuery {
+        q: query.q,
+        limit: Some(limit),
+        offset: Some(offset),
+        shared: Some("yes".to_owned()),
+        archived: Some(false),
+        ..Default::default()
+    };
+
+    let total = usize::try_from(count_bookmarks(&state.pool, &query).await).unwrap_or_default();
+    let results: Vec<PublicBookmarkResponse> = get_bookmarks(&state.pool, query)
+        .await
+        .into_iter()
+        .map(|bookmark| PublicBookmarkResponse {
+            id: bookmark.id,
+            url: bookmark.url,
+            title: bookmark.title,
+            description: bookmark.description,
+            tag_names: bookmark.tag_names,
+            date_added: bookmark.date_added,
+            date_modified: bookmark.date_modified,
+        })
+        .collect();
+
+    let mut response = Json(PublicBookmarksResponse {
+        count: results.len(),
+        total,
+        limit,
+        offset,
+        results,
+    })
+    .into_response();
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+    if let Some(links) = pagination_links(&uri, limit, offset, total) {
+        response.headers_mut().insert(header::LINK, links);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let rate_limit = RateLimit::parse("2").unwrap();
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        let now = Instant::now();
+
+        assert!(rate_limit.allow(ip, now));
+        assert!(rate_limit.allow(ip, now));
+        assert!(!rate_limit.allow(ip, now));
+        assert!(rate_limit.allow(IpAddr::from([10, 0, 0, 2]), now));
+        assert!(rate_limit.allow(ip, now + WINDOW));
+
+        assert!(RateLimit::parse("0").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use anyhow::Result;
+use chrono::DateTime;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::api::format::escape_html;
+use crate::api::handlers::bookmarks::{BookmarkQuery, BookmarkResponse};
+
+// Tags can contain any character, keep the file names to ones that don't need escaping in links
+fn tag_file(tag: &str) -> String {
+    let name: String = tag
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' {
+                char::from(byte).to_string()
+            } else {
+                format!("_{byte:02x}")
+            }
+        })
+        .collect();
+    format!("{name}.html")
+}
+
+// root is the path from the page to the root of the site
+fn page(title: &str, body: &str, root: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<link rel="alternate" type="application/rss+xml" title="Links" href="{root}feed.xml">
+</head>
+<body>
+<h1>{title}</h1>
+{body}
+</body>
+</html>
+"#,
+        title = escape_html(title),
+    )
+}
+
+fn bookmark_list(bookmarks: &[&BookmarkResponse], tag_dir: &str) -> String {
+    let mut result = vec!["<ul>".to_owned()];
+
+    for bookmark in bookmarks {
+        let tags: Vec<String> = bookmark
+            .tag_names
+            .iter()
+            .map(|tag| {
+                format!(
+                    r#"<a href="{tag_dir}{}">#{}</a>"#,
+                    tag_file(tag),
+                    escape_html(tag)
+                )
+            })
+            .collect();
+        let description = bookmark
+            .description
+            .as_deref()
+            .filter(|description| !description.is_empty())
+            .map(|description| format!("<br>{}", escape_html(description)))
+            .unwrap_or_default();
+
+        result.push(format!(
+            r#"<li><a href="{}">{}</a> {}{}</li>"#,
+            escape_html(&bookmark.url),
+            escape_html(&bookmark.title),
+            tags.join(" "),
+            description
+        ));
+    }
+
+    result.push("</ul>".to_owned());
+    result.join("\n")
+}
+
+fn rss(bookmarks: &[BookmarkResponse], base_url: &str) -> String {
+    let mut result = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_owned(),
+        r#"<rss version="2.0">"#.to_owned(),
+        "<channel>".to_owned(),
+        "<title>Links</title>".to_owned(),
+        format!("<link>{}</link>", escape_html(base_url)),
+        "<description>Shared bookmarks</description>".to_owned(),
+    ];
+
+    for bookmark in bookmarks {
+        let date = DateTime::parse_from_rfc3339(&bookmark.date_added)
+            .map(|date| date.to_rfc2822())
+            .unwrap_or_default();
+        result.push("<item>".to_owned());
+        result.push(format!("<title>{}</title>", escape_html(&bookmark.title)));
+        result.push(format!("<link>{}</link>", escape_html(&bookmark.url)));
+        result.push(format!("<guid>{}</guid>", escape_html(&bookmark.url)));
+        result.push(format!(
+            "<description>{}</description>",
+            escape_html(bookmark.description.as_deref().unwrap_or_default())
+        ));
+        for tag in &bookmark.tag_names {
+            result.push(format!("<category>{}</category>", escape_html(tag)));
+        }
+        result.push(format!("<pubDate>{date}</pubDate>"));
+        result.push("</item>".to_owned());
+    }
+
+    result.push("</channel>".to_owned());
+    result.push("</rss>".to_owned());
+    result.join("\n")
+}
+
+/// Writes the shared bookmarks as a static site to `dir`: an index page, one page per tag and an
+/// RSS feed. `base_url` is where the site will be hosted, used for the link of the feed.
+pub(crate) async fn publish(pool: &SqlitePool, dir: &Path, base_url: &str) -> Result<()> {
+    let query = BookmarkQuery {
+        limit: Some(0),
+        shared: Some("yes".to_owned()),
+        ..Default::default()
+    };
+
+    let bookmarks = crate::api::handlers::bookmarks::get_bookmarks(pool, query).await;
+
+    let mut tags: BTreeMap<&str, Vec<&BookmarkResponse>> = BTreeMap::new();
+    for bookmark in &bookmarks {
+        for tag in &bookmark.tag_names {
+            tags.entry(tag).or_default().push(bookmark);
+        }
+    }
+
+    fs::create_dir_all(dir.join("tags"))?;
+
+    let tag_list: Vec<String> = tags
+        .iter()
+        .map(|(tag, tagged)| {
+            format!(
+                r#"<a href="tags/{}">#{}</a> ({})"#,
+                tag_file(tag),
+                escape_html(tag),
+                tagged.len()
+            )
+        })
+        .collect();
+    let all: Vec<&BookmarkResponse> = bookmarks.iter().collect();
+    let index = format!(
+        "<p>{}</p>\n{}",
+        tag_list.join("\n"),
+        bookmark_list(&all, "tags/")
+    );
+    fs::write(dir.join("index.html"), page("Links", &index, ""))?;
+
+    for (tag, tagged) in &tags {
+        let body = format!(
+            "<p><a href=\"../index.html\">All links</a></p>\n{}",
+            bookmark_list(tagged, "")
+        );
+        fs::write(
+            dir.join("tags").join(tag_file(tag)),
+            page(&format!("#{tag}"), &body, "../"),
+        )?;
+    }
+
+    fs::write(dir.join("feed.xml"), rss(&bookmarks, base_url))?;
+
+    println!(
+        "Published {} bookmarks and {} tags to {}",
+        bookmarks.len(),
+        tags.len(),
+        dir.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::handlers::bookmarks::{add_bookmark, BookmarkRequest};
+    use crate::setup_db;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let pool = setup_db(true).await;
+        for (url, title, tags, shared) in [
+            (
+                "https://a.example/?x=1&y=2",
+                "<b>A</b> & co",
+                vec!["<script>", "rust"],
+                true,
+            ),
+            (
+                "https://b.example",
+                "unshared",
+                vec!["rust", "hidden"],
+                false,
+            ),
+        ] {
+            let bookmark = BookmarkRequest {
+                url: url.to_owned(),
+                title: title.to_owned(),
+                description: Some("described".to_owned()),
+                private_notes: Some("my private notes".to_owned()),
+                shared: Some(shared),
+                tag_names: Some(tags.into_iter().map(str::to_owned).collect()),
+                ..Default::default()
+            };
+            add_bookmark(&pool, bookmark).await.unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("pinrs-test-publish-{}", std::process::id()));
+        publish(&pool, &dir, "https://links.example/")
+            .await
+            .unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        let index = read("index.html");
+        let rust = read("tags/rust.html");
+        let script = read(&format!("tags/{}", tag_file("<script>")));
+        let feed = read("feed.xml");
+        let hidden = dir.join("tags").join(tag_file("hidden")).exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // only the shared bookmark, without the private notes
+        assert!(!hidden);
+        for output in [&index, &rust, &script, &feed] {
+            assert!(output.contains("a.example"));
+            assert!(!output.contains("b.example"));
+            assert!(!output.contains("my private notes"));
+            assert!(!output.contains("<script>"));
+            assert!(!output.contains("<b>"));
+        }
+
+        assert!(index.contains("&lt;b&gt;A&lt;/b&gt; &amp; co"));
+        assert!(index.contains("#&lt;script&gt;"));
+        assert!(rust.contains(r#"href="https://a.example/?x=1&amp;y=2""#));
+        assert!(feed.contains("<title>&lt;b&gt;A&lt;/b&gt; &amp; co</title>"));
+        assert!(feed.contains("<category>&lt;script&gt;</category>"));
+        assert!(feed.contains("<link>https://a.example/?x=1&amp;y=2</link>"));
+    }
+}
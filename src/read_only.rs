@@ -0,0 +1,40 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Read-only mode, `PINRS_READ_ONLY=true`, for a mirror serving a replica of the database, e.g.
+//! one kept up to date by Litestream. The database is opened read-only and the requests that
+//! would change anything are answered with 405 Method Not Allowed.
+
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::header;
+
+use crate::api::handlers::pinboard;
+
+/// Whether the request would change anything: all but GET, HEAD and OPTIONS, and the changes the
+/// Pinboard API makes with GET
+pub(crate) fn is_write(method: &Method, path: &str) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        || pinboard::WRITE_ROUTES.contains(&path)
+}
+
+// Logging in and out only changes the sessions kept in memory, see auth.rs
+const SESSION_ROUTES: [&str; 2] = ["/auth/login", "/auth/logout"];
+
+pub(crate) async fn reject_writes(req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    if !is_write(req.method(), path) || SESSION_ROUTES.contains(&path) {
+        return next.run(req).await;
+    }
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        [(header::ALLOW, "GET, HEAD, OPTIONS")],
+        "Read-only mirror",
+    )
+        .into_response()
+}
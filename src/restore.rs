@@ -0,0 +1,373 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Restores a backup, either an `SQLite` database as from `/api/admin/backup.sqlite` or a linkding
+//! JSON export as from `--export-linkding`. The backup is first loaded into a temporary database
+//! with the current schema, which is then copied into the real one in a single transaction.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Connection, SqlitePool};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+use crate::api::handlers::bookmarks::fill_url_keys;
+use crate::create_schema;
+use crate::import::{import_entries, ImportFormat};
+use crate::notes;
+use crate::output::Summary;
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+// the columns of posts copied from the backup, all but the id
+const POST_COLUMNS: &str = "url, title, description, notes, private_notes, unread, shared, is_archived, original_url, source, referrer, uuid, date_added, date_modified";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RestoreMode {
+    // adds the bookmarks with an url or uuid not already saved, and the tags of all bookmarks
+    Merge,
+    // removes all bookmarks and tags first
+    Replace,
+}
+
+/// Outcome of a restore
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RestoreReport {
+    // bookmarks in the backup, and how many of them were added
+    pub(crate) in_backup: i64,
+    pub(crate) restored: i64,
+    // counts after the restore
+    pub(crate) bookmarks: i64,
+    pub(crate) tags: i64,
+}
+
+impl Summary for RestoreReport {
+    fn plain(&self) -> String {
+        format!(
+            "Restored {} of {} bookmarks, now {} bookmarks and {} tags",
+            self.restored, self.in_backup, self.bookmarks, self.tags
+        )
+    }
+
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("in_backup", self.in_backup.to_string()),
+            ("restored", self.restored.to_string()),
+            ("bookmarks", self.bookmarks.to_string()),
+            ("tags", self.tags.to_string()),
+        ]
+    }
+}
+
+// Deleted when dropped, also when the restore fails
+struct TempDb(PathBuf);
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.0) {
+            error!("Failed to remove {}: {}", self.0.display(), err);
+        }
+    }
+}
+
+async fn open(path: &Path) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true);
+    Ok(SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?)
+}
+
+// Loads the backup into a temporary database with the current schema
+async fn load(backup: &[u8], path: &Path) -> Result<()> {
+    if backup.starts_with(SQLITE_HEADER) {
+        fs::write(path, backup)?;
+        let pool = open(path).await?;
+
+        let ok: String = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_one(&pool)
+            .await?;
+        let tables: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('posts', 'tags', 'post_tag')",
+        )
+        .fetch_one(&pool)
+        .await?;
+        if ok != "ok" || tables != 3 {
+            bail!("Not a pinrs database");
+        }
+
+        // adds the columns missing in backups from older versions
+        create_schema(&pool).await;
+        pool.close().await;
+    } else {
+        let Ok(input) = std::str::from_utf8(backup) else {
+            bail!("Neither an SQLite database nor JSON");
+        };
+        let pool = open(path).await?;
+        create_schema(&pool).await;
+        // a restore keeps the tags as they were
+        let report = import_entries(input, ImportFormat::LinkDing, &pool, true).await;
+        pool.close().await;
+        if let Some(parse_error) = report.parse_errors.first() {
+            bail!(
+                "Invalid JSON at line {}, column {}: {}",
+                parse_error.line,
+                parse_error.column,
+                parse_error.error
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Restores the backup into the database, validating it first. Errors from the database are
+/// returned as `sqlx::Error`, any other error means the backup isn't valid.
+pub(crate) async fn restore(
+    pool: &SqlitePool,
+    backup: &[u8],
+    mode: RestoreMode,
+) -> Result<RestoreReport> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let temp = TempDb(std::env::temp_dir().join(format!(
+        "pinrs-restore-{}-{}.sqlite",
+        std::process::id(),
+        nanos
+    )));
+    load(backup, &temp.0).await?;
+
+    let mut conn = pool.acquire().await?;
+    // a URI to not inherit the mode of the main database, e.g. memory in the tests
+    sqlx::query("ATTACH DATABASE $1 AS backup")
+        .bind(format!("file:{}?mode=ro", temp.0.to_string_lossy()))
+        .execute(&mut *conn)
+        .await?;
+
+    let result = copy(&mut conn, mode).await;
+
+    sqlx::query("DETACH DATABASE backup")
+        .execute(&mut *conn)
+        .await?;
+    Ok(result?)
+}
+
+async fn copy(
+    conn: &mut sqlx::SqliteConnection,
+    mode: RestoreMode,
+) -> Result<RestoreReport, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    if mode == RestoreMode::Replace {
+        for table in ["post_tag", "posts", "tags", "smart_tags"] {
+            sqlx::query(&format!("DELETE FROM main.{table}"))
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    let in_backup: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM backup.posts")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let restored = sqlx::query(&format!(
+        r"
+            INSERT INTO main.posts ({POST_COLUMNS})
+                SELECT {POST_COLUMNS} FROM backup.posts
+                WHERE url NOT IN (SELECT url FROM main.posts)
+                    AND uuid NOT IN (SELECT uuid FROM main.posts)
+        "
+    ))
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    fill_url_keys(&mut tx).await?;
+
+    sqlx::query(
+        r"
+            INSERT OR IGNORE INTO main.tags (name, date_added)
+                SELECT name, date_added FROM backup.tags
+        ",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // linked by url and name, the ids differ between the databases
+    sqlx::query(
+        r"
+            INSERT OR IGNORE INTO main.post_tag (post_id, tag_id)
+                SELECT main_posts.id, main_tags.id
+                FROM backup.post_tag
+                JOIN backup.posts ON backup.posts.id = backup.post_tag.post_id
+                JOIN backup.tags ON backup.tags.id = backup.post_tag.tag_id
+                JOIN main.posts AS main_posts ON main_posts.url = backup.posts.url
+                JOIN main.tags AS main_tags ON main_tags.name = backup.tags.name
+        ",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // by uuid, the bookmarks already saved keep theirs
+    sqlx::query(
+        r"
+            INSERT OR IGNORE INTO main.large_notes (uuid, notes, size)
+                SELECT uuid, notes, size FROM backup.large_notes
+                WHERE uuid IN (SELECT uuid FROM main.posts)
+        ",
+    )
+    .execute(&mut *tx)
+    .await?;
+    notes::index_missing(&mut tx).await?;
+
+    sqlx::query(
+        r"
+            INSERT OR IGNORE INTO main.smart_tags (name, query, date_added)
+                SELECT name, query, date_added FROM backup.smart_tags
+        ",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let bookmarks: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM main.posts")
+        .fetch_one(&mut *tx)
+        .await?;
+    let tags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM main.tags")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(RestoreReport {
+        in_backup,
+        restored: i64::try_from(restored).unwrap_or_default(),
+        bookmarks,
+        tags,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup_db;
+
+    async fn add(pool: &SqlitePool, url: &str, tag: &str) {
+        crate::api::handlers::bookmarks::add_bookmark(
+            pool,
+            crate::api::handlers::bookmarks::BookmarkRequest {
+                url: url.to_owned(),
+                tag_names: Some(vec![tag.to_owned()]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_sqlite() {
+        let path =
+            std::env::temp_dir().join(format!("pinrs-test-restore-{}.sqlite", std::process::id()));
+        let backup = open(&path).await.unwrap();
+        create_schema(&backup).await;
+        add(&backup, "https://example.com/a", "a").await;
+        add(&backup, "https://example.com/b", "b").await;
+        backup.close().await;
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let pool = setup_db(true).await;
+        add(&pool, "https://example.com/a", "c").await;
+        add(&pool, "https://example.com/c", "c").await;
+
+        let report = restore(&pool, &bytes, RestoreMode::Merge).await.unwrap();
+        assert_eq!(
+            (
+                report.in_backup,
+                report.restored,
+                report.bookmarks,
+                report.tags
+            ),
+            (2, 1, 3, 3)
+        );
+        // the existing bookmark gets the tags from the backup too
+        let tags: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM post_tag JOIN posts ON posts.id = post_id WHERE url = 'https://example.com/a'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(tags, 2);
+
+        let report = restore(&pool, &bytes, RestoreMode::Replace).await.unwrap();
+        assert_eq!(
+            (
+                report.in_backup,
+                report.restored,
+                report.bookmarks,
+                report.tags
+            ),
+            (2, 2, 2, 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_edited_url() {
+        let path = std::env::temp_dir().join(format!(
+            "pinrs-test-restore-edited-{}.sqlite",
+            std::process::id()
+        ));
+        let backup = open(&path).await.unwrap();
+        create_schema(&backup).await;
+        add(&backup, "https://example.com/a", "a").await;
+        backup.close().await;
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let pool = setup_db(true).await;
+        restore(&pool, &bytes, RestoreMode::Replace).await.unwrap();
+        sqlx::query("UPDATE posts SET url = 'https://example.com/b'")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // the same bookmark by uuid, the one saved is kept
+        let report = restore(&pool, &bytes, RestoreMode::Merge).await.unwrap();
+        assert_eq!((report.restored, report.bookmarks), (0, 1));
+        let url: String = sqlx::query_scalar("SELECT url FROM posts")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(url, "https://example.com/b");
+    }
+
+    #[tokio::test]
+    async fn test_restore_json() {
+        let pool = setup_db(true).await;
+        add(&pool, "https://example.com/a", "a").await;
+
+        let json = r#"[{"url": "https://example.com/b", "title": "b", "description": "", "notes": "", "unread": false, "tag_names": ["b"], "date_added": "2024-01-01T00:00:00Z", "date_modified": "2024-01-01T00:00:00Z"}]"#;
+        let report = restore(&pool, json.as_bytes(), RestoreMode::Merge)
+            .await
+            .unwrap();
+        assert_eq!((report.restored, report.bookmarks, report.tags), (1, 2, 2));
+
+        assert!(restore(&pool, b"[{", RestoreMode::Replace).await.is_err());
+        assert!(
+            restore(&pool, b"SQLite format 3\0garbage", RestoreMode::Replace)
+                .await
+                .is_err()
+        );
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM posts")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+}
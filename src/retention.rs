@@ -0,0 +1,119 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::handlers::bookmarks::BookmarkQuery;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RetentionAction {
+    Delete,
+    Archive,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RetentionRule {
+    pub(crate) tag: String,
+    pub(crate) action: RetentionAction,
+    pub(crate) days: u32,
+}
+
+impl RetentionRule {
+    /// The bookmarks the rule applies to, those with the tag added more than the days ago. The
+    /// archived bookmarks are only deleted, not archived again.
+    pub(crate) fn query(&self) -> BookmarkQuery {
+        BookmarkQuery {
+            tag: vec![self.tag.clone()],
+            added_before: Some(format!("{}d", self.days)),
+            archived: (self.action == RetentionAction::Archive).then_some(false),
+            limit: Some(0),
+            ..Default::default()
+        }
+    }
+}
+
+/// Rules deleting or archiving the bookmarks with a tag some days after they were added. Read
+/// from a file with one rule per line: the tag, `delete` or `archive`, and the days, separated by
+/// whitespace. Empty lines and lines starting with `#` are ignored.
+///
+/// ```text
+/// temp delete 30d
+/// news archive 7d
+/// ```
+///
+/// There are no background tasks, the rules are applied with `POST /api/admin/retention`, e.g.
+/// from a systemd timer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RetentionRules(pub(crate) Vec<RetentionRule>);
+
+impl RetentionRules {
+    pub(crate) fn parse(rules: &str) -> Result<Self, String> {
+        rules
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                let [tag, action, days] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                    return Err(format!("Expected tag, action and days on line {number}"));
+                };
+                let action = match action {
+                    "delete" => RetentionAction::Delete,
+                    "archive" => RetentionAction::Archive,
+                    _ => return Err(format!("Unknown action on line {number}: {action}")),
+                };
+                let days = days
+                    .strip_suffix('d')
+                    .unwrap_or(days)
+                    .parse()
+                    .map_err(|_| format!("Invalid days on line {number}: {days}"))?;
+                Ok(RetentionRule {
+                    tag: tag.to_owned(),
+                    action,
+                    days,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(RetentionRules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retention_rules() {
+        let rules = RetentionRules::parse(
+            r"
+                # ephemeral saves
+                temp delete 30d
+
+                news archive 7
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.0,
+            vec![
+                RetentionRule {
+                    tag: "temp".to_owned(),
+                    action: RetentionAction::Delete,
+                    days: 30,
+                },
+                RetentionRule {
+                    tag: "news".to_owned(),
+                    action: RetentionAction::Archive,
+                    days: 7,
+                },
+            ]
+        );
+
+        assert!(RetentionRules::parse("temp delete").is_err());
+        assert!(RetentionRules::parse("temp expire 30d").is_err());
+        assert!(RetentionRules::parse("temp delete 30w").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use regex::Regex;
+use std::borrow::Cow;
+
+/// Rules rewriting the urls of bookmarks when they are saved, e.g. to use an alternative front
+/// end. Read from a file with one rule per line: a regex and its replacement separated by
+/// whitespace, where the replacement can refer to groups with `$1`. Empty lines and lines
+/// starting with `#` are ignored. The first matching rule is used.
+///
+/// ```text
+/// ^https?://(www\.)?youtube\.com/ https://invidious.example/
+/// ^(https?://)amp\.(.*)$ $1$2
+/// ```
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RewriteRules(Vec<(Regex, String)>);
+
+impl RewriteRules {
+    pub(crate) fn parse(rules: &str) -> Result<Self, String> {
+        rules
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(number, line)| {
+                let Some((pattern, replacement)) = line.split_once(char::is_whitespace) else {
+                    return Err(format!("Missing replacement on line {number}"));
+                };
+                let regex = Regex::new(pattern)
+                    .map_err(|err| format!("Invalid regex on line {number}: {err}"))?;
+                Ok((regex, replacement.trim().to_owned()))
+            })
+            .collect::<Result<_, _>>()
+            .map(RewriteRules)
+    }
+
+    /// The rewritten url, or `None` if no rule changes it
+    pub(crate) fn apply(&self, url: &str) -> Option<String> {
+        self.0.iter().find_map(|(regex, replacement)| {
+            match regex.replace(url, replacement.as_str()) {
+                Cow::Owned(rewritten) if rewritten != url => Some(rewritten),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let rules = RewriteRules::parse(
+            r"
+                # alternative front ends
+                ^https?://(www\.)?twitter\.com/ https://nitter.example/
+
+                ^(https?://)amp\.(.*)$ $1$2
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.apply("https://twitter.com/user/status/1").as_deref(),
+            Some("https://nitter.example/user/status/1")
+        );
+        assert_eq!(
+            rules.apply("https://amp.example.com/news").as_deref(),
+            Some("https://example.com/news")
+        );
+        assert_eq!(rules.apply("https://example.com/"), None);
+
+        assert!(RewriteRules::parse("^https://example.com/").is_err());
+        assert!(RewriteRules::parse("^https://(example.com/ x").is_err());
+    }
+}
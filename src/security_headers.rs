@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Security headers on all responses. Pinrs serves cleartext HTTP, so HSTS is only sent when
+//! `PINRS_HSTS_MAX_AGE` says TLS is terminated in front of it, e.g. by a reverse proxy.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use hyper::header;
+use std::sync::Arc;
+
+use crate::AppState;
+
+// nothing but the inline svg of /stats is needed by the pages
+const DEFAULT_CSP: &str = "default-src 'none'; img-src 'self' data:; frame-ancestors 'none'; base-uri 'none'; form-action 'none'";
+
+pub(crate) struct SecurityHeaders {
+    content_security_policy: HeaderValue,
+    // Strict-Transport-Security, only set when configured
+    hsts: Option<HeaderValue>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders {
+            content_security_policy: HeaderValue::from_static(DEFAULT_CSP),
+            hsts: None,
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// From `PINRS_CSP`, replacing the default Content-Security-Policy, and `PINRS_HSTS_MAX_AGE`
+    /// in seconds.
+    pub(crate) fn parse(csp: Option<&str>, hsts_max_age: Option<&str>) -> Result<Self, String> {
+        let mut headers = SecurityHeaders::default();
+        if let Some(csp) = csp {
+            headers.content_security_policy = HeaderValue::from_str(csp.trim())
+                .map_err(|_| format!("Invalid PINRS_CSP: {csp}"))?;
+        }
+        if let Some(max_age) = hsts_max_age {
+            let max_age: u64 = max_age
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid PINRS_HSTS_MAX_AGE: {max_age}"))?;
+            headers.hsts = Some(
+                HeaderValue::from_str(&format!("max-age={max_age}"))
+                    .map_err(|err| err.to_string())?,
+            );
+        }
+        Ok(headers)
+    }
+
+    /// Served over HTTPS by a reverse proxy, as told by HSTS being set
+    pub(crate) fn https(&self) -> bool {
+        self.hsts.is_some()
+    }
+}
+
+/// Adds the headers not already set by the handler
+pub(crate) async fn set(State(state): State<Arc<AppState>>, req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    let config = &state.security_headers;
+    let headers = response.headers_mut();
+
+    headers
+        .entry(header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    headers
+        .entry(header::REFERRER_POLICY)
+        .or_insert(HeaderValue::from_static("no-referrer"));
+    headers
+        .entry(header::CONTENT_SECURITY_POLICY)
+        .or_insert(config.content_security_policy.clone());
+    if let Some(hsts) = &config.hsts {
+        headers
+            .entry(header::STRICT_TRANSPORT_SECURITY)
+            .or_insert(hsts.clone());
+    }
+
+    response
+}
@@ -0,0 +1,195 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use axum::extract::{ConnectInfo, Request};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::env;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tower::{Layer, ServiceExt};
+use tower_http::normalize_path::{NormalizePath, NormalizePathLayer};
+use tracing::{debug, error};
+
+pub(crate) struct ServerConfig {
+    pub(crate) http2: bool,
+    pub(crate) max_concurrent_streams: Option<u32>,
+    pub(crate) keep_alive: bool,
+    pub(crate) header_read_timeout: Option<Duration>,
+    pub(crate) http2_keep_alive_interval: Option<Duration>,
+    pub(crate) max_body_size: usize,
+}
+
+pub(crate) fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("Failed to parse {name}: {value}"))
+    })
+}
+
+impl ServerConfig {
+    pub(crate) fn from_env() -> Self {
+        ServerConfig {
+            http2: env_opt("PINRS_HTTP2").unwrap_or(true),
+            max_concurrent_streams: env_opt("PINRS_HTTP2_MAX_CONCURRENT_STREAMS"),
+            keep_alive: env_opt("PINRS_KEEP_ALIVE").unwrap_or(true),
+            header_read_timeout: env_opt("PINRS_HEADER_READ_TIMEOUT").map(Duration::from_secs),
+            http2_keep_alive_interval: env_opt("PINRS_HTTP2_KEEP_ALIVE_INTERVAL")
+                .map(Duration::from_secs),
+            // same as the axum default
+            max_body_size: env_opt("PINRS_MAX_BODY_SIZE").unwrap_or(2 * 1024 * 1024),
+        }
+    }
+
+    fn builder(&self) -> Builder<TokioExecutor> {
+        let mut builder = Builder::new(TokioExecutor::new());
+
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .keep_alive(self.keep_alive);
+        // hyper reads the headers for 30 seconds if not set
+        if let Some(timeout) = self.header_read_timeout {
+            builder.http1().header_read_timeout(timeout);
+        }
+
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(self.max_concurrent_streams);
+        // a ping that isn't answered before the next one is due closes the connection
+        if let Some(interval) = self.http2_keep_alive_interval {
+            builder
+                .http2()
+                .keep_alive_interval(interval)
+                .keep_alive_timeout(interval);
+        }
+
+        if self.http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+}
+
+/// Parses a comma separated list of addresses to listen on, e.g. `127.0.0.1,[::1]:8080`.
+/// Addresses without a port use `port`.
+pub(crate) fn parse_bind(addresses: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+    addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse::<SocketAddr>()
+                .or_else(|_| {
+                    address
+                        .parse::<IpAddr>()
+                        .map(|ip| SocketAddr::new(ip, port))
+                })
+                .map_err(|_| format!("Invalid address: {address}"))
+        })
+        .collect()
+}
+
+/// Binds a listener to the address. IPv6 addresses only accept IPv6, so that `0.0.0.0` and `::`
+/// can be listened on at the same time also where IPv6 sockets accept IPv4 by default.
+pub(crate) fn bind(address: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    // as TcpListener::bind, to be able to restart while old connections linger
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Serves the app on the listeners with the HTTP settings from the config, giving the handlers
+/// access to the address of the client through `ConnectInfo`.
+pub(crate) async fn serve(listeners: Vec<TcpListener>, app: Router, config: &ServerConfig) {
+    let builder = config.builder();
+    let app = NormalizePathLayer::trim_trailing_slash().layer(app);
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for listener in listeners {
+        tasks.spawn(accept(listener, app.clone(), builder.clone()));
+    }
+    while tasks.join_next().await.is_some() {}
+}
+
+async fn accept(
+    listener: TcpListener,
+    app: NormalizePath<Router>,
+    builder: Builder<TokioExecutor>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                error!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+
+        let service = app.clone().map_request(move |mut req: Request<_>| {
+            req.extensions_mut().insert(ConnectInfo(addr));
+            req
+        });
+        let service = TowerToHyperService::new(service);
+        let builder = builder.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = builder
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Connection from {} closed: {}", addr, err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind() {
+        assert_eq!(
+            parse_bind("127.0.0.1, [::1]:8080,::", 3000).unwrap(),
+            vec![
+                SocketAddr::from(([127, 0, 0, 1], 3000)),
+                SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 8080)),
+                SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 0], 3000)),
+            ]
+        );
+        assert!(parse_bind("localhost", 3000).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_dual_stack() {
+        let v4 = bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let port = v4.local_addr().unwrap().port();
+        // hosts without IPv6 can't bind it at all
+        if let Ok(v6) = bind(SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], port))) {
+            assert_eq!(v6.local_addr().unwrap().port(), port);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::Router;
+use chrono::{Datelike, Months, Utc};
+use hyper::StatusCode;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::api::format::escape_html;
+use crate::AppState;
+
+const TOP_TAGS: i64 = 10;
+const MONTHS: u32 = 12;
+
+const BAR_WIDTH: u32 = 30;
+const CHART_HEIGHT: u32 = 100;
+
+#[derive(sqlx::FromRow, Debug)]
+struct Counts {
+    total: i64,
+    unread: i64,
+    shared: i64,
+    archived: i64,
+    untagged: i64,
+    tags: i64,
+}
+
+struct Stats {
+    counts: Counts,
+    top_tags: Vec<(String, i64)>,
+    // bookmarks added per month, oldest first
+    months: Vec<(String, i64)>,
+}
+
+pub fn configure(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/stats", get(handle_get_stats))
+        .with_state(state)
+}
+
+async fn get_stats(pool: &SqlitePool) -> Result<Stats, sqlx::Error> {
+    let counts = sqlx::query_as::<_, Counts>(
+        r"
+            SELECT COUNT(*) AS total,
+                COALESCE(SUM(posts.unread = 1), 0) AS unread,
+                COALESCE(SUM(posts.shared = 1), 0) AS shared,
+                COALESCE(SUM(posts.is_archived = 1), 0) AS archived,
+                COALESCE(SUM(NOT EXISTS (SELECT 1 FROM post_tag WHERE post_tag.post_id = posts.id)), 0) AS untagged,
+                (SELECT COUNT(*) FROM tags) AS tags
+            FROM posts
+        ",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let top_tags = sqlx::query(
+        r"
+            SELECT tags.name, COUNT(*) AS count
+                FROM tags
+                JOIN post_tag ON (tags.id = post_tag.tag_id)
+                GROUP BY tags.id
+                ORDER BY count DESC, tags.name
+                LIMIT $1
+        ",
+    )
+    .bind(TOP_TAGS)
+    .map(|row: SqliteRow| (row.get::<String, _>("name"), row.get::<i64, _>("count")))
+    .fetch_all(pool)
+    .await?;
+
+    let added: HashMap<String, i64> = sqlx::query(
+        r"
+            SELECT strftime('%Y-%m', date_added, 'unixepoch') AS month, COUNT(*) AS count
+                FROM posts
+                WHERE date_added >= unixepoch('now', 'start of month', $1)
+                GROUP BY month
+        ",
+    )
+    .bind(format!("-{} months", MONTHS - 1))
+    .map(|row: SqliteRow| (row.get::<String, _>("month"), row.get::<i64, _>("count")))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+
+    // months without any added bookmarks aren't in the result
+    let this_month = Utc::now().date_naive().with_day(1).unwrap_or_default();
+    let months = (0..MONTHS)
+        .rev()
+        .filter_map(|ago| this_month.checked_sub_months(Months::new(ago)))
+        .map(|month| {
+            let month = month.format("%Y-%m").to_string();
+            let count = added.get(&month).copied().unwrap_or_default();
+            (month, count)
+        })
+        .collect();
+
+    Ok(Stats {
+        counts,
+        top_tags,
+        months,
+    })
+}
+
+fn chart(months: &[(String, i64)]) -> String {
+    let max = months
+        .iter()
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or_default();
+    let width = BAR_WIDTH * MONTHS;
+    let mut result = vec![format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{}" role="img" aria-label="Bookmarks added per month">"#,
+        CHART_HEIGHT + 20
+    )];
+
+    for (x, (month, count)) in (0..).step_by(BAR_WIDTH as usize).zip(months) {
+        let height = if max > 0 {
+            count * i64::from(CHART_HEIGHT) / max
+        } else {
+            0
+        };
+        result.push(format!(
+            r#"<rect x="{}" y="{}" width="{}" height="{height}" fill="steelblue"><title>{month}: {count}</title></rect>"#,
+            x + 2,
+            i64::from(CHART_HEIGHT) - height,
+            BAR_WIDTH - 4,
+        ));
+        result.push(format!(
+            r#"<text x="{}" y="{}" font-size="10" text-anchor="middle">{}</text>"#,
+            x + BAR_WIDTH / 2,
+            CHART_HEIGHT + 14,
+            month.get(5..).unwrap_or_default()
+        ));
+    }
+
+    result.push("</svg>".to_owned());
+    result.join("\n")
+}
+
+fn render(stats: &Stats) -> String {
+    let counts = &stats.counts;
+    let top_tags: Vec<String> = stats
+        .top_tags
+        .iter()
+        .map(|(tag, count)| format!("<li>#{} ({count})</li>", escape_html(tag)))
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Stats</title>
+</head>
+<body>
+<h1>Stats</h1>
+<table>
+<tr><td>Bookmarks</td><td>{}</td></tr>
+<tr><td>Unread</td><td>{}</td></tr>
+<tr><td>Shared</td><td>{}</td></tr>
+<tr><td>Archived</td><td>{}</td></tr>
+<tr><td>Untagged</td><td>{}</td></tr>
+<tr><td>Tags</td><td>{}</td></tr>
+</table>
+<h2>Top tags</h2>
+<ol>
+{}
+</ol>
+<h2>Added per month</h2>
+{}
+</body>
+</html>
+"#,
+        counts.total,
+        counts.unread,
+        counts.shared,
+        counts.archived,
+        counts.untagged,
+        counts.tags,
+        top_tags.join("\n"),
+        chart(&stats.months)
+    )
+}
+
+async fn handle_get_stats(State(state): State<Arc<AppState>>) -> Result<Html<String>, StatusCode> {
+    match get_stats(&state.pool).await {
+        Ok(result) => Ok(Html(render(&result))),
+        Err(err) => {
+            error!("Failed to get stats: {}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app, setup_db};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use hyper::header;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "abc";
+
+    #[tokio::test]
+    async fn test_get_stats() {
+        let pool = setup_db(true).await;
+        let app = app(pool, TOKEN.to_owned());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/bookmarks")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                    .body(Body::from(
+                        r#"{"url": "https://example.com", "title": "a", "tag_names": ["<b>"]}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/stats")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/stats")
+                    .header(header::AUTHORIZATION, format!("Token {TOKEN}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert!(body.contains("<tr><td>Bookmarks</td><td>1</td></tr>"));
+        assert!(body.contains("<li>#&lt;b&gt; (1)</li>"));
+        assert!(body.contains("<svg"));
+    }
+}
@@ -0,0 +1,35 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use tracing::error;
+
+// Sends a state to systemd, does nothing when not started by systemd with Type=notify
+fn notify(state: &str) {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        // a leading @ means a socket in the abstract namespace
+        if let Some(name) = path.strip_prefix('@') {
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)
+        } else {
+            socket.send_to(state.as_bytes(), &path)
+        }
+    });
+
+    if let Err(err) = result {
+        error!("Failed to notify systemd of {}: {}", state, err);
+    }
+}
+
+/// Tells systemd that the server is up and accepting connections. There's no watchdog ping, it
+/// would need a task running in the background, which pinrs doesn't have.
+pub(crate) fn ready() {
+    notify("READY=1");
+}
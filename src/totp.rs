@@ -0,0 +1,204 @@
+// SPDX-FileCopyrightText: 2025 Fredrik Lanker <fredrik@lanker.se>
+//
+// SPDX-License-Identifier: AGPL-3.0-or-later
+
+//! Time-based one-time passwords (RFC 6238) protecting the destructive admin routes. When a
+//! secret has been set up with `--totp-setup`, those routes need the current code in the
+//! `X-TOTP-Code` header besides the token.
+
+use anyhow::Result;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+use crate::AppState;
+
+const X_TOTP_CODE: HeaderName = HeaderName::from_static("x-totp-code");
+
+const STEP: u64 = 30;
+const DIGITS: u32 = 6;
+
+// RFC 4226
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes a key of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = usize::from(hash[19] & 0x0f);
+    let code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    code % 10u32.pow(DIGITS)
+}
+
+// The code is accepted one step before and after the current one, for clocks a bit off
+fn is_valid(secret: &[u8], code: &str, now: u64) -> bool {
+    let Ok(code) = code.trim().parse::<u32>() else {
+        return false;
+    };
+    let counter = now / STEP;
+    [counter.saturating_sub(1), counter, counter + 1]
+        .iter()
+        .any(|&counter| hotp(secret, counter) == code)
+}
+
+// RFC 4648 base32 without padding, as used by authenticator apps
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer
+            .iter()
+            .fold(0u64, |bits, byte| (bits << 8) | u64::from(*byte));
+        for i in 0..(chunk.len() * 8).div_ceil(5) {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            encoded.push(char::from(
+                ALPHABET[usize::try_from(index).unwrap_or_default()],
+            ));
+        }
+    }
+    encoded
+}
+
+async fn get_secret(pool: &SqlitePool) -> Result<Option<Vec<u8>>, sqlx::Error> {
+    sqlx::query_scalar::<_, Vec<u8>>("SELECT value FROM settings WHERE name = 'totp_secret'")
+        .fetch_optional(pool)
+        .await
+}
+
+/// Generates and stores a new secret, replacing any earlier one. Returns the `otpauth` url to
+/// add to an authenticator app.
+pub(crate) async fn setup(pool: &SqlitePool) -> Result<String> {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+
+    sqlx::query("INSERT OR REPLACE INTO settings (name, value) VALUES ('totp_secret', $1)")
+        .bind(&secret[..])
+        .execute(pool)
+        .await?;
+
+    Ok(format!(
+        "otpauth://totp/pinrs?secret={}&issuer=pinrs",
+        base32(&secret)
+    ))
+}
+
+/// Removes the secret, the admin routes then only need the token.
+pub(crate) async fn disable(pool: &SqlitePool) -> Result<()> {
+    sqlx::query("DELETE FROM settings WHERE name = 'totp_secret'")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub(crate) async fn require(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let secret = match get_secret(&state.pool).await {
+        Ok(Some(secret)) => secret,
+        Ok(None) => return Ok(next.run(req).await),
+        Err(err) => {
+            error!("Failed to get the TOTP secret: {}", err);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let valid = req
+        .headers()
+        .get(X_TOTP_CODE)
+        .and_then(|code| code.to_str().ok())
+        .is_some_and(|code| is_valid(&secret, code, now));
+
+    if valid {
+        Ok(next.run(req).await)
+    } else {
+        info!("Missing or wrong TOTP code for {}", req.uri());
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app, setup_db};
+    use axum::body::Body;
+    use hyper::header;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_totp() {
+        // RFC 6238 test vectors, the last 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(hotp(secret, 59 / STEP), 287_082);
+        assert_eq!(hotp(secret, 1_111_111_109 / STEP), 81_804);
+        assert_eq!(hotp(secret, 2_000_000_000 / STEP), 279_037);
+
+        assert!(is_valid(secret, "287082", 59));
+        assert!(is_valid(secret, "287082", 89));
+        assert!(!is_valid(secret, "287082", 150));
+        assert!(!is_valid(secret, "", 59));
+
+        assert_eq!(
+            base32(b"12345678901234567890"),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+        assert_eq!(base32(b"f"), "MY");
+    }
+
+    #[tokio::test]
+    async fn test_require_code() {
+        let pool = setup_db(true).await;
+        let app = app(pool.clone(), "abc".to_owned());
+
+        let rebuild = |app: axum::Router, code: Option<String>| async move {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/api/admin/rebuild")
+                .header(header::AUTHORIZATION, "Token abc");
+            if let Some(code) = code {
+                request = request.header(X_TOTP_CODE, code);
+            }
+            app.oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap()
+                .status()
+        };
+
+        assert_eq!(rebuild(app.clone(), None).await, StatusCode::OK);
+
+        setup(&pool).await.unwrap();
+        let secret = get_secret(&pool).await.unwrap().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let code = format!("{:06}", hotp(&secret, now / STEP));
+
+        assert_eq!(rebuild(app.clone(), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(rebuild(app.clone(), Some(code)).await, StatusCode::OK);
+
+        disable(&pool).await.unwrap();
+        assert_eq!(rebuild(app, None).await, StatusCode::OK);
+    }
+}